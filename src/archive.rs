@@ -0,0 +1,275 @@
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+
+use anyhow::format_err;
+use anyhow::Result;
+use git2::IndexAddOption;
+use git2::Repository;
+use git2::RepositoryInitOptions;
+use git2::Signature;
+use git2::Time;
+use sha2::Digest;
+use sha2::Sha256;
+
+use crate::deps::Dependency;
+
+/// Name of the git config entry where the checksum of the archive is kept.
+const CHECKSUM_KEY: &str = "vendify.sha256";
+
+/// Returns whether the url points to a release archive rather than to a git
+/// repository: a `.tar.gz`, `.tgz`, `.tar` or `.zip` file, or any url with
+/// the `tar+` prefix, such as `tar+https://`.
+pub fn is_archive(url: &str) -> bool {
+    let url = url.trim();
+    url.starts_with("tar+")
+        || [".tar.gz", ".tgz", ".tar", ".zip"]
+            .iter()
+            .any(|extension| url.ends_with(extension))
+}
+
+/// Makes sure the release archive of the dependency is extracted into a bare
+/// git repository at `path`, committed on a branch named after the refname,
+/// so it is vendored like any other repository. When the archive has a
+/// single top-level folder, its contents are committed instead.
+///
+/// The archive is only downloaded when it is not extracted yet, the commit
+/// has a fixed author and date, so the same archive always results in the
+/// same commit. Returns the sha256 checksum of the archive.
+///
+/// # Errors
+///
+/// Will return `Err` when the archive cannot be downloaded or extracted, when
+/// it is not extracted yet and `offline` is set, or when its checksum does
+/// not match the one that the dependency expects.
+pub fn ensure(dep: &Dependency, path: &Path, offline: bool) -> Result<String> {
+    let checksum = match Repository::open_bare(path) {
+        Ok(repository) => repository.config()?.get_string(CHECKSUM_KEY)?,
+        Err(_) if offline => {
+            return Err(format_err!("the archive is not available in the cache"));
+        }
+        Err(_) => return extract(dep, path),
+    };
+    verify(dep, &checksum)?;
+    Ok(checksum)
+}
+
+fn verify(dep: &Dependency, checksum: &str) -> Result<()> {
+    match &dep.sha256 {
+        Some(expected) if !expected.eq_ignore_ascii_case(checksum) => Err(format_err!(
+            "the archive has sha256 {checksum}, but {expected} is expected"
+        )),
+        _ => Ok(()),
+    }
+}
+
+fn extract(dep: &Dependency, path: &Path) -> Result<String> {
+    let staging = path.with_extension("part");
+    if staging.exists() {
+        fs::remove_dir_all(&staging)?;
+    }
+    let tree = staging.join("tree");
+    fs::create_dir_all(&tree)?;
+
+    let location = location(&dep.url);
+    let archive = staging.join(file_name(location));
+    download(location, &archive)
+        .map_err(|err| format_err!("cannot download the archive: {err}"))?;
+    let checksum = format!("{:x}", Sha256::digest(fs::read(&archive)?));
+    if let Err(err) = verify(dep, &checksum) {
+        fs::remove_dir_all(&staging)?;
+        return Err(err);
+    }
+    unpack(&archive, &tree).map_err(|err| format_err!("cannot extract the archive: {err}"))?;
+
+    if path.exists() {
+        fs::remove_dir_all(path)?;
+    }
+    commit(dep, &top_level(&tree)?, path, &checksum)
+        .map_err(|err| format_err!("cannot commit the archive: {err}"))?;
+    fs::remove_dir_all(&staging)?;
+    Ok(checksum)
+}
+
+/// Returns where the archive is read from, the url without the `tar+` prefix.
+fn location(url: &str) -> &str {
+    let url = url.trim();
+    url.strip_prefix("tar+").unwrap_or(url)
+}
+
+fn file_name(location: &str) -> String {
+    let name = location
+        .trim_end_matches('/')
+        .rsplit('/')
+        .next()
+        .unwrap_or_default();
+    if is_archive(name) {
+        name.to_string()
+    } else {
+        "archive.tar.gz".to_string()
+    }
+}
+
+fn download(location: &str, to: &Path) -> Result<()> {
+    if location.starts_with("http://") || location.starts_with("https://") {
+        let mut command = Command::new("curl");
+        command.args([
+            "--fail",
+            "--silent",
+            "--show-error",
+            "--location",
+            "--output",
+        ]);
+        command.arg(to).arg(location);
+        return run("curl", command);
+    }
+    let from = location.strip_prefix("file://").unwrap_or(location);
+    fs::copy(from, to).map_err(|err| format_err!("{from}: {err}"))?;
+    Ok(())
+}
+
+fn unpack(archive: &Path, to: &Path) -> Result<()> {
+    if archive
+        .extension()
+        .is_some_and(|extension| extension == "zip")
+    {
+        let mut command = Command::new("unzip");
+        command.arg("-q").arg(archive).arg("-d").arg(to);
+        run("unzip", command)
+    } else {
+        let mut command = Command::new("tar");
+        command.arg("-xf").arg(archive).arg("-C").arg(to);
+        run("tar", command)
+    }
+}
+
+/// Returns the single top-level folder of the extracted tree, release
+/// archives usually wrap their files in one, or the tree itself otherwise.
+fn top_level(tree: &Path) -> Result<PathBuf> {
+    let entries = fs::read_dir(tree)?.collect::<Result<Vec<_>, _>>()?;
+    match entries.as_slice() {
+        [entry] if entry.file_type()?.is_dir() => Ok(entry.path()),
+        _ => Ok(tree.to_path_buf()),
+    }
+}
+
+fn commit(dep: &Dependency, tree: &Path, path: &Path, checksum: &str) -> Result<()> {
+    let branch = if dep.refname.is_empty() {
+        "main"
+    } else {
+        &dep.refname
+    };
+    let repository = Repository::init_opts(
+        path,
+        RepositoryInitOptions::new().bare(true).initial_head(branch),
+    )?;
+    repository.set_workdir(tree, false)?;
+    let mut index = repository.index()?;
+    index.add_all(["*"], IndexAddOption::FORCE, None)?;
+    let tree_id = index.write_tree()?;
+    let signature = Signature::new("vendify", "vendify@localhost", &Time::new(0, 0))?;
+    repository.commit(
+        Some("HEAD"),
+        &signature,
+        &signature,
+        "Extract the release archive",
+        &repository.find_tree(tree_id)?,
+        &[],
+    )?;
+    repository.config()?.set_str(CHECKSUM_KEY, checksum)?;
+    Ok(())
+}
+
+fn run(tool: &str, mut command: Command) -> Result<()> {
+    let output = command
+        .output()
+        .map_err(|err| format_err!("cannot run {tool}: {err}"))?;
+    if !output.status.success() {
+        return Err(format_err!(
+            "{}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::test_utils::tempdir;
+    use crate::test_utils::write_to;
+
+    /// Packs the files into a tarball under a top-level folder, returns its
+    /// path.
+    fn tarball(root: &Path, files: &[(&str, &str)]) -> PathBuf {
+        for (path, data) in files {
+            let dst = root.join("project-1.0.0").join(path);
+            fs::create_dir_all(dst.parent().unwrap()).unwrap();
+            write_to(&dst, data);
+        }
+        let archive = root.join("project-1.0.0.tar.gz");
+        let mut command = Command::new("tar");
+        command
+            .arg("-czf")
+            .arg(&archive)
+            .arg("-C")
+            .arg(root)
+            .arg("project-1.0.0");
+        run("tar", command).unwrap();
+        archive
+    }
+
+    #[test]
+    fn test_is_archive() {
+        assert!(is_archive("https://example.com/releases/v1.0.0.tar.gz"));
+        assert!(is_archive("https://example.com/releases/v1.0.0.zip"));
+        assert!(is_archive("tar+https://example.com/releases/latest"));
+        assert!(is_archive("/tmp/release.tgz"));
+        assert!(!is_archive("https://github.com/org/repo.git"));
+        assert!(!is_archive("/tmp/repo.bundle"));
+    }
+
+    #[test]
+    fn test_ensure_extracts_the_archive() -> Result<()> {
+        let root = tempdir();
+        let archive = tarball(root.path(), &[("proto/a.proto", "a"), ("README", "b")]);
+        let checksum = format!("{:x}", Sha256::digest(fs::read(&archive)?));
+        let mut dep = Dependency::new(archive.to_string_lossy(), "1.0.0");
+        dep.sha256 = Some(checksum.clone());
+
+        let first = root.path().join("first");
+        assert_eq!(checksum, ensure(&dep, &first, false)?);
+        let repository = Repository::open_bare(&first)?;
+        let commit = repository.revparse_single("1.0.0")?.peel_to_commit()?;
+        let tree = commit.tree()?;
+        assert!(tree.get_path(Path::new("proto/a.proto")).is_ok());
+        assert!(tree.get_path(Path::new("README")).is_ok());
+
+        // Extracted again, the archive results in the same commit.
+        let second = root.path().join("second");
+        assert_eq!(checksum, ensure(&dep, &second, false)?);
+        let other = Repository::open_bare(&second)?;
+        assert_eq!(commit.id(), other.revparse_single("1.0.0")?.id());
+
+        // Once extracted, the archive is not needed anymore.
+        fs::remove_file(&archive)?;
+        assert_eq!(checksum, ensure(&dep, &first, true)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_ensure_verifies_the_checksum() {
+        let root = tempdir();
+        let archive = tarball(root.path(), &[("a.proto", "a")]);
+        let mut dep = Dependency::new(archive.to_string_lossy(), "1.0.0");
+        dep.sha256 = Some("0123".to_string());
+
+        let err = ensure(&dep, &root.path().join("repo"), false).unwrap_err();
+        assert!(err.to_string().ends_with("but 0123 is expected"), "{err}");
+
+        let err = ensure(&dep, &root.path().join("missing"), true).unwrap_err();
+        assert_eq!("the archive is not available in the cache", err.to_string());
+    }
+}
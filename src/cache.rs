@@ -1,5 +1,7 @@
+use std::fs;
 use std::fs::create_dir_all;
 use std::fs::remove_dir_all;
+use std::path::Path;
 use std::path::PathBuf;
 use std::time::Duration;
 
@@ -8,29 +10,50 @@ use anyhow::Result;
 use sha2::Digest;
 
 use crate::deps::Dependency;
+use crate::error::prefixed;
+use crate::interpolate::interpolate;
 use crate::lock::Lock;
 use crate::preset::Preset;
+use crate::repository::CloneOptions;
 use crate::repository::Repository;
+use crate::url;
 
+#[derive(Clone)]
 pub struct Cache {
     root: PathBuf,
     lock_file: PathBuf,
     locks_dir: PathBuf,
     repos_dir: PathBuf,
+    worktrees_dir: PathBuf,
+    archives_dir: PathBuf,
+    objects_dir: PathBuf,
 }
 
 impl Cache {
     /// Creates a new [`Cache`] based on a [`Preset`].
     pub fn new(preset: &Preset) -> Self {
-        let root = PathBuf::from(preset.cache());
+        Self::from_root(preset.cache())
+    }
+
+    /// Creates a new [`Cache`] rooted at the given directory.
+    pub fn from_root<P: AsRef<Path>>(root: P) -> Self {
+        let root = root.as_ref().to_path_buf();
         Self {
             lock_file: root.join(".LOCK"),
             locks_dir: root.join("locks"),
             repos_dir: root.join("repos"),
+            worktrees_dir: root.join("worktrees"),
+            archives_dir: root.join("archives"),
+            objects_dir: root.join("objects"),
             root,
         }
     }
 
+    /// Returns the root directory of this [`Cache`].
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
     /// Initializes the cache folder, making sure it exists and contains the
     /// expected directory structure.
     ///
@@ -47,10 +70,10 @@ impl Cache {
         Ok(())
     }
 
-    /// Returns the lock of this [`Cache`]. It will log a warning message it
-    /// takes longer than expected to acquire, as this could indicate the
-    /// user is trying to run multiple vendoring processes at the same time,
-    /// which is not supported, as the cache is shared.
+    /// Returns the exclusive lock of this [`Cache`], held by the operations
+    /// that span the whole cache, such as pruning it. It will log a warning
+    /// message it takes longer than expected to acquire, as this could
+    /// indicate the user is running a different instance in parallel.
     ///
     /// # Errors
     ///
@@ -64,13 +87,30 @@ impl Cache {
         Ok(lock)
     }
 
+    /// Returns the shared lock of this [`Cache`], held by the operations that
+    /// use some of the cached repositories, such as installing. Processes
+    /// sharing the cache run in parallel, each cached repository is used by
+    /// one of them at a time, see [`Cache::lock_repository`].
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the lock cannot be acquired.
+    pub fn lock_shared(&self) -> Result<Lock> {
+        let mut lock = Lock::new(&self.lock_file).with_warn(
+            "Cannot acquire cache lock, is a different instance pruning the cache?",
+            Duration::from_secs(1),
+        );
+        lock.acquire_shared()?;
+        Ok(lock)
+    }
+
     /// Returns the lock for a [`Dependency`] cache folder
     ///
     /// # Errors
     ///
     /// This function will return an error if the lock annot be acquired.
     pub fn lock_repository(&self, dep: &Dependency) -> Result<Lock> {
-        let path = self.get_repository_lock_path(dep);
+        let path = self.get_repository_lock_path(dep)?;
         let mut lock = Lock::new(path);
         lock.acquire()?;
         Ok(lock)
@@ -89,29 +129,194 @@ impl Cache {
         Ok(())
     }
 
-    /// Returns a [`Repository`] from the cache directory.
+    /// Removes the cached repositories that do not belong to any of the
+    /// provided dependencies, returns the paths that have been removed.
+    ///
+    /// Only the entries that have been created by the cache are considered,
+    /// anything else found in the cache directory is left untouched.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a cached repository cannot be
+    /// removed.
+    pub fn prune(&self, deps: &[Dependency]) -> Result<Vec<PathBuf>> {
+        if !self.repos_dir.exists() {
+            return Ok(vec![]);
+        }
+
+        let active = deps.iter().map(url_md5).collect::<Result<Vec<_>>>()?;
+        let mut pruned = vec![];
+        for entry in fs::read_dir(&self.repos_dir)? {
+            let path = entry?.path();
+            let is_orphan = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| is_url_md5(name) && !active.iter().any(|a| a == name));
+            if !is_orphan || !path.is_dir() {
+                continue;
+            }
+
+            remove_dir_all(&path).map_err(|err| {
+                format_err!("cannot remove cached repository {}: {err}", path.display())
+            })?;
+            if let Some(name) = path.file_name() {
+                let _ = fs::remove_file(self.locks_dir.join(name));
+            }
+            pruned.push(path);
+        }
+        pruned.sort();
+        Ok(pruned)
+    }
+
+    /// Compacts the cached repositories, returns the paths that have been
+    /// compacted. Repositories that are locked, as they are in use, are
+    /// skipped.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a cached repository cannot be
+    /// compacted.
+    pub fn gc(&self) -> Result<Vec<PathBuf>> {
+        if !self.repos_dir.exists() {
+            return Ok(vec![]);
+        }
+
+        let mut collected = vec![];
+        for entry in fs::read_dir(&self.repos_dir)? {
+            let path = entry?.path();
+            let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+            if !is_url_md5(name) || !path.is_dir() {
+                continue;
+            }
+
+            let mut lock = Lock::new(self.locks_dir.join(name));
+            if !lock.try_acquire()? {
+                log::info!("skipping {}, it is in use", path.display());
+                continue;
+            }
+            Repository::new(&path)
+                .gc()
+                .map_err(|err| format_err!("{}: {err}", path.display()))?;
+            collected.push(path);
+        }
+        collected.sort();
+        Ok(collected)
+    }
+
+    /// Removes the cached repository of the dependency, its worktree and its
+    /// extracted release archive, so it is cloned again from scratch. The rest
+    /// of the cache is left as it is.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the cached repository cannot be
+    /// removed.
+    pub fn remove_repository(&self, dep: &Dependency) -> Result<()> {
+        let name = url_md5(dep)?;
+        let paths = [
+            self.repos_dir.join(&name),
+            self.worktrees_dir.join(&name),
+            self.archives_dir.join(&name),
+        ];
+        for path in paths {
+            if path.exists() {
+                remove_dir_all(&path).map_err(|err| {
+                    format_err!("cannot remove cached repository {}: {err}", path.display())
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns a [`Repository`] from the cache directory, cloning it from
+    /// `origin` when it is not there, see [`Repository::ensure`]. The origin
+    /// is the interpolated url of the dependency, or the folder its release
+    /// archive was extracted to.
     ///
     /// # Errors
     ///
     /// This function will return an error if cannot open repository.
-    pub fn get_repository(&self, dep: &Dependency) -> Result<Repository> {
-        let path = self.get_repository_path(dep);
+    pub fn get_repository(
+        &self,
+        dep: &Dependency,
+        origin: &str,
+        options: &CloneOptions,
+    ) -> Result<Repository> {
+        let path = self.get_repository_path(dep)?;
+        let mut source = dep.clone();
+        source.url = origin.to_string();
+        Repository::new(path)
+            .ensure(&source, options)
+            .and_then(|repo| self.with_worktree(repo, dep))
+            .map_err(|err| prefixed(err, "cannot ensure repository"))
+    }
+
+    /// Returns a [`Repository`] that is already present in the cache directory,
+    /// without cloning it.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the repository is not cached.
+    pub fn get_cached_repository(&self, dep: &Dependency) -> Result<Repository> {
+        let path = self.get_repository_path(dep)?;
         let repo = Repository::new(path);
-        repo.ensure(dep)
-            .map_err(|err| format_err!("cannot ensure repository: {err}"))
+        let repo = repo
+            .open()
+            .map_err(|err| format_err!("{} is not available in the cache: {err}", dep.url))?;
+        repo.clean()?;
+        self.with_worktree(repo, dep)
+    }
+
+    /// Gives bare repositories a worktree in the cache, where their files
+    /// are checked out while the dependency is vendored.
+    fn with_worktree(&self, repo: Repository, dep: &Dependency) -> Result<Repository> {
+        if repo.is_bare() {
+            Ok(repo.with_worktree(self.worktrees_dir.join(url_md5(dep)?)))
+        } else {
+            Ok(repo)
+        }
+    }
+
+    /// Returns the folder of the files that vendored files are hardlinked to,
+    /// named after the checksum of their contents, see
+    /// [`crate::spec::Spec::hardlink`].
+    pub fn objects_path(&self) -> &Path {
+        &self.objects_dir
+    }
+
+    /// Returns the path where the release archive of the dependency is
+    /// extracted, see [`crate::archive::ensure`].
+    pub fn get_archive_path(&self, dep: &Dependency) -> Result<PathBuf> {
+        Ok(self.archives_dir.join(url_md5(dep)?))
     }
 
-    fn get_repository_path(&self, dep: &Dependency) -> PathBuf {
-        self.repos_dir.join(url_md5(dep))
+    fn get_repository_path(&self, dep: &Dependency) -> Result<PathBuf> {
+        Ok(self.repos_dir.join(url_md5(dep)?))
     }
 
-    fn get_repository_lock_path(&self, dep: &Dependency) -> PathBuf {
-        self.locks_dir.join(url_md5(dep))
+    pub fn get_repository_lock_path(&self, dep: &Dependency) -> Result<PathBuf> {
+        Ok(self.locks_dir.join(url_md5(dep)?))
     }
 }
 
-fn url_md5(dep: &Dependency) -> String {
-    format!("{:x}", sha2::Sha256::digest(&dep.url))
+/// Returns the folder name of the repository of the dependency, every entry
+/// of the cache is named after it. Named dependencies get their own copy, as
+/// the same url can be vendored at different refnames at the same time. The
+/// url is interpolated first, see [`interpolate`], as that is the remote it
+/// is cloned from.
+fn url_md5(dep: &Dependency) -> Result<String> {
+    let url = url::cache_key(&interpolate(&dep.url)?);
+    let key = match &dep.name {
+        Some(name) => format!("{url}#{name}"),
+        None => url,
+    };
+    Ok(format!("{:x}", sha2::Sha256::digest(key)))
+}
+
+fn is_url_md5(name: &str) -> bool {
+    name.len() == 64 && name.chars().all(|c| matches!(c, '0'..='9' | 'a'..='f'))
 }
 
 #[cfg(test)]
@@ -119,6 +324,9 @@ mod tests {
 
     use super::*;
     use crate::test_utils::build_preset;
+    use crate::test_utils::git_commit;
+    use crate::test_utils::git_init;
+    use crate::test_utils::tempdir;
     use crate::test_utils::TestContext;
 
     #[test]
@@ -144,7 +352,112 @@ mod tests {
     }
 
     #[test]
-    fn test_cache_get_repository_path() {
+    fn test_cache_prune() -> Result<()> {
+        let context = &TestContext::new();
+        let sut = Cache::new(&context.preset);
+        sut.initialize()?;
+
+        let active = Dependency::new("active-url", "master");
+        let orphan = Dependency::new("orphan-url", "master");
+        let unknown = sut.repos_dir.join("not-created-by-cache");
+        for path in [
+            sut.get_repository_path(&active)?,
+            sut.get_repository_path(&orphan)?,
+            unknown.clone(),
+        ] {
+            create_dir_all(path)?;
+        }
+        fs::write(sut.get_repository_lock_path(&orphan)?, "")?;
+
+        let pruned = sut.prune(std::slice::from_ref(&active))?;
+
+        assert_eq!(vec![sut.get_repository_path(&orphan)?], pruned);
+        assert!(!sut.get_repository_path(&orphan)?.exists());
+        assert!(!sut.get_repository_lock_path(&orphan)?.exists());
+        assert!(sut.get_repository_path(&active)?.exists());
+        assert!(unknown.exists(), "should not touch unknown entries");
+        Ok(())
+    }
+
+    #[test]
+    fn test_cache_prune_keeps_interpolated_urls() -> Result<()> {
+        let context = &TestContext::new();
+        let upstream = tempdir();
+        git_init(upstream.path(), &[("file.txt", "data")]);
+        let sut = Cache::new(&context.preset);
+        sut.initialize()?;
+        let url = format!("${{VENDOR_TEST_UNSET:-{}}}", upstream.path().display());
+        let dep = Dependency::new(url, "master");
+        let origin = interpolate(&dep.url)?;
+        let repository = sut.get_repository(&dep, &origin, &CloneOptions::default())?;
+
+        let pruned = sut.prune(std::slice::from_ref(&dep))?;
+
+        assert!(pruned.is_empty(), "{pruned:?}");
+        assert!(repository.path().exists());
+        assert_eq!(
+            sut.get_repository_lock_path(&Dependency::new(origin, "master"))?,
+            sut.get_repository_lock_path(&dep)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_cache_remove_repository() -> Result<()> {
+        let context = &TestContext::new();
+        let sut = Cache::new(&context.preset);
+        sut.initialize()?;
+
+        let removed = Dependency::new("removed-url", "master");
+        let kept = Dependency::new("kept-url", "master");
+        for path in [
+            sut.get_repository_path(&removed)?,
+            sut.worktrees_dir.join(url_md5(&removed)?),
+            sut.get_repository_path(&kept)?,
+        ] {
+            create_dir_all(path)?;
+        }
+
+        sut.remove_repository(&removed)?;
+
+        assert!(!sut.get_repository_path(&removed)?.exists());
+        assert!(!sut.worktrees_dir.join(url_md5(&removed)?).exists());
+        assert!(sut.get_repository_path(&kept)?.exists());
+        sut.remove_repository(&removed)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_cache_gc() -> Result<()> {
+        let context = &TestContext::new();
+        let upstream = tempdir();
+        let git = git_init(upstream.path(), &[("file.txt", "data")]);
+        let sut = Cache::new(&context.preset);
+        sut.initialize()?;
+        let in_use = Dependency::new(upstream.path().to_string_lossy(), "master");
+        let idle = Dependency::new(format!("file://{}", upstream.path().display()), "master");
+        for dep in [&in_use, &idle] {
+            sut.get_repository(dep, &dep.url, &CloneOptions::default())?;
+        }
+        let head = git_commit(&git, &[("file.txt", "changed")], "change");
+        let repository = sut.get_repository(&idle, &idle.url, &CloneOptions::default())?;
+        repository.fetch("master")?;
+        let _lock = sut.lock_repository(&in_use)?;
+
+        let collected = sut.gc()?;
+
+        assert_eq!(vec![sut.get_repository_path(&idle)?], collected);
+        repository.checkout(&head.to_string())?;
+        assert_eq!(head.to_string(), repository.get_current_refname()?);
+        assert_eq!(
+            "changed",
+            fs::read_to_string(repository.path().join("file.txt"))?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_cache_get_repository_path() -> Result<()> {
         let preset = &build_preset();
         let dep = &Dependency::new("some-url", "some-branch");
 
@@ -152,12 +465,13 @@ mod tests {
 
         assert_eq!(
             ".test-cache/repos/807460ee997e6fbe9d826f58a2af79c570f7bb5aa26f48d9b18dc320af428a05",
-            sut.get_repository_path(dep).as_os_str()
+            sut.get_repository_path(dep)?.as_os_str()
         );
+        Ok(())
     }
 
     #[test]
-    fn test_cache_get_repository_lock_path() {
+    fn test_cache_get_repository_lock_path() -> Result<()> {
         let preset = &build_preset();
         let dep = &Dependency::new("some-url", "some-branch");
 
@@ -165,7 +479,25 @@ mod tests {
 
         assert_eq!(
             ".test-cache/locks/807460ee997e6fbe9d826f58a2af79c570f7bb5aa26f48d9b18dc320af428a05",
-            sut.get_repository_lock_path(dep).as_os_str()
+            sut.get_repository_lock_path(dep)?.as_os_str()
         );
+        Ok(())
+    }
+
+    #[test]
+    fn test_cache_get_repository_path_of_equivalent_urls() -> Result<()> {
+        let preset = &build_preset();
+        let sut = Cache::new(preset);
+
+        let expected = sut.get_repository_path(&Dependency::new("https://github.com/a/b", "v1"))?;
+        for url in [
+            "https://github.com/a/b.git",
+            "git@github.com:a/b.git",
+            "https://GitHub.com/A/B",
+        ] {
+            let dep = &Dependency::new(url, "v1");
+            assert_eq!(expected, sut.get_repository_path(dep)?, "{url}");
+        }
+        Ok(())
     }
 }
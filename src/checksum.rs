@@ -0,0 +1,271 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::thread;
+
+use anyhow::format_err;
+use anyhow::Result;
+use sha2::Digest;
+use sha2::Sha256;
+
+/// Checksums of the files of a folder, keyed by their path relative to it.
+pub type Checksums = BTreeMap<String, String>;
+
+/// Difference between the recorded checksums and the files on disk.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Mismatch {
+    Modified(String),
+    Missing(String),
+    Unexpected(String),
+}
+
+impl Mismatch {
+    /// Returns the path of the mismatching file.
+    pub fn path(&self) -> &str {
+        match self {
+            Self::Modified(path) | Self::Missing(path) | Self::Unexpected(path) => path,
+        }
+    }
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Modified(path) => write!(f, "{path} has been modified"),
+            Self::Missing(path) => write!(f, "{path} is missing"),
+            Self::Unexpected(path) => write!(f, "{path} is not locked"),
+        }
+    }
+}
+
+/// Returns the default number of hashing jobs, one per available core.
+pub fn default_jobs() -> usize {
+    thread::available_parallelism().map_or(1, Into::into)
+}
+
+/// Files added, removed and modified between two runs, sorted by path.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Changes {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub modified: Vec<String>,
+}
+
+impl Changes {
+    /// Compares the manifests of two runs. Files are only reported as
+    /// modified when both manifests have their checksums.
+    pub fn between(before: &Checksums, after: &Checksums) -> Self {
+        let mut changes = Self::default();
+        for (path, checksum) in after {
+            match before.get(path) {
+                None => changes.added.push(path.clone()),
+                Some(previous) if !checksum.is_empty() && previous != checksum => {
+                    changes.modified.push(path.clone());
+                }
+                Some(_) => {}
+            }
+        }
+        for path in before.keys().filter(|path| !after.contains_key(*path)) {
+            changes.removed.push(path.clone());
+        }
+        changes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.modified.is_empty()
+    }
+}
+
+/// Returns the manifest of the files under `root`, an empty manifest when
+/// it does not exist. Without checksums, the manifest maps the files to
+/// empty strings.
+pub fn manifest(root: &Path, with_checksums: bool) -> Result<Checksums> {
+    if !root.exists() {
+        return Ok(Checksums::new());
+    }
+    if with_checksums {
+        return hash_tree(root, default_jobs());
+    }
+    list_files(root)?
+        .iter()
+        .map(|path| Ok((relative(root, path)?, String::new())))
+        .collect()
+}
+
+/// Hashes every file under `root`, spreading the files across at most
+/// `jobs` threads.
+pub fn hash_tree(root: &Path, jobs: usize) -> Result<Checksums> {
+    let files = list_files(root)?;
+
+    let next = AtomicUsize::new(0);
+    let results: Vec<Result<Checksums>> = thread::scope(|s| {
+        let handles: Vec<_> = (0..jobs.clamp(1, files.len().max(1)))
+            .map(|_| {
+                s.spawn(|| {
+                    let mut checksums = Checksums::new();
+                    loop {
+                        let Some(path) = files.get(next.fetch_add(1, Ordering::Relaxed)) else {
+                            return Ok(checksums);
+                        };
+                        checksums.insert(relative(root, path)?, hash_file(path)?);
+                    }
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| {
+                handle
+                    .join()
+                    .unwrap_or_else(|_| Err(format_err!("hashing thread panicked")))
+            })
+            .collect()
+    });
+
+    let mut checksums = Checksums::new();
+    for result in results {
+        checksums.extend(result?);
+    }
+    Ok(checksums)
+}
+
+/// Compares the recorded checksums against the files under `root`, returns
+/// the mismatches sorted by kind and path.
+pub fn verify(expected: &Checksums, root: &Path, jobs: usize) -> Result<Vec<Mismatch>> {
+    let actual = hash_tree(root, jobs)?;
+
+    let mut mismatches = vec![];
+    for (path, checksum) in expected {
+        match actual.get(path) {
+            Some(found) if found == checksum => {}
+            Some(_) => mismatches.push(Mismatch::Modified(path.clone())),
+            None => mismatches.push(Mismatch::Missing(path.clone())),
+        }
+    }
+    for path in actual.keys().filter(|path| !expected.contains_key(*path)) {
+        mismatches.push(Mismatch::Unexpected(path.clone()));
+    }
+    mismatches.sort();
+    Ok(mismatches)
+}
+
+fn list_files(root: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    for entry in walkdir::WalkDir::new(root) {
+        let entry = entry?;
+        if entry.file_type().is_file() {
+            files.push(entry.into_path());
+        }
+    }
+    Ok(files)
+}
+
+fn relative(root: &Path, path: &Path) -> Result<String> {
+    Ok(path.strip_prefix(root)?.to_string_lossy().to_string())
+}
+
+fn hash_file(path: &Path) -> Result<String> {
+    let contents =
+        fs::read(path).map_err(|err| format_err!("cannot read {}: {err}", path.display()))?;
+    Ok(format!("{:x}", Sha256::digest(contents)))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::test_utils::tempdir;
+
+    fn write_fixture(root: &Path) {
+        for n in 0..20 {
+            let path = root.join(format!("dir-{}/file-{n}.txt", n % 3));
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, format!("contents {n}")).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_hash_tree_is_independent_of_jobs() -> Result<()> {
+        let dir = tempdir();
+        write_fixture(dir.path());
+
+        let serial = hash_tree(dir.path(), 1)?;
+
+        assert_eq!(20, serial.len());
+        assert_eq!(serial, hash_tree(dir.path(), 4)?);
+        assert_eq!(serial, hash_tree(dir.path(), 64)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_parallel_matches_serial() -> Result<()> {
+        let dir = tempdir();
+        write_fixture(dir.path());
+        let expected = hash_tree(dir.path(), 1)?;
+        fs::write(dir.path().join("dir-1/file-4.txt"), "tampered")?;
+        fs::remove_file(dir.path().join("dir-2/file-5.txt"))?;
+        fs::write(dir.path().join("dir-0/extra.txt"), "extra")?;
+
+        let serial = verify(&expected, dir.path(), 1)?;
+        let parallel = verify(&expected, dir.path(), 8)?;
+
+        assert_eq!(
+            vec![
+                Mismatch::Modified("dir-1/file-4.txt".into()),
+                Mismatch::Missing("dir-2/file-5.txt".into()),
+                Mismatch::Unexpected("dir-0/extra.txt".into()),
+            ],
+            serial
+        );
+        assert_eq!(serial, parallel);
+        Ok(())
+    }
+
+    #[test]
+    fn test_changes_between() {
+        let manifest = |entries: &[(&str, &str)]| -> Checksums {
+            entries
+                .iter()
+                .map(|(path, checksum)| ((*path).to_string(), (*checksum).to_string()))
+                .collect()
+        };
+        let before = manifest(&[("kept", "1"), ("changed", "1"), ("removed", "1")]);
+        let after = manifest(&[("kept", "1"), ("changed", "2"), ("added", "1")]);
+
+        assert_eq!(
+            Changes {
+                added: vec!["added".into()],
+                removed: vec!["removed".into()],
+                modified: vec!["changed".into()],
+            },
+            Changes::between(&before, &after)
+        );
+
+        let unhashed = manifest(&[("kept", ""), ("changed", "")]);
+        assert!(Changes::between(&unhashed, &unhashed).is_empty());
+    }
+
+    #[test]
+    fn test_manifest_without_checksums() -> Result<()> {
+        let dir = tempdir();
+        write_fixture(dir.path());
+
+        let actual = manifest(dir.path(), false)?;
+
+        assert_eq!(20, actual.len());
+        assert!(actual.values().all(String::is_empty));
+        assert!(manifest(&dir.path().join("missing"), true)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_hash_tree_of_empty_folder() -> Result<()> {
+        let dir = tempdir();
+        assert!(hash_tree(dir.path(), 4)?.is_empty());
+        Ok(())
+    }
+}
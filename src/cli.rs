@@ -1,14 +1,12 @@
 use anyhow::Result;
 use clap::Parser;
-use simplelog::ColorChoice;
-use simplelog::ConfigBuilder;
-use simplelog::LevelFilter;
-use simplelog::TermLogger;
-use simplelog::TerminalMode;
+use log::LevelFilter;
 
 use self::structs::Cli;
 use self::structs::Commands;
+use crate::config::Config;
 use crate::control::Controller;
+use crate::logging::init_logging;
 use crate::preset::Preset;
 
 mod structs;
@@ -20,39 +18,61 @@ mod structs;
 /// Will return `Err` if the operation has not succeeded.
 pub fn run() -> Result<()> {
     let cli = Cli::parse();
-    setup_logging(cli.debug);
+    let level = match cli.log_level {
+        Some(level) => level,
+        None if cli.debug => LevelFilter::Debug,
+        None => LevelFilter::Info,
+    };
+    let _ = init_logging(level, cli.log_format);
 
     let preset = Preset::new();
-    let controller = Controller::new(preset);
+    let mut controller = Controller::new(preset)
+        .with_offline(cli.offline)
+        .with_vendor(cli.vendor.map(Into::into))
+        .with_config(Config::load()?);
+    if let Some(path) = cli.metrics {
+        controller = controller.with_metrics(path);
+    }
     match cli.command {
-        Commands::Init {} => controller.init(),
+        Commands::Init { url, force } => controller.init(url.as_deref(), force)?,
         Commands::Add {
             url,
             refname,
             extensions,
             targets,
             ignores,
-        } => controller.add(&url, &refname, extensions, targets, ignores),
-        Commands::Install {} => controller.install()?,
-        Commands::Update {} => controller.update()?,
+        } => controller.add(&url, &refname, extensions, targets, ignores)?,
+        Commands::Split { dir } => controller.split(&dir)?,
+        Commands::Install {
+            jobs,
+            frozen,
+            force,
+        } => {
+            controller
+                .with_jobs(jobs)
+                .with_frozen(frozen)
+                .with_force(force)
+                .install()?;
+        }
+        Commands::WarmCache {} => controller.warm_cache()?,
+        Commands::Update { jobs, only, force } => {
+            let controller = controller.with_jobs(jobs).with_force(force);
+            match only {
+                Some(url) => controller.update_one(&url)?,
+                None => controller.update()?,
+            }
+        }
+        Commands::Diff { jobs } => controller.with_jobs(jobs).diff()?,
+        Commands::Verify { jobs } => controller.verify(jobs)?,
+        Commands::Outdated {} => controller.outdated()?,
+        Commands::Patches { url, dir, from, to } => {
+            controller.export_patches(&url, from.as_deref(), to.as_deref(), &dir)?;
+        }
+        Commands::Explain { url, path } => controller.explain(&url, &path)?,
         Commands::ClearCache {} => controller.clear_cache()?,
-    };
+        Commands::PruneCache {} => controller.prune_cache()?,
+        Commands::PruneLock {} => controller.prune_lock()?,
+        Commands::GcCache {} => controller.gc_cache()?,
+    }
     Ok(())
 }
-
-fn setup_logging(is_debug: bool) {
-    let logging_config = ConfigBuilder::default()
-        .set_time_level(LevelFilter::Off)
-        .build();
-    TermLogger::init(
-        if is_debug {
-            LevelFilter::Debug
-        } else {
-            LevelFilter::Info
-        },
-        logging_config,
-        TerminalMode::Mixed,
-        ColorChoice::Auto,
-    )
-    .unwrap();
-}
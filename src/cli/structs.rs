@@ -1,5 +1,8 @@
 use clap::Parser;
 use clap::Subcommand;
+use log::LevelFilter;
+
+use crate::logging::LogFormat;
 
 #[derive(Parser)]
 #[clap(author, version, about, long_about = None)]
@@ -9,15 +12,43 @@ pub struct Cli {
     #[clap(subcommand)]
     pub command: Commands,
 
-    /// Enable debug logging
+    /// Enable debug logging, same as `--log-level debug`
     #[clap(short, long, takes_value = false, parse(from_flag))]
     pub debug: bool,
+
+    /// Only log up to this level: off, error, warn, info, debug or trace
+    #[clap(long, global = true)]
+    pub log_level: Option<LevelFilter>,
+
+    /// Format of the logs: human, or structured to include the time and
+    /// module of each record
+    #[clap(long, global = true, default_value = "human")]
+    pub log_format: LogFormat,
+
+    /// Refuse network access, only use the repositories already cached
+    #[clap(long, global = true, takes_value = false, parse(from_flag))]
+    pub offline: bool,
+
+    /// Vendor into this folder instead of the one in the spec file
+    #[clap(long, global = true)]
+    pub vendor: Option<String>,
+
+    /// Write install and update metrics to a file, in Prometheus text format
+    #[clap(long, global = true)]
+    pub metrics: Option<String>,
 }
 
 #[derive(Subcommand)]
 pub enum Commands {
     /// Initialize the working directory
-    Init {},
+    Init {
+        /// Git URL of a first repository to vendor
+        url: Option<String>,
+
+        /// Overwrite the spec file if it already exists
+        #[clap(short, long, takes_value = false, parse(from_flag))]
+        force: bool,
+    },
 
     /// Add a dependency to the spec file
     Add {
@@ -41,12 +72,113 @@ pub enum Commands {
         ignores: Option<Vec<String>>,
     },
 
+    /// Moves each dependency into its own file, included by the spec file
+    Split {
+        /// Directory for the dependency files, relative to the spec file
+        #[clap(default_value = "deps")]
+        dir: String,
+    },
+
     /// Vendors the dependencies respecting the lock pins
-    Install {},
+    Install {
+        /// Number of dependencies vendored in parallel, all of them by
+        /// default. A single job vendors them one by one, in spec order
+        #[clap(short, long)]
+        jobs: Option<usize>,
+
+        /// Fails instead of modifying the lock file, when it is not up to
+        /// date with the spec file
+        #[clap(long)]
+        frozen: bool,
+
+        /// Removes the cached repositories of the dependencies, so they are
+        /// cloned again from scratch
+        #[clap(long)]
+        force: bool,
+    },
+
+    /// Clones or fetches the dependencies into the cache without vendoring
+    /// them, so that `install --offline` can run later without network
+    WarmCache {},
 
     /// Updates the vendored dependencies according to the desired refname
     /// in the spec file, updates the pins in the lock file.
-    Update {},
+    Update {
+        /// Number of dependencies updated in parallel, all of them by
+        /// default. A single job updates them one by one, in spec order
+        #[clap(short, long)]
+        jobs: Option<usize>,
+
+        /// Only updates this dependency, given by its name or url, the
+        /// others are installed as locked
+        #[clap(long)]
+        only: Option<String>,
+
+        /// Removes the cached repositories of the dependencies, so they are
+        /// cloned again from scratch
+        #[clap(long)]
+        force: bool,
+    },
+
+    /// Lists the files that a fresh install would add, remove or modify in
+    /// the vendor folder, without replacing it
+    Diff {
+        /// Number of dependencies vendored in parallel, all of them by
+        /// default
+        #[clap(short, long)]
+        jobs: Option<usize>,
+    },
+
+    /// Verifies the vendored files against the checksums of the lock file
+    Verify {
+        /// Number of files hashed in parallel, one per core by default
+        #[clap(short, long)]
+        jobs: Option<usize>,
+    },
+
+    /// Lists the dependencies whose refname points to a newer commit than
+    /// the locked one
+    Outdated {},
+
+    /// Exports the commits of a dependency as numbered patch files, from the
+    /// locked commit to the refname in the spec file by default
+    Patches {
+        /// Git URL of the dependency
+        url: String,
+
+        /// Directory where the patch files are written
+        #[clap(default_value = "patches")]
+        dir: String,
+
+        /// Refname where the range starts, excluded
+        #[clap(long)]
+        from: Option<String>,
+
+        /// Refname where the range ends, included
+        #[clap(long)]
+        to: Option<String>,
+    },
+
+    /// Explains whether a file of a dependency is vendored by the filters,
+    /// and which target, ignore or extension decides it
+    Explain {
+        /// Git URL of the dependency
+        url: String,
+
+        /// Path of the file, relative to the root of the dependency
+        path: String,
+    },
 
     ClearCache {},
+
+    /// Removes the cached repositories of dependencies that are no longer in
+    /// the spec file
+    PruneCache {},
+
+    /// Removes the entries of the lock file of dependencies that are no
+    /// longer in the spec file
+    PruneLock {},
+
+    /// Compacts the cached repositories, skipping the ones in use
+    GcCache {},
 }
@@ -0,0 +1,177 @@
+use std::env;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::Result;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::spec::Spec;
+use crate::timeout;
+use crate::yaml;
+
+/// User level defaults shared across projects, loaded from
+/// `$XDG_CONFIG_HOME/vendify/config.yml`, or `~/.config/vendify/config.yml`.
+///
+/// The settings are layered, from lowest to highest precedence: the built-in
+/// defaults, this config, the spec of the project, and the command line
+/// flags. A spec setting left at its default does not override this config.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Config {
+    /// Cache folder, instead of the one of the preset. The `VENDOR_CACHE`
+    /// environment variable still takes precedence.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache: Option<String>,
+
+    /// Number of dependencies processed at the same time, when the command
+    /// line does not set one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jobs: Option<usize>,
+
+    /// Seconds after which cloning or fetching a dependency is aborted, when
+    /// the spec does not set one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_secs: Option<u64>,
+
+    /// Proxy to reach the remotes through, when the spec does not set one,
+    /// see [`Spec::proxy`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<String>,
+
+    /// Name of the environment variable holding the token offered to the
+    /// remotes, when the spec does not set one, see [`Spec::token_env`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_env: Option<String>,
+}
+
+impl Config {
+    /// Loads the config from its default location, see [`Config`]. A missing
+    /// config file yields the defaults.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the config file cannot be parsed.
+    pub fn load() -> Result<Self> {
+        match default_path() {
+            Some(path) => Self::load_from(path),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Loads the config from `path`, a missing file yields the defaults.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the config file cannot be parsed.
+    pub fn load_from<P: AsRef<Path>>(path: P) -> Result<Self> {
+        if !path.as_ref().exists() {
+            return Ok(Self::default());
+        }
+        yaml::load(path)
+    }
+
+    /// Returns a copy of the spec with the settings it leaves at their
+    /// defaults taken from this config.
+    pub fn layer(&self, spec: &Spec) -> Spec {
+        let mut layered = spec.clone();
+        if let Some(secs) = self
+            .timeout_secs
+            .filter(|_| timeout::is_default_secs(&spec.timeout_secs))
+        {
+            layered.timeout_secs = secs;
+        }
+        if spec.proxy.is_none() {
+            layered.proxy.clone_from(&self.proxy);
+        }
+        if spec.token_env.is_none() {
+            layered.token_env.clone_from(&self.token_env);
+        }
+        layered
+    }
+}
+
+fn default_path() -> Option<PathBuf> {
+    let config_dir = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| home::home_dir().map(|home| home.join(".config")))?;
+    Some(config_dir.join("vendify").join("config.yml"))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::test_utils::tempdir;
+    use crate::test_utils::write_to;
+    use crate::test_utils::TestContext;
+
+    #[test]
+    fn test_config_load_from() -> Result<()> {
+        let dir = tempdir();
+        let path = dir.path().join("config.yml");
+        assert_eq!(Config::default(), Config::load_from(&path)?);
+
+        write_to(
+            &path,
+            "cache: /shared/cache\njobs: 4\ntimeout_secs: 30\nproxy: \
+             http://proxy:3128\ntoken_env: GITHUB_TOKEN\n",
+        );
+
+        assert_eq!(
+            Config {
+                cache: Some("/shared/cache".into()),
+                jobs: Some(4),
+                timeout_secs: Some(30),
+                proxy: Some("http://proxy:3128".into()),
+                token_env: Some("GITHUB_TOKEN".into()),
+            },
+            Config::load_from(&path)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_config_layer_spec_overrides_global() {
+        let ctx = TestContext::new();
+        let config = Config {
+            timeout_secs: Some(30),
+            ..Config::default()
+        };
+        let mut spec = Spec::with_preset(&ctx.preset);
+
+        assert_eq!(30, config.layer(&spec).timeout_secs);
+        assert_eq!(
+            timeout::DEFAULT_SECS,
+            spec.timeout_secs,
+            "spec is not modified"
+        );
+
+        spec.timeout_secs = 10;
+        assert_eq!(10, config.layer(&spec).timeout_secs);
+
+        assert_eq!(10, Config::default().layer(&spec).timeout_secs);
+    }
+
+    #[test]
+    fn test_config_layer_proxy_and_token_env() {
+        let ctx = TestContext::new();
+        let config = Config {
+            proxy: Some("http://global:3128".into()),
+            token_env: Some("GLOBAL_TOKEN".into()),
+            ..Config::default()
+        };
+        let mut spec = Spec::with_preset(&ctx.preset);
+
+        let layered = config.layer(&spec);
+        assert_eq!(Some("http://global:3128"), layered.proxy.as_deref());
+        assert_eq!(Some("GLOBAL_TOKEN"), layered.token_env.as_deref());
+        assert_eq!(None, spec.proxy, "spec is not modified");
+
+        spec.proxy = Some("http://project:3128".into());
+        spec.token_env = Some("PROJECT_TOKEN".into());
+        let layered = config.layer(&spec);
+        assert_eq!(Some("http://project:3128"), layered.proxy.as_deref());
+        assert_eq!(Some("PROJECT_TOKEN"), layered.token_env.as_deref());
+    }
+}
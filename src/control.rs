@@ -1,5 +1,10 @@
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::Path;
 use std::path::PathBuf;
 
+use anyhow::format_err;
 use anyhow::Result;
 
 use super::deps::Dependency;
@@ -7,36 +12,113 @@ use super::installer::Installer;
 use super::spec::Spec;
 use super::spec_lock::SpecLock;
 use crate::cache::Cache;
+use crate::checksum;
+use crate::config::Config;
 use crate::filters::FilterKind;
+use crate::metrics::Metrics;
 use crate::preset::Preset;
+use crate::preset::CACHE_ENV;
 
 pub struct Controller {
     preset: Preset,
+    metrics: Option<PathBuf>,
+    offline: bool,
+    frozen: bool,
+    force: bool,
+    vendor: Option<PathBuf>,
+    jobs: Option<usize>,
+    config: Config,
 }
 
 impl Controller {
     pub fn new(preset: Preset) -> Self {
-        Self { preset }
+        Self {
+            preset,
+            metrics: None,
+            offline: false,
+            frozen: false,
+            force: false,
+            vendor: None,
+            jobs: None,
+            config: Config::default(),
+        }
+    }
+
+    /// Uses the user level defaults of `config`, see [`Config`] for how they
+    /// are layered with the spec and the command line.
+    pub fn with_config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Writes the metrics of install and update runs to the given path,
+    /// using the Prometheus text exposition format.
+    pub fn with_metrics(mut self, path: impl Into<PathBuf>) -> Self {
+        self.metrics = Some(path.into());
+        self
     }
 
-    pub fn init(&self) {
+    /// Refuses any network access, install only uses the repositories that
+    /// are already cached, and update is rejected.
+    pub fn with_offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    /// Refuses to modify the lock, install fails when the lock would change,
+    /// for instance when a refname of the spec changed since it was locked.
+    pub fn with_frozen(mut self, frozen: bool) -> Self {
+        self.frozen = frozen;
+        self
+    }
+
+    /// Removes the cached repositories of the dependencies before install
+    /// and update, so they are cloned again from scratch.
+    pub fn with_force(mut self, force: bool) -> Self {
+        self.force = force;
+        self
+    }
+
+    /// Vendors the dependencies into a different folder than the one
+    /// configured in the spec file, without modifying the spec.
+    pub fn with_vendor(mut self, vendor: Option<PathBuf>) -> Self {
+        self.vendor = vendor;
+        self
+    }
+
+    /// Limits how many dependencies are installed or updated at the same
+    /// time, see [`Installer::with_jobs`].
+    pub fn with_jobs(mut self, jobs: Option<usize>) -> Self {
+        self.jobs = jobs;
+        self
+    }
+
+    /// Writes a default spec file, optionally depending on `url`. An existing
+    /// spec file is only overwritten when `force` is set.
+    pub fn init(&self, url: Option<&str>, force: bool) -> Result<()> {
         log::info!("initializing vendor in current directory");
 
-        let spec_path: &PathBuf = &self.preset.spec().into();
-        if spec_path.exists() {
-            log::warn!("{} already exists", spec_path.display());
-            return;
+        let spec_path = Path::new(self.preset.spec());
+        if spec_path.exists() && !force {
+            return Err(format_err!(
+                "{} already exists, use --force to overwrite it",
+                spec_path.display()
+            ));
         }
 
         let mut spec = Spec::with_preset(&self.preset);
-        if let Err(err) = spec.save() {
-            log::error!("{err}");
-            return;
+        if let Some(url) = url {
+            spec.add_dependency(Dependency::new(url, "master"));
         }
+        spec.save()?;
 
         log::info!("{} has been created", spec_path.display());
+        Ok(())
     }
 
+    /// Adds a new dependency to the spec file, with the filters of the
+    /// preset applied. Dependencies that are already in the spec are
+    /// rejected.
     pub fn add(
         &self,
         url: &str,
@@ -44,14 +126,8 @@ impl Controller {
         extensions: Option<Vec<String>>,
         targets: Option<Vec<String>>,
         ignores: Option<Vec<String>>,
-    ) {
-        let mut spec = match Spec::load_from(&self.preset) {
-            Ok(spec) => spec,
-            Err(err) => {
-                log::error!("{err}");
-                return;
-            }
-        };
+    ) -> Result<()> {
+        let mut spec = Spec::load_from(&self.preset)?;
 
         let mut dep = Dependency::new(url, refname);
         if let Some(extensions) = extensions {
@@ -63,60 +139,295 @@ impl Controller {
         if let Some(ignores) = ignores {
             dep.filters.add(FilterKind::Ignore(ignores));
         }
-        spec.add_dependency(dep);
+        spec.add_new_dependency(dep)?;
+        spec.save()
+            .map_err(|err| format_err!("cannot add dependency: {err}"))?;
 
-        match spec.save() {
-            Ok(_) => {
-                log::info!("added dependency {url}@{refname}");
-            }
-            Err(err) => {
-                log::error!("cannot add dependency: {err}");
-            }
-        }
+        log::info!("added dependency {url}@{refname}");
+        Ok(())
+    }
+
+    pub fn split(&self, dir: &str) -> Result<()> {
+        let spec = Spec::load_from(&self.preset)?;
+        let mut root = spec.split(dir)?;
+        root.save()?;
+
+        log::info!(
+            "moved {} dependencies into {dir}, {} now includes them",
+            root.includes.len(),
+            self.preset.spec()
+        );
+        Ok(())
     }
 
     pub fn install(&self) -> Result<()> {
         let (mut spec, spec_lock) = self.load_both()?;
-        let cache = Cache::new(&self.preset);
-        let _cache_lock = cache.lock();
-        let installer = Installer::new(cache, &spec, spec_lock);
+        let cache = self.cache();
+        let _cache_lock = cache.lock_shared();
+        let layered = self.config.layer(&spec);
+        let installer = Installer::new(cache, &layered, spec_lock)
+            .with_offline(self.offline)
+            .with_force(self.force)
+            .with_frozen(self.frozen)
+            .with_vendor(self.vendor.clone())
+            .with_jobs(self.jobs.or(self.config.jobs));
 
         if let Err(err) = {
-            let mut spec_lock = installer.install()?;
-            spec_lock.save()?;
-            spec.save()?;
-            Ok(())
+            if self.frozen {
+                installer.check_frozen()?;
+            }
+            let (mut spec_lock, metrics) = if self.offline {
+                installer.install_from_cache()?
+            } else {
+                installer.install()?
+            };
+            self.save_metrics(&metrics)?;
+            check_failures(&metrics)?;
+            if !self.frozen {
+                spec_lock.save()?;
+            }
+            spec.save()
         } {
             log::error!("install failed: {err}");
             return Err(err);
-        };
+        }
 
         log::info!("install success ✅");
         Ok(())
     }
 
+    /// Clones or fetches the dependencies into the cache, so a later
+    /// `install --offline` does not need network access.
+    pub fn warm_cache(&self) -> Result<()> {
+        let (spec, spec_lock) = self.load_both()?;
+        let cache = self.cache();
+        let _cache_lock = cache.lock_shared();
+        let layered = self.config.layer(&spec);
+        let installer = Installer::new(cache, &layered, spec_lock).with_offline(self.offline);
+
+        installer.warm_cache()?;
+        log::info!("cache is warm ✅");
+        Ok(())
+    }
+
     pub fn update(&self) -> Result<()> {
+        self.run_update(None)
+    }
+
+    /// Updates a single dependency, given by its name or url, the others are
+    /// left as locked.
+    pub fn update_one(&self, url: &str) -> Result<()> {
+        self.run_update(Some(url))
+    }
+
+    fn run_update(&self, only: Option<&str>) -> Result<()> {
         let (mut spec, spec_lock) = self.load_both()?;
-        let cache = Cache::new(&self.preset);
-        let _cache_lock = cache.lock();
-        let installer = Installer::new(cache, &spec, spec_lock);
+        let cache = self.cache();
+        let _cache_lock = cache.lock_shared();
+        let layered = self.config.layer(&spec);
+        let installer = Installer::new(cache, &layered, spec_lock)
+            .with_offline(self.offline)
+            .with_force(self.force)
+            .with_vendor(self.vendor.clone())
+            .with_jobs(self.jobs.or(self.config.jobs));
 
         if let Err(err) = {
-            let mut spec_lock = installer.update()?;
+            let (mut spec_lock, metrics) = match only {
+                Some(url) => installer.update_one(url)?,
+                None => installer.update()?,
+            };
+            self.save_metrics(&metrics)?;
+            check_failures(&metrics)?;
             spec_lock.save()?;
-            spec.save()?;
-            Ok(())
+            spec.save()
         } {
             log::error!("update failed: {err}");
             return Err(err);
-        };
+        }
 
         log::info!("update success ✅");
         Ok(())
     }
 
+    /// Logs the files that a fresh install would add, remove or modify in
+    /// the vendor folder, without replacing it.
+    pub fn diff(&self) -> Result<()> {
+        let (spec, spec_lock) = self.load_both()?;
+        let cache = self.cache();
+        let _cache_lock = cache.lock_shared();
+        let layered = self.config.layer(&spec);
+        let installer = Installer::new(cache, &layered, spec_lock)
+            .with_offline(self.offline)
+            .with_vendor(self.vendor.clone())
+            .with_jobs(self.jobs.or(self.config.jobs));
+
+        let changes = installer.diff()?;
+        if changes.is_empty() {
+            log::info!("vendor folder is up to date ✅");
+            return Ok(());
+        }
+        for path in &changes.added {
+            log::info!("\tadded {path}");
+        }
+        for path in &changes.removed {
+            log::info!("\tremoved {path}");
+        }
+        for path in &changes.modified {
+            log::info!("\tmodified {path}");
+        }
+        log::info!(
+            "{} added, {} removed, {} modified",
+            changes.added.len(),
+            changes.removed.len(),
+            changes.modified.len()
+        );
+        Ok(())
+    }
+
+    pub fn outdated(&self) -> Result<()> {
+        let (spec, spec_lock) = self.load_both()?;
+        let layered = self.config.layer(&spec);
+        let installer =
+            Installer::new(self.cache(), &layered, spec_lock).with_offline(self.offline);
+
+        let outdated = installer.outdated()?;
+        if outdated.is_empty() {
+            log::info!("all dependencies are up to date ✅");
+        }
+        for it in outdated {
+            log::info!(
+                "{}: {} -> {}",
+                it.url,
+                it.locked.as_deref().unwrap_or("not locked"),
+                it.latest
+            );
+        }
+        Ok(())
+    }
+
+    pub fn export_patches(
+        &self,
+        url: &str,
+        from: Option<&str>,
+        to: Option<&str>,
+        dir: &str,
+    ) -> Result<()> {
+        let (spec, spec_lock) = self.load_both()?;
+        let cache = self.cache();
+        let _cache_lock = cache.lock_shared();
+        let layered = self.config.layer(&spec);
+        let installer = Installer::new(cache, &layered, spec_lock).with_offline(self.offline);
+
+        let patches = installer.export_patches(url, from, to, Path::new(dir))?;
+        for path in &patches {
+            log::info!("\t{}", path.display());
+        }
+        log::info!("exported {} patches into {dir}", patches.len());
+        Ok(())
+    }
+
+    /// Logs whether the file at `path` of the dependency is vendored by the
+    /// filters, and why.
+    pub fn explain(&self, url: &str, path: &str) -> Result<()> {
+        let (spec, spec_lock) = self.load_both()?;
+        let layered = self.config.layer(&spec);
+        let installer = Installer::new(self.cache(), &layered, spec_lock);
+
+        let decision = installer.explain(url, Path::new(path))?;
+        log::info!("{path}: {decision}");
+        Ok(())
+    }
+
+    /// Verifies the vendor folder against the checksums of the lock file,
+    /// hashing the files with at most `jobs` threads.
+    pub fn verify(&self, jobs: Option<usize>) -> Result<()> {
+        let (spec, spec_lock) = self.load_both()?;
+        if spec_lock.checksums.is_empty() {
+            return Err(format_err!(
+                "{} has no checksums, enable lock_checksums and run install",
+                self.preset.spec_lock()
+            ));
+        }
+
+        let vendor = self.vendor.clone().unwrap_or_else(|| spec.vendor.into());
+        let jobs = jobs.unwrap_or_else(checksum::default_jobs);
+        let mismatches = checksum::verify(&spec_lock.checksums, &vendor, jobs)?;
+        if mismatches.is_empty() {
+            log::info!("verified {} files ✅", spec_lock.checksums.len());
+            return Ok(());
+        }
+        for mismatch in &mismatches {
+            log::error!("\t{mismatch}");
+        }
+        Err(format_err!(
+            "{} does not match the lock, found {} mismatches",
+            vendor.display(),
+            mismatches.len()
+        ))
+    }
+
     pub fn clear_cache(&self) -> Result<()> {
-        Cache::new(&self.preset).clear()
+        self.cache().clear()
+    }
+
+    pub fn prune_cache(&self) -> Result<()> {
+        let spec = Spec::load_from(&self.preset)?;
+        let cache = self.cache();
+        let _cache_lock = cache.lock()?;
+
+        log::info!("pruning cache at {}", cache.root().display());
+        for path in cache.prune(&spec.resolved_deps())? {
+            log::info!("\tremoved {}", path.display());
+        }
+        Ok(())
+    }
+
+    /// Removes the entries of the lock whose dependency is no longer in the
+    /// spec, installs and updates keep them otherwise.
+    pub fn prune_lock(&self) -> Result<()> {
+        let spec = Spec::load_from(&self.preset)?;
+        let mut spec_lock = SpecLock::load_from(&self.preset)?;
+
+        for locked in spec_lock.prune(&spec.resolved_deps()) {
+            log::info!("\tremoved {}@{}", locked.url, locked.refname);
+        }
+        spec_lock.save()
+    }
+
+    /// Compacts the cached repositories, skipping the ones in use.
+    pub fn gc_cache(&self) -> Result<()> {
+        let cache = self.cache();
+
+        log::info!("compacting cache at {}", cache.root().display());
+        for path in cache.gc()? {
+            log::info!("\tcompacted {}", path.display());
+        }
+        Ok(())
+    }
+
+    /// Returns the cache of the `VENDOR_CACHE` environment variable, then
+    /// the one of the global config, or the one of the preset.
+    fn cache(&self) -> Cache {
+        self.cache_with(|name| env::var_os(name))
+    }
+
+    fn cache_with(&self, lookup: impl Fn(&str) -> Option<OsString>) -> Cache {
+        match (
+            lookup(CACHE_ENV).filter(|root| !root.is_empty()),
+            &self.config.cache,
+        ) {
+            (Some(root), _) => Cache::from_root(root),
+            (None, Some(root)) => Cache::from_root(root),
+            (None, None) => Cache::new(&self.preset),
+        }
+    }
+
+    fn save_metrics(&self, metrics: &Metrics) -> Result<()> {
+        if let Some(path) = &self.metrics {
+            fs::write(path, metrics.to_prometheus())
+                .map_err(|err| format_err!("cannot save metrics to {}: {err}", path.display()))?;
+        }
+        Ok(())
     }
 
     fn load_both(&self) -> Result<(Spec, SpecLock)> {
@@ -136,3 +447,208 @@ impl Controller {
         Ok((spec, spec_lock))
     }
 }
+
+fn check_failures(metrics: &Metrics) -> Result<()> {
+    if metrics.failures > 0 {
+        return Err(format_err!(
+            "{} of {} dependencies failed",
+            metrics.failures,
+            metrics.dependencies
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::deps::LockedDependency;
+    use crate::test_utils::git_commit;
+    use crate::test_utils::git_init;
+    use crate::test_utils::read_to_string;
+    use crate::test_utils::tempdir;
+    use crate::test_utils::write_to;
+    use crate::test_utils::TestContext;
+    use crate::yaml;
+
+    #[test]
+    fn test_controller_init_creates_spec() -> Result<()> {
+        let ctx = TestContext::new();
+        let sut = Controller::new(ctx.preset.clone());
+
+        sut.init(Some("some-url"), false)?;
+
+        let spec = Spec::load_from(&ctx.preset)?;
+        assert_eq!(1, spec.deps.len());
+        assert_eq!("some-url", spec.deps[0].url);
+        assert_eq!("master", spec.deps[0].refname);
+        Ok(())
+    }
+
+    #[test]
+    fn test_controller_add() -> Result<()> {
+        let ctx = TestContext::new();
+        let sut = Controller::new(ctx.preset.clone());
+        sut.init(None, false)?;
+
+        sut.add("https://host/some-url", "v1", None, None, None)?;
+
+        let spec = Spec::load_from(&ctx.preset)?;
+        let mut expected = Dependency::new("https://host/some-url", "v1");
+        expected.apply_preset(&ctx.preset);
+        assert_eq!(vec![expected], spec.deps);
+        Ok(())
+    }
+
+    #[test]
+    fn test_controller_add_rejects_duplicates() -> Result<()> {
+        let ctx = TestContext::new();
+        let sut = Controller::new(ctx.preset.clone());
+        sut.init(Some("https://host/some-url"), false)?;
+
+        let actual = sut.add("HTTPS://HOST/Some-Url", "v2", None, None, None);
+
+        assert_eq!(
+            "dependency HTTPS://HOST/Some-Url is already in the spec",
+            actual.unwrap_err().to_string()
+        );
+        let spec = Spec::load_from(&ctx.preset)?;
+        assert_eq!(1, spec.deps.len());
+        assert_eq!("master", spec.deps[0].refname);
+        Ok(())
+    }
+
+    #[test]
+    fn test_controller_init_refuses_to_overwrite() {
+        let ctx = TestContext::new();
+        write_to(ctx.preset.spec(), "existing");
+        let sut = Controller::new(ctx.preset.clone());
+
+        let actual = sut.init(None, false).unwrap_err().to_string();
+
+        assert!(actual.ends_with("already exists, use --force to overwrite it"));
+        assert_eq!("existing", read_to_string(ctx.preset.spec()));
+    }
+
+    #[test]
+    fn test_controller_init_force_overwrites() -> Result<()> {
+        let ctx = TestContext::new();
+        write_to(ctx.preset.spec(), "existing");
+        let sut = Controller::new(ctx.preset.clone());
+
+        sut.init(None, true)?;
+
+        assert!(Spec::load_from(&ctx.preset)?.deps.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_controller_cache_prefers_the_environment() {
+        let ctx = TestContext::new();
+        let sut = Controller::new(ctx.preset.clone()).with_config(Config {
+            cache: Some("/config/cache".into()),
+            ..Config::default()
+        });
+
+        let from_env = sut.cache_with(|name| (name == CACHE_ENV).then(|| "/env/cache".into()));
+        assert_eq!(Path::new("/env/cache"), from_env.root());
+        let from_config = sut.cache_with(|_| None);
+        assert_eq!(Path::new("/config/cache"), from_config.root());
+        let from_preset = Controller::new(ctx.preset.clone()).cache_with(|_| None);
+        assert_eq!(Path::new(ctx.preset.cache()), from_preset.root());
+    }
+
+    #[test]
+    fn test_controller_frozen_install_rejects_outdated_lock() -> Result<()> {
+        let ctx = TestContext::new();
+        let upstream = tempdir();
+        let git = git_init(upstream.path(), &[("global/target/a/file.txt", "one")]);
+        let sut = Controller::new(ctx.preset.clone());
+        sut.init(Some(&upstream.path().to_string_lossy()), false)?;
+        sut.update()?;
+        git_commit(&git, &[("global/target/a/file.txt", "two")], "two");
+        let mut spec_lock = SpecLock::load_from(&ctx.preset)?;
+        spec_lock.deps[0].tree = None;
+        spec_lock.deps[0].committed_at = None;
+        yaml::save(&spec_lock, ctx.preset.spec_lock())?;
+        let locked = read_to_string(&ctx.preset.spec_lock());
+        let frozen = Controller::new(ctx.preset.clone()).with_frozen(true);
+        frozen.install()?;
+
+        let vendored = Path::new(ctx.preset.vendor()).join("global/target/a/file.txt");
+        assert_eq!("one", read_to_string(&vendored));
+        assert_eq!(locked, read_to_string(&ctx.preset.spec_lock()));
+
+        let mut spec = Spec::load_from(&ctx.preset)?;
+        spec.locking.lock_checksums = true;
+        spec.save()?;
+        write_to(&vendored, "edited");
+        let actual = frozen.install().unwrap_err().to_string();
+        assert!(
+            actual.ends_with("needs to be updated, but it is frozen"),
+            "{actual}"
+        );
+        assert_eq!("edited", read_to_string(&vendored));
+        write_to(&vendored, "one");
+
+        spec.locking.lock_checksums = false;
+        spec.deps[0].refname = "default".into();
+        spec.save()?;
+        let actual = frozen.install().unwrap_err().to_string();
+
+        assert!(
+            actual.contains("asks for default, but master is locked"),
+            "{actual}"
+        );
+        assert!(
+            actual.ends_with("needs to be updated, but it is frozen"),
+            "{actual}"
+        );
+        assert_eq!(locked, read_to_string(&ctx.preset.spec_lock()));
+        assert_eq!("one", read_to_string(&vendored));
+
+        sut.install()?;
+        let spec_lock = SpecLock::load_from(&ctx.preset)?;
+        assert_eq!(Some("default"), spec_lock.deps[0].requested.as_deref());
+        Ok(())
+    }
+
+    #[test]
+    fn test_controller_noop_update_keeps_lock_file() -> Result<()> {
+        let ctx = TestContext::new();
+        let upstream = tempdir();
+        git_init(upstream.path(), &[("global/target/a/file.txt", "data")]);
+        let sut = Controller::new(ctx.preset.clone());
+        sut.init(Some(&upstream.path().to_string_lossy()), false)?;
+        sut.update()?;
+        let locked = format!("# untouched\n{}", read_to_string(&ctx.preset.spec_lock()));
+        write_to(ctx.preset.spec_lock(), &locked);
+
+        sut.update()?;
+
+        assert_eq!(locked, read_to_string(&ctx.preset.spec_lock()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_controller_prune_lock_removes_orphaned_entries() -> Result<()> {
+        let ctx = TestContext::new();
+        let upstream = tempdir();
+        git_init(upstream.path(), &[("global/target/a/file.txt", "data")]);
+        let sut = Controller::new(ctx.preset.clone());
+        sut.init(Some(&upstream.path().to_string_lossy()), false)?;
+        sut.update()?;
+        let mut spec_lock = SpecLock::load_from(&ctx.preset)?;
+        let kept = spec_lock.deps[0].clone();
+        spec_lock.add_locked_dependency(LockedDependency::new("https://host/orphan", "v1"));
+        spec_lock.save()?;
+
+        sut.install()?;
+        assert_eq!(2, SpecLock::load_from(&ctx.preset)?.deps.len());
+
+        sut.prune_lock()?;
+        assert_eq!(vec![kept], SpecLock::load_from(&ctx.preset)?.deps);
+        Ok(())
+    }
+}
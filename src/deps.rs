@@ -5,12 +5,64 @@ use serde::Serialize;
 
 use crate::filters::Filters;
 use crate::preset::Preset;
+use crate::url;
 
 #[derive(Debug, Eq, PartialEq, Serialize, Deserialize, Clone)]
 pub struct Dependency {
+    /// Url of the git repository. Local paths and `file://` urls, to both
+    /// bare and non-bare repositories, are cloned without network access, as
+    /// are `bundle://` urls and paths to `.bundle` files. Bundles require the
+    /// git command line tool.
+    ///
+    /// Urls to release archives, ending in `.tar.gz`, `.tgz`, `.tar` or
+    /// `.zip`, or with the `tar+` prefix such as `tar+https://`, are
+    /// downloaded and extracted instead, and the refname names the release.
+    /// They need the external `curl` tool for http(s) urls, and `tar` or
+    /// `unzip` to extract them.
+    ///
+    /// The url and the refname may reference environment variables, as
+    /// `${VAR}` or `${VAR:-default}`. The refname is interpolated when the
+    /// spec is loaded, the url only when the repository is cloned, so the
+    /// lock, the manifest and the logs keep it as written.
     pub url: String,
     pub refname: String,
 
+    /// Name that tells apart dependencies on the same url, so a repository
+    /// can be vendored more than once, for instance at different refnames.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
+    /// Folder, relative to the vendor folder, where the files of the
+    /// dependency are vendored. By default, they are vendored at the root.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vendor_dir: Option<String>,
+
+    /// Name of the preset to apply to the dependency, on top of the preset
+    /// of the spec.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preset: Option<String>,
+
+    /// Condition on an environment variable, the dependency is skipped when
+    /// it is not met. `NAME` requires the variable to be set, and
+    /// `NAME=value` requires it to be set to exactly `value`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub when_env: Option<String>,
+
+    /// Whether the submodules of the dependency are vendored, overriding the
+    /// `submodules` setting of the spec.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub submodules: Option<bool>,
+
+    /// Urls of mirrors of the repository, tried in order when the url cannot
+    /// be cloned or fetched from. The lock is still keyed by the url.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mirrors: Vec<String>,
+
+    /// Expected sha256 checksum of the release archive that the url points
+    /// to, the dependency fails when the archive does not match it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+
     #[serde(flatten)]
     pub filters: Filters,
 }
@@ -19,6 +71,43 @@ pub struct Dependency {
 pub struct LockedDependency {
     pub url: String,
     pub refname: String,
+
+    /// Name of the dependency, see [`Dependency::name`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
+    /// Refname of the spec that the commit was resolved from, the lock is
+    /// outdated once the spec asks for another refname.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requested: Option<String>,
+
+    /// Tag that the semver range of the requested refname resolved to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resolved: Option<String>,
+
+    /// Mirror that the dependency was vendored from, when its url failed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mirror: Option<String>,
+
+    /// Id of the git tree that was vendored, it allows to verify that the
+    /// vendored contents have not changed even if the history was rewritten.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tree: Option<String>,
+
+    /// Time at which the locked commit was committed, in seconds since the
+    /// unix epoch, it tells how old the vendored contents are.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub committed_at: Option<i64>,
+
+    /// Sha256 checksum of the release archive that was vendored, when the
+    /// url points to one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+
+    /// Files that could not be copied, the dependency is only partially
+    /// vendored when there are any.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub failed_files: Vec<String>,
 }
 
 impl Dependency {
@@ -28,17 +117,68 @@ impl Dependency {
         Self {
             url: url.into(),
             refname: refname.into(),
+            name: None,
+            vendor_dir: None,
+            preset: None,
+            when_env: None,
+            submodules: None,
+            mirrors: vec![],
+            sha256: None,
             filters: Filters::new(),
         }
     }
 
+    /// Returns a short name that identifies the dependency, its name when it
+    /// has one, or the last path segment of its url, without the `.git`
+    /// suffix.
+    #[must_use]
+    pub fn name(&self) -> &str {
+        if let Some(name) = &self.name {
+            return name;
+        }
+        let url = self.url.trim_end_matches('/');
+        let name = url.rsplit(['/', ':']).next().unwrap_or(url);
+        name.strip_suffix(".git").unwrap_or(name)
+    }
+
+    /// Returns whether the `when_env` condition is met by the environment
+    /// variables that `lookup` finds. Dependencies without condition are
+    /// always active.
+    pub fn is_active(&self, lookup: impl Fn(&str) -> Option<String>) -> bool {
+        let Some(condition) = &self.when_env else {
+            return true;
+        };
+        match condition.split_once('=') {
+            Some((name, expected)) => lookup(name).is_some_and(|value| value == expected),
+            None => lookup(condition).is_some(),
+        }
+    }
+
+    /// Returns whether `other` is the same dependency, both have the same url
+    /// and the same name.
+    #[must_use]
+    pub fn is_same(&self, other: &Dependency) -> bool {
+        is_same_key(
+            &self.url,
+            self.name.as_deref(),
+            &other.url,
+            other.name.as_deref(),
+        )
+    }
+
     pub fn to_locked_dependency(&self, refname: impl Into<String>) -> LockedDependency {
-        LockedDependency::new(&self.url, refname)
+        let mut locked = LockedDependency::new(&self.url, refname);
+        locked.name.clone_from(&self.name);
+        locked
     }
 
     /// Updates the values, taken from another dependency.
     pub fn update_from(&mut self, other: &Dependency) -> &Self {
-        self.refname = other.refname.clone();
+        self.refname.clone_from(&other.refname);
+        self.vendor_dir.clone_from(&other.vendor_dir);
+        self.preset.clone_from(&other.preset);
+        self.when_env.clone_from(&other.when_env);
+        self.sha256.clone_from(&other.sha256);
         self.filters = other.filters.clone();
         self
     }
@@ -50,6 +190,17 @@ impl Dependency {
         self.filters.merge(&preset.dependency_filters(self));
         self
     }
+
+    /// Applies the presets in order, merging their filters. A preset that
+    /// forces its filters clears everything contributed before it, including
+    /// the filters of the dependency itself, so the last forcing preset wins
+    /// and only the presets that follow it are merged on top.
+    pub fn apply_presets(&mut self, presets: &[Preset]) -> &Self {
+        for preset in presets {
+            self.apply_preset(preset);
+        }
+        self
+    }
 }
 
 impl LockedDependency {
@@ -57,8 +208,48 @@ impl LockedDependency {
         Self {
             url: url.into(),
             refname: refname.into(),
+            name: None,
+            requested: None,
+            resolved: None,
+            mirror: None,
+            tree: None,
+            committed_at: None,
+            sha256: None,
+            failed_files: vec![],
         }
     }
+
+    /// Returns whether this is the lock of `dep`, see [`Dependency::is_same`].
+    pub fn is_lock_of(&self, dep: &Dependency) -> bool {
+        is_same_key(
+            &self.url,
+            self.name.as_deref(),
+            &dep.url,
+            dep.name.as_deref(),
+        )
+    }
+
+    /// Returns whether the spec now asks `dep` for another refname than the
+    /// one this was locked from, so the lock no longer applies.
+    pub fn is_outdated(&self, dep: &Dependency) -> bool {
+        self.requested
+            .as_ref()
+            .is_some_and(|requested| *requested != dep.refname)
+    }
+
+    /// Returns whether `other` locks the same dependency.
+    pub fn is_same(&self, other: &LockedDependency) -> bool {
+        is_same_key(
+            &self.url,
+            self.name.as_deref(),
+            &other.url,
+            other.name.as_deref(),
+        )
+    }
+}
+
+fn is_same_key(url: &str, name: Option<&str>, other_url: &str, other_name: Option<&str>) -> bool {
+    url::is_same(url, other_url) && name == other_name
 }
 
 #[cfg(test)]
@@ -103,6 +294,55 @@ mod tests {
         assert_eq!(preset.dependency_filters(sut), sut.filters);
     }
 
+    fn preset_with_extension(extension: &str, force: bool) -> Preset {
+        let provider = match extension {
+            "proto" => |_: &Dependency| get_ext_filters("proto"),
+            "md" => |_: &Dependency| get_ext_filters("md"),
+            _ => |_: &Dependency| get_ext_filters("yaml"),
+        };
+        preset_builder()
+            .dependency_filters(provider)
+            .force_filters(force)
+            .build()
+    }
+
+    fn get_ext_filters(extension: &str) -> Filters {
+        let mut filters = Filters::new();
+        filters.add(FilterKind::Extension(svec![extension]));
+        filters
+    }
+
+    #[test]
+    fn test_dependency_apply_presets_merges_in_order() {
+        let protobuf = preset_with_extension("proto", false);
+        let docs = preset_with_extension("md", false);
+        let sut = &mut Dependency::new("some-url", "some-refname");
+        sut.filters = get_dep_filters();
+
+        sut.apply_presets(&[protobuf, docs]);
+
+        assert_eq!(svec!["some-target"], sut.filters.targets);
+        assert_eq!(svec!["md", "proto", "some-ext"], sut.filters.extensions);
+    }
+
+    #[test]
+    fn test_dependency_apply_presets_last_forcing_preset_wins() {
+        let protobuf = preset_with_extension("proto", true);
+        let docs = preset_with_extension("md", true);
+        let openapi = preset_with_extension("yaml", false);
+        let sut = &mut Dependency::new("some-url", "some-refname");
+        sut.filters = get_dep_filters();
+
+        sut.apply_presets(&[protobuf.clone(), docs.clone(), openapi]);
+        assert_eq!(
+            get_ext_filters("md").merge(&get_ext_filters("yaml")),
+            &sut.filters
+        );
+
+        sut.apply_presets(&[docs, protobuf]);
+        assert_eq!(get_ext_filters("proto"), sut.filters);
+    }
+
     #[test]
     fn test_dependency_to_locked_dependency() {
         let sut = Dependency::new("some-url", "some-refname");
@@ -112,6 +352,19 @@ mod tests {
         assert_eq!("other-refname", locked.refname);
     }
 
+    #[test]
+    fn test_dependency_name() {
+        let name = |url: &str| Dependency::new(url, "master").name().to_string();
+
+        assert_eq!("ledger", name("https://github.com/alevinval/ledger"));
+        assert_eq!("ledger", name("https://github.com/alevinval/ledger.git"));
+        assert_eq!("ledger", name("https://github.com/alevinval/ledger/"));
+        assert_eq!("ledger", name("git@github.com:alevinval/ledger.git"));
+        assert_eq!("ledger", name("git@github.com:ledger"));
+        assert_eq!("ledger", name("/some/local/ledger"));
+        assert_eq!("ledger", name("ledger"));
+    }
+
     #[test]
     fn test_dependency_update_from() {
         let mut original = Dependency::new("url-a", "refname-a");
@@ -134,4 +387,22 @@ mod tests {
         assert_eq!("refname-b", actual.refname);
         assert_eq!(actual.filters, other.filters);
     }
+
+    #[test]
+    fn test_dependency_is_active() {
+        let lookup = |name: &str| (name == "EXTRAS").then(|| "yes".to_string());
+        let with_condition = |condition: Option<&str>| {
+            let mut dep = Dependency::new("some-url", "some-refname");
+            dep.when_env = condition.map(Into::into);
+            dep.is_active(lookup)
+        };
+
+        assert!(with_condition(None));
+        assert!(with_condition(Some("EXTRAS")));
+        assert!(with_condition(Some("EXTRAS=yes")));
+
+        assert!(!with_condition(Some("MISSING")));
+        assert!(!with_condition(Some("EXTRAS=no")));
+        assert!(!with_condition(Some("MISSING=yes")));
+    }
 }
@@ -0,0 +1,157 @@
+use std::error::Error;
+use std::fmt;
+use std::fmt::Display;
+use std::io;
+
+use git2::ErrorClass;
+use git2::ErrorCode;
+
+/// Error returned by the public API. The variants classify the failure, so
+/// callers can branch on its kind, while the message keeps the details.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum VendorError {
+    /// A refname of a dependency cannot be found in its repository.
+    RefNotFound(String),
+
+    /// The remote rejected the credentials, or none could be found.
+    Auth(String),
+
+    /// Reading or writing files failed, for instance when the disk is full.
+    Io(String),
+
+    /// Any other failure.
+    Other(String),
+}
+
+impl VendorError {
+    /// Returns the message that describes the failure.
+    #[must_use]
+    pub fn message(&self) -> &str {
+        match self {
+            Self::RefNotFound(message)
+            | Self::Auth(message)
+            | Self::Io(message)
+            | Self::Other(message) => message,
+        }
+    }
+
+    /// Returns an error of the same kind with another message.
+    fn with_message(&self, message: String) -> Self {
+        match self {
+            Self::RefNotFound(_) => Self::RefNotFound(message),
+            Self::Auth(_) => Self::Auth(message),
+            Self::Io(_) => Self::Io(message),
+            Self::Other(_) => Self::Other(message),
+        }
+    }
+}
+
+impl Display for VendorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+impl Error for VendorError {}
+
+/// Classifies an internal error by the first cause of a known kind, the
+/// message is the one of the outermost error.
+impl From<anyhow::Error> for VendorError {
+    fn from(err: anyhow::Error) -> Self {
+        let message = err.to_string();
+        classify(&err, message)
+    }
+}
+
+/// Returns the error of the kind of the first known cause of `err`, with
+/// the given message.
+fn classify(err: &anyhow::Error, message: String) -> VendorError {
+    for cause in err.chain() {
+        if let Some(found) = cause.downcast_ref::<VendorError>() {
+            return found.with_message(message);
+        }
+        if let Some(git) = cause.downcast_ref::<git2::Error>() {
+            match (git.code(), git.class()) {
+                (ErrorCode::Auth, _) => return VendorError::Auth(message),
+                (ErrorCode::NotFound, ErrorClass::Reference) => {
+                    return VendorError::RefNotFound(message)
+                }
+                (_, ErrorClass::Os) => return VendorError::Io(message),
+                _ => {}
+            }
+        }
+        if cause.is::<io::Error>() {
+            return VendorError::Io(message);
+        }
+    }
+    VendorError::Other(message)
+}
+
+/// Prefixes the message of the error, keeping the error as its source so the
+/// failure can still be classified, see [`VendorError`].
+pub(crate) fn prefixed(err: anyhow::Error, prefix: impl Display) -> anyhow::Error {
+    let message = format!("{prefix}: {err}");
+    err.context(message)
+}
+
+/// Error of a repository that cannot be cloned or fetched from its remote,
+/// the failures that a mirror may not have, see [`unreachable`].
+#[derive(Debug)]
+pub(crate) struct Unreachable(String);
+
+impl Display for Unreachable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Error for Unreachable {}
+
+/// Marks the error of a clone or fetch as [`Unreachable`], keeping its
+/// message and causes. Missing refnames are left unmarked, as every mirror
+/// would miss them as well.
+pub(crate) fn unreachable(err: anyhow::Error) -> anyhow::Error {
+    if matches!(classify(&err, String::new()), VendorError::RefNotFound(_)) {
+        return err;
+    }
+    let message = err.to_string();
+    err.context(Unreachable(message))
+}
+
+/// Returns whether the error, or any of the errors it was given as context
+/// to, is [`Unreachable`].
+pub(crate) fn is_unreachable(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<Unreachable>().is_some()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use anyhow::format_err;
+
+    use super::*;
+
+    #[test]
+    fn test_vendor_error_classifies_the_cause() {
+        let auth = git2::Error::new(ErrorCode::Auth, ErrorClass::Http, "unauthorized");
+        let err = prefixed(prefixed(auth.into(), "cannot clone"), "some-url");
+
+        let actual = VendorError::from(err);
+
+        assert!(matches!(actual, VendorError::Auth(_)), "{actual:?}");
+        assert!(
+            actual
+                .to_string()
+                .starts_with("some-url: cannot clone: unauthorized"),
+            "{actual}"
+        );
+
+        let disk_full = io::Error::other("no space left on device");
+        let actual = VendorError::from(prefixed(disk_full.into(), "cannot copy"));
+        assert!(matches!(actual, VendorError::Io(_)), "{actual:?}");
+
+        let actual = VendorError::from(format_err!("unexpected"));
+        assert!(matches!(actual, VendorError::Other(_)), "{actual:?}");
+    }
+}
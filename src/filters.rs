@@ -14,20 +14,60 @@ pub struct Filters {
     #[serde(default = "Vec::new")]
     #[serde(skip_serializing_if = "<[_]>::is_empty")]
     pub extensions: Vec<String>,
+
+    /// Template of the destination path of the vendored files, with the
+    /// `{name}`, `{ref}` and `{path}` placeholders, files keep their path
+    /// when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rename_template: Option<String>,
+
+    /// Skip the files that look binary, those with a NUL byte within their
+    /// first 8000 bytes, whatever their extension.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub exclude_binary: bool,
+
+    /// Vendor the hidden files and directories, those whose name starts with
+    /// a dot, even when they are not explicitly targeted.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub include_hidden: bool,
 }
 
+#[derive(Debug, Clone, Eq, PartialEq)]
 pub enum FilterKind {
     Target(Vec<String>),
     Ignore(Vec<String>),
     Extension(Vec<String>),
 }
 
+impl FilterKind {
+    /// Returns the name of the kind, as it is shown in messages.
+    pub fn name(&self) -> &'static str {
+        match self {
+            FilterKind::Target(_) => "target",
+            FilterKind::Ignore(_) => "ignore",
+            FilterKind::Extension(_) => "extension",
+        }
+    }
+
+    /// Returns the patterns of the kind.
+    pub fn patterns(&self) -> &[String] {
+        match self {
+            FilterKind::Target(patterns)
+            | FilterKind::Ignore(patterns)
+            | FilterKind::Extension(patterns) => patterns,
+        }
+    }
+}
+
 impl Filters {
     pub fn new() -> Self {
         Self {
             targets: vec![],
             ignores: vec![],
             extensions: vec![],
+            rename_template: None,
+            exclude_binary: false,
+            include_hidden: false,
         }
     }
 
@@ -36,11 +76,20 @@ impl Filters {
             FilterKind::Target(target) => Self::extend(&mut self.targets, &target),
             FilterKind::Ignore(ignore) => Self::extend(&mut self.ignores, &ignore),
             FilterKind::Extension(extension) => Self::extend(&mut self.extensions, &extension),
-        };
+        }
         self
     }
 
+    /// Extends the filters with the ones of `other`, whose rename template
+    /// takes precedence when set. Binary files are excluded when either
+    /// excludes them, and hidden files are included when either includes
+    /// them.
     pub fn merge(&mut self, other: &Filters) -> &mut Self {
+        if other.rename_template.is_some() {
+            self.rename_template.clone_from(&other.rename_template);
+        }
+        self.exclude_binary |= other.exclude_binary;
+        self.include_hidden |= other.include_hidden;
         self.add(FilterKind::Target(other.targets.clone()))
             .add(FilterKind::Ignore(other.ignores.clone()))
             .add(FilterKind::Extension(other.extensions.clone()))
@@ -50,9 +99,27 @@ impl Filters {
         self.targets.clear();
         self.ignores.clear();
         self.extensions.clear();
+        self.rename_template = None;
+        self.exclude_binary = false;
+        self.include_hidden = false;
+        self
+    }
+
+    /// Sorts and dedups the targets, ignores and extensions, as they are when
+    /// added, so filters loaded from a file are saved in a stable order.
+    pub fn lint(&mut self) -> &mut Self {
+        for elems in [&mut self.targets, &mut self.ignores, &mut self.extensions] {
+            elems.sort();
+            elems.dedup();
+        }
         self
     }
 
+    /// Returns whether there are no targets, ignores nor extensions.
+    pub fn is_empty(&self) -> bool {
+        self.targets.is_empty() && self.ignores.is_empty() && self.extensions.is_empty()
+    }
+
     fn extend(target: &mut Vec<String>, elems: &[String]) {
         target.extend(elems.to_vec());
         target.sort();
@@ -163,4 +230,75 @@ mod tests {
         assert_eq!(get_expected(input), sut.ignores);
         assert_eq!(get_expected(input), sut.extensions,);
     }
+
+    #[test]
+    fn test_filters_merge_rename_template() {
+        let sut = &mut Filters::new();
+        sut.rename_template = Some("{name}/{path}".into());
+
+        sut.merge(&Filters::new());
+        assert_eq!(Some("{name}/{path}"), sut.rename_template.as_deref());
+
+        let other = &mut Filters::new();
+        other.rename_template = Some("{ref}/{path}".into());
+        sut.merge(other);
+        assert_eq!(Some("{ref}/{path}"), sut.rename_template.as_deref());
+    }
+
+    #[test]
+    fn test_filters_merge_exclude_binary() {
+        let sut = &mut Filters::new();
+
+        sut.merge(&Filters::new());
+        assert!(!sut.exclude_binary);
+
+        let other = &mut Filters::new();
+        other.exclude_binary = true;
+        sut.merge(other);
+        assert!(sut.exclude_binary);
+
+        sut.merge(&Filters::new());
+        assert!(sut.exclude_binary);
+        assert!(!sut.clear().exclude_binary);
+    }
+
+    #[test]
+    fn test_filters_merge_include_hidden() {
+        let sut = &mut Filters::new();
+
+        let other = &mut Filters::new();
+        other.include_hidden = true;
+        sut.merge(other);
+        assert!(sut.include_hidden);
+
+        sut.merge(&Filters::new());
+        assert!(sut.include_hidden);
+        assert!(!sut.clear().include_hidden);
+    }
+
+    #[test]
+    fn test_filters_lint() {
+        let sut = &mut Filters::new();
+        sut.targets = get_input();
+        sut.ignores = get_input();
+        sut.extensions = svec!["proto", "md", "proto"];
+
+        sut.lint();
+
+        assert_eq!(get_expected(&get_input()), sut.targets);
+        assert_eq!(get_expected(&get_input()), sut.ignores);
+        assert_eq!(svec!["md", "proto"], sut.extensions);
+    }
+
+    #[test]
+    fn test_filters_is_empty() {
+        let sut = &mut Filters::new();
+        assert!(sut.is_empty());
+
+        sut.add(FilterKind::Ignore(svec!["a"]));
+        assert!(!sut.is_empty());
+
+        sut.clear();
+        assert!(sut.is_empty());
+    }
 }
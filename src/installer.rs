@@ -1,25 +1,102 @@
+use std::collections::BTreeMap;
+use std::env;
 use std::fs;
+use std::mem;
+use std::panic;
+use std::panic::AssertUnwindSafe;
+use std::path::Component;
 use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::Arc;
 use std::thread;
+use std::thread::ScopedJoinHandle;
+use std::time::Duration;
+use std::time::Instant;
 
 use anyhow::format_err;
 use anyhow::Result;
+use serde::Serialize;
 
+use self::budget::Budget;
+pub use self::importer::FileFilter;
+use self::importer::Imported;
 use self::importer::Importer;
+pub use self::selector::CopyDecision;
+use self::selector::Selector;
+use crate::archive;
 use crate::cache::Cache;
+use crate::checksum;
+use crate::checksum::Changes;
+use crate::checksum::Checksums;
+use crate::checksum::Mismatch;
 use crate::deps::Dependency;
 use crate::deps::LockedDependency;
+use crate::error::is_unreachable;
+use crate::error::prefixed;
+use crate::error::unreachable;
+use crate::filters::FilterKind;
+use crate::interpolate::interpolate;
+use crate::lock::Lock;
+use crate::metrics::DependencyMetrics;
+use crate::metrics::Metrics;
+use crate::repository::Git;
+use crate::repository::RemoteRefs;
+use crate::repository::Repository;
+use crate::spec::Collisions;
+use crate::spec::Severity;
 use crate::spec::Spec;
+use crate::spec::VendorGit;
 use crate::spec_lock::SpecLock;
+use crate::timeout;
+use crate::url;
+use crate::yaml;
 
+mod budget;
 mod collector;
 mod importer;
+mod rename;
 mod selector;
+mod transform;
+
+/// A dependency whose refname points to a different commit than the locked
+/// one.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Outdated {
+    pub url: String,
+    pub locked: Option<String>,
+    pub latest: String,
+}
+
+/// Transforms or validates the lock once it has been built, before it is
+/// returned to be saved. An error fails the run.
+pub type LockHook = dyn Fn(&mut SpecLock) -> Result<()> + Send + Sync;
+
+/// Dependencies that failed, along with their error.
+pub type Failures = Vec<(Dependency, anyhow::Error)>;
+
+/// How the dependencies are fetched and the lock is checked, as set with the
+/// builder methods of the [`Installer`].
+#[derive(Default, Clone, Copy)]
+struct Mode {
+    offline: bool,
+    force: bool,
+    frozen: bool,
+}
 
 pub struct Installer<'spec> {
     cache: Cache,
     spec: &'spec Spec,
+    deps: Vec<Dependency>,
     spec_lock: SpecLock,
+    mode: Mode,
+    locally_modified: bool,
+    remote_refs: Box<dyn RemoteRefs>,
+    file_filter: Option<Box<FileFilter>>,
+    lock_hook: Option<Box<LockHook>>,
+    budget: Budget,
+    vendor: Option<PathBuf>,
+    jobs: Option<usize>,
 }
 
 impl<'spec> Installer<'spec> {
@@ -27,72 +104,1063 @@ impl<'spec> Installer<'spec> {
         Self {
             cache,
             spec,
+            deps: active_deps(spec.resolved_deps(), |name| env::var(name).ok()),
             spec_lock,
+            mode: Mode::default(),
+            locally_modified: false,
+            remote_refs: Box::new(Git),
+            file_filter: None,
+            lock_hook: None,
+            budget: Budget::new(spec.max_files, spec.max_total_bytes),
+            vendor: None,
+            jobs: None,
+        }
+    }
+
+    /// Removes the cached repository of each dependency before using it, so
+    /// it is cloned again from scratch. Cannot be combined with offline.
+    pub fn with_force(mut self, force: bool) -> Self {
+        self.mode.force = force;
+        self
+    }
+
+    /// Fails, before the vendor folder is replaced, when the install would
+    /// modify the lock, see [`SpecLock::check_unchanged`].
+    pub fn with_frozen(mut self, frozen: bool) -> Self {
+        self.mode.frozen = frozen;
+        self
+    }
+
+    /// Limits how many dependencies are processed at the same time, all of
+    /// them by default. With a single job no threads are spawned, the
+    /// dependencies are processed one after the other in spec order.
+    pub fn with_jobs(mut self, jobs: Option<usize>) -> Self {
+        self.jobs = jobs;
+        self
+    }
+
+    /// Vendors the dependencies into `vendor`, instead of the vendor folder
+    /// configured in the spec, which is left untouched.
+    pub fn with_vendor(mut self, vendor: Option<PathBuf>) -> Self {
+        self.vendor = vendor;
+        self
+    }
+
+    /// Refuses any network access, dependencies are installed from the
+    /// repositories that are already in the cache.
+    pub fn with_offline(mut self, offline: bool) -> Self {
+        self.mode.offline = offline;
+        self
+    }
+
+    /// Filters the files of every dependency with `file_filter`, which runs
+    /// last, once a file passes the filters of the spec and is not ignored.
+    pub fn with_file_filter(mut self, file_filter: Option<Box<FileFilter>>) -> Self {
+        self.file_filter = file_filter;
+        self
+    }
+
+    /// Lists the references of the remotes with `remote_refs` when checking
+    /// for updates, instead of talking the git protocol.
+    pub fn with_remote_refs(mut self, remote_refs: Option<Box<dyn RemoteRefs>>) -> Self {
+        if let Some(remote_refs) = remote_refs {
+            self.remote_refs = remote_refs;
+        }
+        self
+    }
+
+    /// Calls `lock_hook` with the lock once all the dependencies have been
+    /// imported, before the vendor folder is replaced, so it can be modified
+    /// before it is saved. An error leaves the vendor folder untouched. The
+    /// checksums of the vendored files are recorded after it runs.
+    pub fn with_lock_hook(mut self, lock_hook: Option<Box<LockHook>>) -> Self {
+        self.lock_hook = lock_hook;
+        self
+    }
+
+    /// Resolves the refname of each dependency against its remote, returns
+    /// the dependencies whose locked commit is not the latest one.
+    pub fn outdated(&self) -> Result<Vec<Outdated>> {
+        if self.mode.offline {
+            return Err(format_err!("cannot check for updates in offline mode"));
+        }
+
+        let mut outdated = vec![];
+        for dep in &self.deps {
+            let latest = self
+                .remote_refs
+                .resolve(&interpolate(&dep.url)?, &dep.refname)?;
+            let locked = self
+                .spec_lock
+                .get_locked_dependency(dep)
+                .map(|locked| locked.refname.clone());
+            if locked.as_ref() != Some(&latest) {
+                outdated.push(Outdated {
+                    url: dep.url.clone(),
+                    locked,
+                    latest,
+                });
+            }
+        }
+        Ok(outdated)
+    }
+
+    /// Fails when installing would modify the lock, see
+    /// [`SpecLock::check_frozen`].
+    pub fn check_frozen(&self) -> Result<()> {
+        self.spec_lock.check_frozen(&self.deps)
+    }
+
+    pub fn install(self) -> Result<(SpecLock, Metrics)> {
+        if self.mode.offline && self.mode.force {
+            return Err(format_err!(
+                "cannot force the install in offline mode, the cache would be removed"
+            ));
         }
+        let (spec_lock, metrics, _) = self.execute(Self::inner_install)?;
+        Ok((spec_lock, metrics))
     }
 
-    pub fn install(self) -> Result<SpecLock> {
+    /// Installs like [`Installer::install`], also returning the error of
+    /// each dependency that failed.
+    pub fn install_reporting_failures(self) -> Result<(SpecLock, Metrics, Failures)> {
         self.execute(Self::inner_install)
     }
 
-    pub fn update(self) -> Result<SpecLock> {
-        self.execute(Self::inner_update)
+    /// Clones or fetches the repositories of the dependencies into the cache,
+    /// without touching the vendor folder. The dependencies can then be
+    /// installed with [`Installer::install_from_cache`].
+    pub fn warm_cache(&self) -> Result<()> {
+        if self.mode.offline {
+            return Err(format_err!("cannot warm the cache in offline mode"));
+        }
+        self.cache.initialize()?;
+
+        let results = self.run_all(|dep| self.inner_warm_cache(dep));
+
+        let failures = results
+            .into_iter()
+            .filter_map(Result::err)
+            .inspect(|err| log::error!("{err}"))
+            .count();
+        if failures > 0 {
+            return Err(format_err!(
+                "cannot warm the cache of {failures} of {} dependencies",
+                self.deps.len()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Installs the dependencies from the repositories that are already in
+    /// the cache, without reaching the network.
+    pub fn install_from_cache(self) -> Result<(SpecLock, Metrics)> {
+        self.with_offline(true).install()
+    }
+
+    pub fn update(self) -> Result<(SpecLock, Metrics)> {
+        if self.mode.offline {
+            return Err(format_err!("cannot update dependencies in offline mode"));
+        }
+        let (spec_lock, metrics, _) = self.execute(Self::inner_update)?;
+        Ok((spec_lock, metrics))
+    }
+
+    /// Updates a single dependency, looked up by its name, or by its url.
+    /// The other dependencies are installed as locked, so their files and
+    /// lock entries are left as they are.
+    pub fn update_one(self, url: &str) -> Result<(SpecLock, Metrics)> {
+        if self.mode.offline {
+            return Err(format_err!("cannot update dependencies in offline mode"));
+        }
+        let target = self.find_dependency(url)?.clone();
+        let (spec_lock, metrics, _) = self.execute(move |installer, dep| {
+            if dep.is_same(&target) {
+                installer.inner_update(dep)
+            } else {
+                installer.inner_install(dep)
+            }
+        })?;
+        Ok((spec_lock, metrics))
+    }
+
+    /// Installs the dependencies into a scratch folder and compares it with
+    /// the vendor folder, which is left untouched. Returns the files a fresh
+    /// install would add, remove and modify. The scratch folder is removed
+    /// whether the install succeeds or not.
+    pub fn diff(self) -> Result<Changes> {
+        let vendor = self.vendor_path().to_path_buf();
+        let scratch = sibling_path(&vendor, "diff");
+        let mut before = checksum::manifest(&vendor, true)?;
+        before.retain(|path, _| !Path::new(path).starts_with(".git"));
+
+        let installed = self
+            .with_vendor(Some(scratch.clone()))
+            .install()
+            .and_then(|(_, metrics)| Ok((metrics, checksum::manifest(&scratch, true)?)));
+        if scratch.exists() {
+            fs::remove_dir_all(&scratch)?;
+        }
+        let (metrics, after) = installed?;
+        if metrics.failures > 0 {
+            return Err(format_err!(
+                "cannot compute the diff, {} of {} dependencies failed",
+                metrics.failures,
+                metrics.dependencies
+            ));
+        }
+        Ok(Changes::between(&before, &after))
+    }
+
+    /// Exports the commits of a dependency between two refnames as numbered
+    /// patch files into `dir`. By default, the range goes from the locked
+    /// commit to the refname of the dependency in the spec. The dependency is
+    /// looked up by its name, or by its url.
+    pub fn export_patches(
+        &self,
+        url: &str,
+        from: Option<&str>,
+        to: Option<&str>,
+        dir: &Path,
+    ) -> Result<Vec<PathBuf>> {
+        let dependency = self.find_dependency(url)?;
+        let from = match from {
+            Some(from) => from,
+            None => self
+                .spec_lock
+                .get_locked_dependency(dependency)
+                .map(|locked| locked.refname.as_str())
+                .ok_or_else(|| {
+                    format_err!("{url} is not locked, the starting refname is required")
+                })?,
+        };
+        let to = to.unwrap_or(&dependency.refname);
+
+        self.cache.initialize()?;
+        let repository_lock = Arc::new(self.cache.lock_repository(dependency)?);
+        let (repository, _) = self.open_repository(dependency, false, &repository_lock)?;
+        Importer::new(self.spec, dependency, None, &repository)?
+            .with_repository_lock(&repository_lock)
+            .export_patches(from, to, dir, self.mode.offline)
+    }
+
+    /// Explains whether the file at `path`, relative to the root of the
+    /// dependency, is copied by the targets, ignores and extensions, see
+    /// [`Selector::explain`]. The filters are evaluated on the path alone,
+    /// the repository is neither cloned nor fetched.
+    pub fn explain(&self, url: &str, path: &Path) -> Result<CopyDecision> {
+        let dependency = self.find_dependency(url)?;
+        Ok(Selector::for_dependency(self.spec, dependency)?.explain(path))
     }
 
-    fn execute<F>(mut self, callback: F) -> Result<SpecLock>
+    /// Finds a dependency by its name, or by any spelling of its url, see
+    /// [`url::is_same`].
+    fn find_dependency(&self, url: &str) -> Result<&Dependency> {
+        let resolved = self.spec.resolve_url(url);
+        self.deps
+            .iter()
+            .find(|dep| dep.name.as_deref() == Some(url))
+            .or_else(|| {
+                self.deps
+                    .iter()
+                    .find(|dep| url::is_same(&dep.url, &resolved))
+            })
+            .ok_or_else(|| format_err!("cannot find dependency {url} in the spec"))
+    }
+
+    fn execute<F>(mut self, callback: F) -> Result<(SpecLock, Metrics, Failures)>
     where
-        F: (Fn(&Installer<'spec>, &Dependency) -> Result<LockedDependency>) + Sync + Send,
+        F: (Fn(&Installer<'spec>, &Dependency) -> Result<Imported>) + Sync + Send,
     {
+        let started = Instant::now();
         self.cache.initialize()?;
-        recreate_vendor_path(&self.spec.vendor)?;
-
-        let updated_locks: Vec<_> = thread::scope(|s| {
-            self.spec
-                .deps
-                .iter()
-                .map(|dep| s.spawn(|| callback(&self, dep)))
-                .filter_map(|handle| handle.join().ok())
-                .flatten()
+        self.locally_modified = self.check_local_modifications()?;
+        let before = checksum::manifest(self.vendor_path(), self.spec.locking.lock_checksums)?;
+        check_vendor_path(self.vendor_path(), self.spec.vendor_git)?;
+        recreate_vendor_path(self.staging_path(), VendorGit::Refuse)?;
+        recreate_vendor_path(self.parts_path(), VendorGit::Refuse)?;
+
+        let results = self.run_all(|dep| callback(&self, dep));
+
+        let mut metrics = Metrics {
+            dependencies: self.deps.len(),
+            ..Metrics::default()
+        };
+        let mut failures = vec![];
+        let mut unmatched = vec![];
+        for (dep, result) in self.deps.iter().zip(results) {
+            match result {
+                Ok(imported) => {
+                    metrics.files += imported.files;
+                    metrics.bytes += imported.bytes;
+                    metrics.per_dependency.push(DependencyMetrics {
+                        url: dep.url.clone(),
+                        name: dep.name.clone(),
+                        files: imported.files,
+                        bytes: imported.bytes,
+                    });
+                    unmatched.push(imported.unmatched);
+                    self.spec_lock.add_locked_dependency(imported.locked);
+                }
+                Err(err) => {
+                    log::error!("{err}");
+                    metrics.failures += 1;
+                    failures.push((dep.clone(), err));
+                }
+            }
+        }
+        self.warn_unmatched(&unmatched);
+        if let Some(lock_hook) = &self.lock_hook {
+            if let Err(err) = lock_hook(&mut self.spec_lock) {
+                fs::remove_dir_all(self.parts_path())?;
+                fs::remove_dir_all(self.staging_path())?;
+                return Err(prefixed(err, "lock hook failed"));
+            }
+        }
+        if metrics.failures == 0 {
+            let result = self
+                .merge_parts()
+                .and_then(|owners| self.write_manifest(&owners))
+                .and_then(|()| create_keep_dirs(&self.spec.keep_dirs, &self.staging_path()))
+                .and_then(|()| run_hooks(&self.spec.post_install, &self.staging_path()))
+                .and_then(|()| self.check_unchanged());
+            fs::remove_dir_all(self.parts_path())?;
+            if let Err(err) = result {
+                fs::remove_dir_all(self.staging_path())?;
+                return Err(err);
+            }
+            swap_vendor_path(&self.staging_path(), self.vendor_path())?;
+        } else {
+            log::warn!(
+                "leaving {} untouched, as some dependencies failed",
+                self.vendor_path().display()
+            );
+            fs::remove_dir_all(self.parts_path())?;
+            fs::remove_dir_all(self.staging_path())?;
+        }
+        let after = checksum::manifest(self.vendor_path(), self.spec.locking.lock_checksums)?;
+        metrics.changes = Changes::between(&before, &after);
+        if !metrics.changes.is_empty() {
+            log::info!(
+                "vendor changes: {} added, {} removed, {} modified",
+                metrics.changes.added.len(),
+                metrics.changes.removed.len(),
+                metrics.changes.modified.len()
+            );
+        }
+        self.spec_lock.checksums = if self.spec.locking.lock_checksums {
+            after
+        } else {
+            Checksums::new()
+        };
+        metrics.duration = started.elapsed();
+
+        Ok((self.spec_lock, metrics, failures))
+    }
+
+    /// Checks that a frozen install leaves the lock unchanged, with the
+    /// checksums of the staged vendor folder that is about to replace it.
+    fn check_unchanged(&mut self) -> Result<()> {
+        if !self.mode.frozen {
+            return Ok(());
+        }
+        self.spec_lock.checksums = if self.spec.locking.lock_checksums {
+            checksum::manifest(&self.staging_path(), true)?
+        } else {
+            Checksums::new()
+        };
+        self.spec_lock.check_unchanged()
+    }
+
+    /// Warns about the targets and ignores of the spec that match no file in
+    /// any of the imported dependencies.
+    fn warn_unmatched(&self, unmatched: &[Vec<FilterKind>]) {
+        if unmatched.is_empty() {
+            return;
+        }
+        let filters = &self.spec.filters;
+        let kinds = [
+            FilterKind::Target(filters.targets.clone()),
+            FilterKind::Ignore(filters.ignores.clone()),
+        ];
+        for kind in &kinds {
+            for pattern in kind.patterns() {
+                let is_unmatched = unmatched.iter().all(|found| {
+                    found.iter().any(|other| {
+                        mem::discriminant(other) == mem::discriminant(kind)
+                            && other.patterns().contains(pattern)
+                    })
+                });
+                if is_unmatched {
+                    log::warn!(
+                        "{} '{pattern}' of the spec matches no files in any dependency",
+                        kind.name()
+                    );
+                }
+            }
+        }
+    }
+
+    /// Reports the vendored files that were modified, or added, since the
+    /// checksums of the lock were recorded, as the install overwrites them.
+    /// Returns whether there are any.
+    fn check_local_modifications(&self) -> Result<bool> {
+        let vendor = self.vendor_path();
+        if self.spec_lock.checksums.is_empty() || !vendor.exists() {
+            return Ok(false);
+        }
+        let modified: Vec<_> =
+            checksum::verify(&self.spec_lock.checksums, vendor, checksum::default_jobs())?
+                .into_iter()
+                .filter(|mismatch| !matches!(mismatch, Mismatch::Missing(_)))
+                .filter(|mismatch| !Path::new(mismatch.path()).starts_with(".git"))
+                .collect();
+        if modified.is_empty() {
+            return Ok(false);
+        }
+        let message = format!(
+            "{} has {} local modifications, which are about to be overwritten",
+            vendor.display(),
+            modified.len()
+        );
+        match self.spec.local_modifications {
+            Severity::Warn => {
+                log::warn!("{message}");
+                for mismatch in &modified {
+                    log::warn!("\t{mismatch}");
+                }
+                Ok(true)
+            }
+            Severity::Error => {
+                for mismatch in &modified {
+                    log::error!("\t{mismatch}");
+                }
+                Err(format_err!(message))
+            }
+        }
+    }
+
+    /// Runs `op` for each dependency, at most `jobs` at the same time, and
+    /// returns the results in spec order.
+    fn run_all<T, F>(&self, op: F) -> Vec<Result<T>>
+    where
+        T: Send,
+        F: (Fn(&Dependency) -> Result<T>) + Sync,
+    {
+        let jobs = match self.jobs {
+            Some(1) => {
+                return self
+                    .deps
+                    .iter()
+                    .map(|dep| {
+                        import_result(dep, panic::catch_unwind(AssertUnwindSafe(|| op(dep))))
+                    })
+                    .collect();
+            }
+            Some(jobs) => jobs.max(1),
+            None => self.deps.len().max(1),
+        };
+        thread::scope(|s| {
+            self.deps
+                .chunks(jobs)
+                .flat_map(|chunk| {
+                    let handles: Vec<_> =
+                        chunk.iter().map(|dep| (dep, s.spawn(|| op(dep)))).collect();
+                    handles
+                        .into_iter()
+                        .map(|(dep, handle)| join_import(dep, handle))
+                        .collect::<Vec<_>>()
+                })
                 .collect()
-        });
+        })
+    }
+
+    /// Runs `op` with the dependency, and when its repository cannot be
+    /// cloned or fetched, with each of its mirrors in order until one
+    /// succeeds, see [`unreachable`]. Other failures would be the same with
+    /// any mirror, so they are returned as they are. Returns the mirror that
+    /// was used, if any. The error of the dependency itself is returned when
+    /// all are unreachable.
+    ///
+    /// Each attempt spends from its own [`Budget`], released when it fails.
+    fn with_mirrors<T>(
+        &self,
+        dependency: &Dependency,
+        op: impl Fn(&Dependency, &Budget) -> Result<T>,
+    ) -> Result<(T, Option<String>)> {
+        let budget = self.budget.attempt();
+        let err = match op(dependency, &budget) {
+            Ok(it) => return Ok((it, None)),
+            Err(err) => err,
+        };
+        budget.release();
+        if !is_unreachable(&err) {
+            return Err(err);
+        }
+        for mirror in &dependency.mirrors {
+            log::warn!("{err}, trying the mirror {mirror}");
+            let part_path = self.part_path(dependency);
+            if part_path.exists() {
+                fs::remove_dir_all(part_path)?;
+            }
+            let mut remote = dependency.clone();
+            remote.url.clone_from(mirror);
+            remote.mirrors.clear();
+            let budget = self.budget.attempt();
+            match op(&remote, &budget) {
+                Ok(it) => return Ok((it, Some(mirror.clone()))),
+                Err(mirror_err) if is_unreachable(&mirror_err) => {
+                    budget.release();
+                    log::warn!("cannot vendor from the mirror {mirror}: {mirror_err}");
+                }
+                Err(mirror_err) => {
+                    budget.release();
+                    return Err(mirror_err);
+                }
+            }
+        }
+        Err(err)
+    }
+
+    fn inner_install(&self, dependency: &Dependency) -> Result<Imported> {
+        let (mut imported, mirror) = self.with_mirrors(dependency, |remote, budget| {
+            self.install_from(dependency, remote, budget)
+        })?;
+        imported.locked.mirror = mirror;
+        Ok(imported)
+    }
+
+    /// Installs the dependency from the repository of `remote`, which is
+    /// either the dependency or one of its mirrors.
+    fn install_from(
+        &self,
+        dependency: &Dependency,
+        remote: &Dependency,
+        budget: &Budget,
+    ) -> Result<Imported> {
+        let repository_lock = Arc::new(self.cache.lock_repository(remote)?);
+        let (repository, sha256) = self.open_repository(remote, false, &repository_lock)?;
+        let dependency_lock = self.spec_lock.get_locked_dependency(dependency);
+        if let Some(sha256) = &sha256 {
+            check_locked_archive(sha256, dependency, dependency_lock)?;
+        }
+        let importer = Importer::new(self.spec, dependency, dependency_lock, &repository)?
+            .with_vendor(self.part_path(dependency))
+            .with_previous(self.vendor_path())
+            .with_file_filter(self.file_filter.as_deref())
+            .with_budget(budget)
+            .with_objects(self.objects_path())
+            .with_repository_lock(&repository_lock);
+
+        let mut installed = if self.mode.offline {
+            importer.install_offline()?
+        } else {
+            importer.install()?
+        };
+        installed.locked.sha256 = sha256;
+        Ok(installed)
+    }
+
+    fn inner_warm_cache(&self, dependency: &Dependency) -> Result<()> {
+        self.with_mirrors(dependency, |remote, _| {
+            let repository_lock = Arc::new(self.cache.lock_repository(remote)?);
+            let (repository, _) = self.open_repository(remote, false, &repository_lock)?;
+            Importer::new(self.spec, dependency, None, &repository)?
+                .with_repository_lock(&repository_lock)
+                .warm()
+        })
+        .map(|_| ())
+    }
+
+    fn inner_update(&self, dependency: &Dependency) -> Result<Imported> {
+        let (mut imported, mirror) = self.with_mirrors(dependency, |remote, budget| {
+            self.update_from(dependency, remote, budget)
+        })?;
+        imported.locked.mirror = mirror;
+        Ok(imported)
+    }
+
+    /// Updates the dependency from the repository of `remote`, see
+    /// [`Installer::install_from`].
+    fn update_from(
+        &self,
+        dependency: &Dependency,
+        remote: &Dependency,
+        budget: &Budget,
+    ) -> Result<Imported> {
+        let repository_lock = Arc::new(self.cache.lock_repository(remote)?);
+        let (repository, sha256) = self.open_repository(remote, true, &repository_lock)?;
+        let mut importer = Importer::new(self.spec, dependency, None, &repository)?
+            .with_vendor(self.part_path(dependency))
+            .with_file_filter(self.file_filter.as_deref())
+            .with_budget(budget)
+            .with_objects(self.objects_path())
+            .with_repository_lock(&repository_lock);
+        if self.is_delta() {
+            let locked = self
+                .spec_lock
+                .get_locked_dependency(dependency)
+                .map(|lock| lock.refname.clone());
+            importer = importer
+                .with_previous(self.vendor_path())
+                .with_delta_from(locked);
+        }
+
+        let mut updated = importer.update()?;
+        updated.locked.sha256 = sha256;
+        Ok(updated)
+    }
+
+    /// Returns whether updates only read the changed files, see
+    /// [`Spec::delta_updates`]. The others are copied from the vendor folder,
+    /// which needs the checksums of the lock to be known unmodified.
+    fn is_delta(&self) -> bool {
+        if !self.spec.write.delta_updates {
+            return false;
+        }
+        if self.spec_lock.checksums.is_empty() {
+            log::debug!(
+                "reading every file, the lock has no checksums to verify the vendor folder"
+            );
+            return false;
+        }
+        if self.locally_modified {
+            log::debug!("reading every file, the vendor folder has local modifications");
+            return false;
+        }
+        true
+    }
+
+    /// Opens the repository of `remote` from the cache, cloning or fetching
+    /// it unless offline. The url is interpolated only to clone it, see
+    /// [`interpolate`], so the lock and the logs keep it as written.
+    ///
+    /// Release archives are extracted into a repository of the cache first,
+    /// see [`archive::ensure`], which also returns their checksum. They are
+    /// downloaded again when `refresh` is set, as the url may point to the
+    /// latest release.
+    fn open_repository(
+        &self,
+        remote: &Dependency,
+        refresh: bool,
+        repository_lock: &Arc<Lock>,
+    ) -> Result<(Repository, Option<String>)> {
+        let mut origin = interpolate(&remote.url)?;
+        let mut sha256 = None;
+        if archive::is_archive(&origin) {
+            if self.mode.force || refresh {
+                self.cache.remove_repository(remote)?;
+            }
+            let mut source = remote.clone();
+            source.url = origin;
+            let path = self.cache.get_archive_path(remote)?;
+            sha256 = Some(archive::ensure(&source, &path, self.mode.offline).map_err(unreachable)?);
+            origin = path.to_string_lossy().into_owned();
+        }
+        let repository = if self.mode.offline {
+            self.cache.get_cached_repository(remote)?
+        } else {
+            self.get_repository(remote, &origin, repository_lock)?
+        };
+        Ok((repository, sha256))
+    }
+
+    /// Clones or fetches the repository of the dependency into the cache,
+    /// from `origin`, see [`Cache::get_repository`]. The lock of the
+    /// repository is held until the clone ends, even after a timeout.
+    fn get_repository(
+        &self,
+        dependency: &Dependency,
+        origin: &str,
+        repository_lock: &Arc<Lock>,
+    ) -> Result<Repository> {
+        let url = &dependency.url;
+        if self.mode.force {
+            log::info!("removing the cached repository of {url}");
+            self.cache.remove_repository(dependency)?;
+        }
+        let what = format!("cloning {url}");
+        self.spec
+            .retry
+            .run(&what, || {
+                let cache = self.cache.clone();
+                let dependency = dependency.clone();
+                let origin = origin.to_string();
+                let options = self.spec.clone_options();
+                timeout::run(
+                    &what,
+                    Duration::from_secs(self.spec.timeout_secs),
+                    Arc::clone(repository_lock),
+                    move || cache.get_repository(&dependency, &origin, &options),
+                )
+            })
+            .map_err(unreachable)
+    }
 
-        for lock in updated_locks {
-            self.spec_lock.add_locked_dependency(lock);
+    /// Returns the folder of the cache that the vendored files are hardlinked
+    /// to, when the spec asks for hardlinks.
+    fn objects_path(&self) -> Option<&Path> {
+        self.spec.write.hardlink.then(|| self.cache.objects_path())
+    }
+
+    fn vendor_path(&self) -> &Path {
+        self.vendor
+            .as_deref()
+            .unwrap_or_else(|| Path::new(&self.spec.vendor))
+    }
+
+    /// Folder where the dependencies are imported, it replaces the vendor
+    /// folder once all of them succeed.
+    fn staging_path(&self) -> PathBuf {
+        sibling_path(self.vendor_path(), "staging")
+    }
+
+    /// Folder where the dependencies are imported apart from each other,
+    /// before they are merged into the staging folder.
+    fn parts_path(&self) -> PathBuf {
+        sibling_path(self.vendor_path(), "parts")
+    }
+
+    fn part_path(&self, dependency: &Dependency) -> PathBuf {
+        let index = self
+            .deps
+            .iter()
+            .position(|dep| dep.is_same(dependency))
+            .unwrap_or(self.deps.len());
+        self.parts_path().join(index.to_string())
+    }
+
+    /// Moves the imported files of each dependency into the staging folder,
+    /// in spec order, handling the files that more than one dependency
+    /// vendors according to the collisions setting.
+    fn merge_parts(&self) -> Result<BTreeMap<PathBuf, &Dependency>> {
+        let staging = self.staging_path();
+        let mut owners: BTreeMap<PathBuf, &Dependency> = BTreeMap::new();
+        let mut collisions = vec![];
+        for dep in &self.deps {
+            let part = self.part_path(dep);
+            for entry in walkdir::WalkDir::new(&part) {
+                let entry = entry?;
+                if !entry.file_type().is_file() {
+                    continue;
+                }
+                let rel = entry.path().strip_prefix(&part)?.to_path_buf();
+                if let Some(owner) = owners.get(&rel) {
+                    let collision = format!(
+                        "{} is vendored by {} and {}",
+                        rel.display(),
+                        owner.url,
+                        dep.url
+                    );
+                    match self.spec.collisions {
+                        Collisions::LastWins => log::debug!("{collision}, keeping the last one"),
+                        Collisions::FirstWins | Collisions::Error => {
+                            collisions.push(collision);
+                            continue;
+                        }
+                    }
+                }
+                let to = staging.join(&rel);
+                if let Some(parent) = to.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                rename(entry.path(), &to)?;
+                owners.insert(rel, dep);
+            }
+        }
+
+        match self.spec.collisions {
+            Collisions::LastWins => Ok(owners),
+            Collisions::FirstWins => {
+                for collision in collisions {
+                    log::warn!("{collision}, keeping the first one");
+                }
+                Ok(owners)
+            }
+            Collisions::Error if collisions.is_empty() => Ok(owners),
+            Collisions::Error => Err(format_err!(
+                "dependencies vendor the same files:\n\t{}",
+                collisions.join("\n\t")
+            )),
+        }
+    }
+
+    /// Writes the manifest of the spec, if any, into the staging folder. It
+    /// lists the vendored files, sorted, along with the url and the locked
+    /// commit of the dependency that each comes from.
+    fn write_manifest(&self, owners: &BTreeMap<PathBuf, &Dependency>) -> Result<()> {
+        let Some(manifest) = &self.spec.manifest else {
+            return Ok(());
+        };
+        if owners.contains_key(Path::new(manifest)) {
+            return Err(format_err!(
+                "cannot write the manifest {manifest}, a dependency vendors the same file"
+            ));
+        }
+        let entries: BTreeMap<_, _> = owners
+            .iter()
+            .map(|(path, dep)| {
+                let refname = self
+                    .spec_lock
+                    .get_locked_dependency(dep)
+                    .map(|lock| lock.refname.clone())
+                    .unwrap_or_default();
+                let entry = ManifestEntry {
+                    url: dep.url.clone(),
+                    refname,
+                };
+                (path.to_string_lossy().to_string(), entry)
+            })
+            .collect();
+        let path = self.staging_path().join(manifest);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        yaml::save(&entries, path)
+    }
+}
+
+/// Provenance of a vendored file, as listed in the manifest.
+#[derive(Serialize)]
+struct ManifestEntry {
+    url: String,
+    refname: String,
+}
+
+/// Drops the dependencies whose `when_env` condition is not met, their lock
+/// entries are kept as they are.
+fn active_deps(deps: Vec<Dependency>, lookup: impl Fn(&str) -> Option<String>) -> Vec<Dependency> {
+    deps.into_iter()
+        .filter(|dep| {
+            let active = dep.is_active(&lookup);
+            if !active {
+                log::info!(
+                    "[{}] skipped, when_env {} is not met",
+                    dep.name(),
+                    dep.when_env.as_deref().unwrap_or_default()
+                );
+            }
+            active
+        })
+        .collect()
+}
+
+/// Waits for the work on a dependency to finish, a panic in its thread is
+/// turned into an error that identifies the dependency.
+fn join_import<T>(dep: &Dependency, handle: ScopedJoinHandle<Result<T>>) -> Result<T> {
+    import_result(dep, handle.join())
+}
+
+/// Adds the url of the dependency to the error of its work, panics included.
+fn import_result<T>(dep: &Dependency, result: thread::Result<Result<T>>) -> Result<T> {
+    match result {
+        Ok(result) => result.map_err(|err| prefixed(err, &dep.url)),
+        Err(panic) => {
+            let message = panic
+                .downcast_ref::<&str>()
+                .map(ToString::to_string)
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown reason".into());
+            Err(format_err!("{} panicked: {message}", dep.url))
+        }
+    }
+}
+
+/// Creates the directories to keep inside the vendor folder at `root`, they
+/// cannot point outside of it.
+fn create_keep_dirs(dirs: &[String], root: &Path) -> Result<()> {
+    for dir in dirs {
+        let path = Path::new(dir);
+        if !path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+        {
+            return Err(format_err!(
+                "cannot keep directory {dir}, it must be relative to the vendor folder"
+            ));
         }
+        fs::create_dir_all(root.join(path))?;
+    }
+    Ok(())
+}
 
-        Ok(self.spec_lock)
+/// Runs the post install commands in order from `dir`, stopping at the first
+/// one that fails. The commands run on the staged vendor folder, so a failing
+/// command leaves the current vendor folder untouched.
+fn run_hooks(commands: &[Vec<String>], dir: &Path) -> Result<()> {
+    for command in commands {
+        let (program, args) = command
+            .split_first()
+            .ok_or_else(|| format_err!("post install commands cannot be empty"))?;
+        let display = command.join(" ");
+        log::info!("running post install command: {display}");
+        let output = Command::new(program)
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .map_err(|err| format_err!("cannot run post install command '{display}': {err}"))?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if !stdout.trim().is_empty() {
+            log::info!("{}", stdout.trim());
+        }
+        if !output.status.success() {
+            return Err(format_err!(
+                "post install command '{display}' failed ({}): {}",
+                output.status,
+                stderr.trim()
+            ));
+        }
+        if !stderr.trim().is_empty() {
+            log::warn!("{}", stderr.trim());
+        }
     }
+    Ok(())
+}
 
-    fn inner_install(&self, dependency: &Dependency) -> Result<LockedDependency> {
-        let _repository_lock = self.cache.lock_repository(dependency)?;
-        let repository = self.cache.get_repository(dependency)?;
-        let dependency_lock = self.spec_lock.get_locked_dependency(&dependency.url);
-        let importer = Importer::new(self.spec, dependency, dependency_lock, &repository);
+/// Checks that the vendor folder can be replaced, before importing anything.
+fn check_vendor_path(path: &Path, vendor_git: VendorGit) -> Result<()> {
+    if !path.exists() {
+        return Ok(());
+    }
+    if !path.is_dir() {
+        return Err(format_err!(
+            "vendor path '{}' already exists, and it's not a directory",
+            path.display()
+        ));
+    }
+    if vendor_git == VendorGit::Refuse && path.join(".git").exists() {
+        return Err(format_err!(
+            "vendor path '{}' contains a .git entry, refusing to delete it",
+            path.display()
+        ));
+    }
+    Ok(())
+}
 
-        importer.install()
+/// Fails when the release archive does not have the checksum it was locked
+/// with, as the url may serve another archive since, such as a latest release.
+fn check_locked_archive(
+    sha256: &str,
+    dependency: &Dependency,
+    dependency_lock: Option<&LockedDependency>,
+) -> Result<()> {
+    let locked = dependency_lock
+        .filter(|lock| !lock.is_outdated(dependency))
+        .and_then(|lock| lock.sha256.as_deref());
+    match locked {
+        Some(locked) if !locked.eq_ignore_ascii_case(sha256) => Err(format_err!(
+            "the archive has sha256 {sha256}, but {locked} is locked"
+        )),
+        _ => Ok(()),
     }
+}
+
+/// Replaces the vendor folder with the staging folder. The previous vendor
+/// folder is moved aside and only deleted once the staging folder is in
+/// place, its `.git` entry is carried over.
+fn swap_vendor_path(staging: &Path, vendor: &Path) -> Result<()> {
+    let swap = || -> Result<()> {
+        if vendor.join(".git").exists() {
+            rename(&vendor.join(".git"), &staging.join(".git"))?;
+        }
+        let previous = sibling_path(vendor, "previous");
+        if vendor.exists() {
+            rename(vendor, &previous)?;
+        }
+        if let Err(err) = rename(staging, vendor) {
+            if previous.exists() {
+                rename(&previous, vendor)?;
+            }
+            return Err(err);
+        }
+        if previous.exists() {
+            fs::remove_dir_all(&previous)?;
+        }
+        Ok(())
+    };
+    swap().map_err(|err| format_err!("cannot replace vendor folder: {err}"))
+}
 
-    fn inner_update(&self, dependency: &Dependency) -> Result<LockedDependency> {
-        let _repository_lock = self.cache.lock_repository(dependency)?;
-        let repository = self.cache.get_repository(dependency)?;
-        let importer = Importer::new(self.spec, dependency, None, &repository);
+/// Renames a path, falling back to copy and delete when the destination is
+/// in a different filesystem.
+fn rename(from: &Path, to: &Path) -> Result<()> {
+    match fs::rename(from, to) {
+        Err(err) if err.raw_os_error() == Some(libc::EXDEV) => {
+            copy_all(from, to)?;
+            if from.is_dir() {
+                fs::remove_dir_all(from)?;
+            } else {
+                fs::remove_file(from)?;
+            }
+            Ok(())
+        }
+        result => Ok(result?),
+    }
+}
 
-        importer.update()
+fn copy_all(from: &Path, to: &Path) -> Result<()> {
+    if !from.is_dir() {
+        fs::copy(from, to)?;
+        return Ok(());
     }
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        copy_all(&entry.path(), &to.join(entry.file_name()))?;
+    }
+    Ok(())
+}
+
+/// Returns a hidden path next to `path`, named after it.
+fn sibling_path(path: &Path, suffix: &str) -> PathBuf {
+    let name = path
+        .file_name()
+        .map_or_else(|| "vendor".into(), |name| name.to_string_lossy());
+    path.with_file_name(format!(".{name}.{suffix}"))
 }
 
-fn recreate_vendor_path<P: AsRef<Path>>(path: P) -> Result<()> {
-    delete_vendor_path(&path)?;
+fn recreate_vendor_path<P: AsRef<Path>>(path: P, vendor_git: VendorGit) -> Result<()> {
+    delete_vendor_path(&path, vendor_git)?;
     create_vendor_path(&path)
 }
 
-fn delete_vendor_path<P: AsRef<Path>>(path: P) -> Result<()> {
+fn delete_vendor_path<P: AsRef<Path>>(path: P, vendor_git: VendorGit) -> Result<()> {
     let path = path.as_ref();
-    if path.exists() {
-        fs::remove_dir_all(path)
-            .map_err(|err| format_err!("cannot reset vendor folder: {}", err))?;
+    if !path.exists() {
+        return Ok(());
     }
-    Ok(())
+
+    if path.is_dir() && path.join(".git").exists() {
+        return match vendor_git {
+            VendorGit::Refuse => Err(format_err!(
+                "vendor path '{}' contains a .git entry, refusing to delete it",
+                path.display()
+            )),
+            VendorGit::Preserve => delete_vendor_contents(path),
+        };
+    }
+
+    fs::remove_dir_all(path).map_err(|err| format_err!("cannot reset vendor folder: {}", err))
+}
+
+/// Deletes the contents of the vendor folder, except for its `.git` entry.
+fn delete_vendor_contents(path: &Path) -> Result<()> {
+    let reset = || -> Result<()> {
+        for entry in fs::read_dir(path)? {
+            let entry = entry?;
+            if entry.file_name() == ".git" {
+                continue;
+            }
+            if entry.file_type()?.is_dir() {
+                fs::remove_dir_all(entry.path())?;
+            } else {
+                fs::remove_file(entry.path())?;
+            }
+        }
+        Ok(())
+    };
+    reset().map_err(|err| format_err!("cannot reset vendor folder: {}", err))
 }
 
 fn create_vendor_path<P: AsRef<Path>>(path: P) -> Result<()> {
@@ -118,9 +1186,1754 @@ fn create_vendor_path<P: AsRef<Path>>(path: P) -> Result<()> {
 #[cfg(test)]
 mod tests {
 
+    use std::net::TcpListener;
+    use std::time::SystemTime;
+
+    use sha2::Digest;
+    use sha2::Sha256;
+    use tempfile::TempDir;
+
     use super::*;
+    use crate::preset::Preset;
+    use crate::svec;
+    use crate::test_utils::git_commit;
+    use crate::test_utils::git_commit_at;
+    use crate::test_utils::git_init;
+    use crate::test_utils::git_remove;
+    use crate::test_utils::read_to_string;
     use crate::test_utils::tempdir;
     use crate::test_utils::write_to;
+    use crate::test_utils::TestContext;
+
+    /// Returns a spec depending on a local upstream repository, containing a
+    /// single file that is selected by the test preset filters.
+    fn get_spec(preset: &Preset, upstream: &TempDir) -> Spec {
+        git_init(upstream.path(), &[("global/target/a/file.txt", "data")]);
+        let mut spec = Spec::with_preset(preset);
+        spec.add_dependency(Dependency::new(upstream.path().to_string_lossy(), "master"));
+        spec
+    }
+
+    fn get_installer<'spec>(preset: &Preset, spec: &'spec Spec) -> Installer<'spec> {
+        Installer::new(Cache::new(preset), spec, SpecLock::with_preset(preset))
+    }
+
+    struct StaticRefs;
+
+    impl RemoteRefs for StaticRefs {
+        fn list_refs(&self, _url: &str) -> Result<Vec<(String, String)>> {
+            Ok(vec![("refs/heads/master".into(), "latest-commit".into())])
+        }
+    }
+
+    #[test]
+    fn test_installer_outdated_uses_remote_refs() -> Result<()> {
+        let ctx = TestContext::new();
+        let mut spec = Spec::with_preset(&ctx.preset);
+        spec.add_dependency(Dependency::new("outdated-url", "master"));
+        spec.add_dependency(Dependency::new("up-to-date-url", "master"));
+        let mut spec_lock = SpecLock::with_preset(&ctx.preset);
+        spec_lock.add_locked_dependency(LockedDependency::new("outdated-url", "old-commit"));
+        spec_lock.add_locked_dependency(LockedDependency::new("up-to-date-url", "latest-commit"));
+
+        let sut = Installer::new(Cache::new(&ctx.preset), &spec, spec_lock)
+            .with_remote_refs(Some(Box::new(StaticRefs)));
+
+        assert_eq!(
+            vec![Outdated {
+                url: "outdated-url".into(),
+                locked: Some("old-commit".into()),
+                latest: "latest-commit".into(),
+            }],
+            sut.outdated()?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_installer_reports_panics() -> Result<()> {
+        let ctx = TestContext::new();
+        let mut spec = Spec::with_preset(&ctx.preset);
+        spec.add_dependency(Dependency::new("panicking-url", "master"));
+        let sut = get_installer(&ctx.preset, &spec);
+
+        let (spec_lock, metrics, _) = sut.execute(|_, _| panic!("boom"))?;
+
+        assert_eq!(1, metrics.failures);
+        assert!(spec_lock.deps.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_installer_runs_post_install_commands() -> Result<()> {
+        let ctx = TestContext::new();
+        let upstream = tempdir();
+        let mut spec = get_spec(&ctx.preset, &upstream);
+        spec.post_install = vec![svec!["touch", "generated.txt"]];
+
+        let (_, metrics) = get_installer(&ctx.preset, &spec).install()?;
+
+        assert_eq!(0, metrics.failures);
+        let vendor = Path::new(&spec.vendor);
+        assert!(vendor.join("generated.txt").exists());
+        assert!(vendor.join("global/target/a/file.txt").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_installer_fails_when_post_install_command_fails() -> Result<()> {
+        let ctx = TestContext::new();
+        let upstream = tempdir();
+        let mut spec = get_spec(&ctx.preset, &upstream);
+        get_installer(&ctx.preset, &spec).install()?;
+        spec.post_install = vec![svec!["sh", "-c", "echo boom >&2; exit 3"]];
+
+        let actual = get_installer(&ctx.preset, &spec).install();
+
+        assert_eq!(
+            "post install command 'sh -c echo boom >&2; exit 3' failed (exit status: 3): boom",
+            actual.expect_err("should fail").to_string()
+        );
+        let vendor = Path::new(&spec.vendor);
+        assert!(vendor.join("global/target/a/file.txt").exists());
+        assert!(!sibling_path(vendor, "staging").exists());
+        assert!(!sibling_path(vendor, "parts").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_installer_partial_clone() -> Result<()> {
+        let ctx = TestContext::new();
+        let upstream = tempdir();
+        let git = git_init(upstream.path(), &[("global/target/a/file.txt", "data")]);
+        git.config()?.set_bool("uploadpack.allowFilter", true)?;
+        let url = format!("file://{}", upstream.path().display());
+        let mut spec = Spec::with_preset(&ctx.preset);
+        spec.add_dependency(Dependency::new(&url, "master"));
+        spec.partial_clone = true;
+
+        let (_, metrics) = get_installer(&ctx.preset, &spec).update()?;
+        assert_eq!(0, metrics.failures);
+
+        git_commit(&git, &[("global/target/a/file.txt", "changed")], "change");
+        let (_, metrics) = get_installer(&ctx.preset, &spec).update()?;
+
+        assert_eq!(0, metrics.failures);
+        assert_eq!(
+            "changed",
+            read_to_string(&Path::new(&spec.vendor).join("global/target/a/file.txt"))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_installer_cleans_dirty_cache_repository() -> Result<()> {
+        let ctx = TestContext::new();
+        let upstream = tempdir();
+        let spec = get_spec(&ctx.preset, &upstream);
+        get_installer(&ctx.preset, &spec).update()?;
+        let cached = Cache::new(&ctx.preset).get_cached_repository(&spec.deps[0])?;
+        let target = cached.path().join("global/target/a");
+        write_to(target.join("file.txt"), "dirty");
+        write_to(target.join("stray.txt"), "stray");
+        let git = git2::Repository::open(cached.path())?;
+        git.set_head_detached(git.head()?.peel_to_commit()?.id())?;
+
+        let (_, metrics) = get_installer(&ctx.preset, &spec).update()?;
+
+        assert_eq!(0, metrics.failures);
+        let vendor = Path::new(&spec.vendor).join("global/target/a");
+        assert_eq!("data", read_to_string(&vendor.join("file.txt")));
+        assert!(!vendor.join("stray.txt").exists());
+        assert!(!target.join("stray.txt").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_installer_reproducible_sets_commit_time() -> Result<()> {
+        let upstream = tempdir();
+        let git = git_init(upstream.path(), &[]);
+        git_commit_at(
+            &git,
+            &[("global/target/a/file.txt", "data")],
+            "data",
+            1_700_000_000,
+        );
+        let install = || -> Result<SystemTime> {
+            let ctx = TestContext::new();
+            let mut spec = Spec::with_preset(&ctx.preset);
+            spec.write.reproducible = true;
+            spec.add_dependency(Dependency::new(upstream.path().to_string_lossy(), "master"));
+            get_installer(&ctx.preset, &spec).install()?;
+            let vendored = Path::new(&spec.vendor).join("global/target/a/file.txt");
+            Ok(fs::metadata(vendored)?.modified()?)
+        };
+
+        let first = install()?;
+        let second = install()?;
+
+        assert_eq!(first, second);
+        assert_eq!(
+            SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+            first
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_installer_aborts_when_exceeding_total_limits() -> Result<()> {
+        let first = tempdir();
+        let second = tempdir();
+        git_init(first.path(), &[("global/target/a/first.txt", "one")]);
+        git_init(second.path(), &[("global/target/a/second.txt", "two")]);
+        let install = |limit: fn(&mut Spec)| -> Result<Failures> {
+            let ctx = TestContext::new();
+            let mut spec = Spec::with_preset(&ctx.preset);
+            limit(&mut spec);
+            spec.add_dependency(Dependency::new(first.path().to_string_lossy(), "master"));
+            spec.add_dependency(Dependency::new(second.path().to_string_lossy(), "master"));
+            let (_, _, failures) =
+                get_installer(&ctx.preset, &spec).install_reporting_failures()?;
+            Ok(failures)
+        };
+
+        let failures = install(|spec| spec.max_files = Some(1))?;
+        assert_eq!(1, failures.len());
+        assert!(
+            format!("{:#}", failures[0].1).contains("exceed the maximum of 1 files in total"),
+            "{:#}",
+            failures[0].1
+        );
+
+        let failures = install(|spec| spec.max_total_bytes = Some(4))?;
+        assert_eq!(1, failures.len());
+        assert!(
+            format!("{:#}", failures[0].1).contains("exceed the maximum of 4 bytes in total"),
+            "{:#}",
+            failures[0].1
+        );
+
+        assert!(install(|spec| spec.max_total_bytes = Some(6))?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_installer_lock_hook_rewrites_the_lock() -> Result<()> {
+        let ctx = TestContext::new();
+        let upstream = tempdir();
+        let spec = get_spec(&ctx.preset, &upstream);
+        let upstream_url = upstream.path().to_string_lossy().to_string();
+
+        let (spec_lock, _) = get_installer(&ctx.preset, &spec)
+            .with_lock_hook(Some(Box::new(move |lock| {
+                for dep in &mut lock.deps {
+                    dep.url = dep.url.replace(&upstream_url, "https://mirror.internal/a");
+                }
+                Ok(())
+            })))
+            .install()?;
+
+        assert_eq!("https://mirror.internal/a", spec_lock.deps[0].url);
+
+        let git = git2::Repository::open(upstream.path())?;
+        git_commit(&git, &[("global/target/a/file.txt", "changed")], "changed");
+        let actual = get_installer(&ctx.preset, &spec)
+            .with_lock_hook(Some(Box::new(|_| Err(format_err!("not allowed")))))
+            .update();
+        assert_eq!(
+            "lock hook failed: not allowed",
+            actual.unwrap_err().to_string()
+        );
+        assert_eq!(
+            "data",
+            read_to_string(&Path::new(&spec.vendor).join("global/target/a/file.txt"))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_installer_clears_failed_files_once_they_succeed() -> Result<()> {
+        let ctx = TestContext::new();
+        let upstream = tempdir();
+        let mut spec = get_spec(&ctx.preset, &upstream);
+        let long_name = format!("global/target/a/{}.txt", "a".repeat(200));
+        let git = git2::Repository::open(upstream.path())?;
+        git_commit(&git, &[(&long_name, "long")], "long");
+        spec.read.skip_failed_files = true;
+        // The suffix makes the name of the long file too long to be vendored.
+        spec.filters.rename_template = Some(format!("{{path}}.{}", "b".repeat(60)));
+
+        let (spec_lock, _) = get_installer(&ctx.preset, &spec).install()?;
+        assert_eq!(svec![long_name.clone()], spec_lock.deps[0].failed_files);
+
+        git_remove(&git, &[&long_name], "remove the long file");
+        let (spec_lock, metrics) =
+            Installer::new(Cache::new(&ctx.preset), &spec, spec_lock).update()?;
+
+        assert_eq!(0, metrics.failures);
+        assert!(spec_lock.deps[0].failed_files.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_installer_reports_metrics_per_dependency() -> Result<()> {
+        let ctx = TestContext::new();
+        let first = tempdir();
+        let second = tempdir();
+        git_init(
+            first.path(),
+            &[
+                ("global/target/a/one.txt", "one"),
+                ("global/target/a/three.txt", "three"),
+            ],
+        );
+        git_init(second.path(), &[("global/target/a/two.txt", "two")]);
+        let mut spec = Spec::with_preset(&ctx.preset);
+        spec.add_dependency(Dependency::new(first.path().to_string_lossy(), "master"));
+        let mut named = Dependency::new(second.path().to_string_lossy(), "master");
+        named.name = Some("second".into());
+        spec.add_dependency(named);
+
+        let (_, metrics) = get_installer(&ctx.preset, &spec).install()?;
+
+        assert_eq!(
+            vec![
+                DependencyMetrics {
+                    url: first.path().to_string_lossy().to_string(),
+                    name: None,
+                    files: 2,
+                    bytes: 8,
+                },
+                DependencyMetrics {
+                    url: second.path().to_string_lossy().to_string(),
+                    name: Some("second".into()),
+                    files: 1,
+                    bytes: 3,
+                },
+            ],
+            metrics.per_dependency
+        );
+        assert_eq!(3, metrics.files);
+        assert_eq!(11, metrics.bytes);
+        Ok(())
+    }
+
+    #[test]
+    fn test_installer_fails_over_to_mirrors() -> Result<()> {
+        let ctx = TestContext::new();
+        let mirror = tempdir();
+        git_init(mirror.path(), &[("global/target/a/file.txt", "mirrored")]);
+        let missing = tempdir();
+        let primary = missing.path().join("unreachable");
+        let mut dep = Dependency::new(primary.to_string_lossy(), "master");
+        dep.mirrors = vec![
+            missing
+                .path()
+                .join("also-unreachable")
+                .to_string_lossy()
+                .to_string(),
+            mirror.path().to_string_lossy().to_string(),
+        ];
+        let mut spec = Spec::with_preset(&ctx.preset);
+        spec.add_dependency(dep);
+
+        let (spec_lock, metrics) = get_installer(&ctx.preset, &spec).install()?;
+
+        assert_eq!(0, metrics.failures);
+        let vendored = Path::new(&spec.vendor).join("global/target/a/file.txt");
+        assert_eq!("mirrored", read_to_string(&vendored));
+        assert_eq!(primary.to_string_lossy(), spec_lock.deps[0].url);
+        assert_eq!(
+            Some(mirror.path().to_string_lossy().as_ref()),
+            spec_lock.deps[0].mirror.as_deref()
+        );
+
+        let (spec_lock, _) = Installer::new(Cache::new(&ctx.preset), &spec, spec_lock).install()?;
+        assert_eq!(1, spec_lock.deps.len(), "mirrors share the lock entry");
+        Ok(())
+    }
+
+    #[test]
+    fn test_installer_fails_over_only_when_unreachable() -> Result<()> {
+        let ctx = TestContext::new();
+        let upstream = tempdir();
+        git_init(
+            upstream.path(),
+            &[
+                ("global/target/a/file.txt", "upstream"),
+                ("global/target/a/other.txt", "upstream"),
+            ],
+        );
+        let mirror = tempdir();
+        git_init(mirror.path(), &[("global/target/a/file.txt", "mirrored")]);
+        let mut dep = Dependency::new(upstream.path().to_string_lossy(), "master");
+        dep.mirrors = vec![mirror.path().to_string_lossy().to_string()];
+        let mut spec = Spec::with_preset(&ctx.preset);
+        spec.max_files = Some(1);
+        spec.add_dependency(dep);
+
+        let (_, metrics) = get_installer(&ctx.preset, &spec).install()?;
+
+        assert_eq!(1, metrics.failures, "the mirror would exceed it as well");
+        let vendored = Path::new(&spec.vendor).join("global/target/a/file.txt");
+        assert!(!vendored.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_installer_force_recreates_corrupted_cache() -> Result<()> {
+        let ctx = TestContext::new();
+        let upstream = tempdir();
+        let spec = get_spec(&ctx.preset, &upstream);
+        get_installer(&ctx.preset, &spec).install()?;
+        let repos = Path::new(ctx.preset.cache()).join("repos");
+        let cached = fs::read_dir(&repos)?.next().unwrap()?.path();
+        let missing = tempdir();
+        git2::Repository::open(&cached)?
+            .remote_set_url("origin", &missing.path().join("gone").to_string_lossy())?;
+
+        let (_, metrics) = get_installer(&ctx.preset, &spec).install()?;
+        assert_eq!(1, metrics.failures, "should fail with the corrupted cache");
+
+        let (_, metrics) = get_installer(&ctx.preset, &spec)
+            .with_force(true)
+            .install()?;
+        assert_eq!(0, metrics.failures);
+        let origin = git2::Repository::open(&cached)?
+            .find_remote("origin")?
+            .url()
+            .map(ToString::to_string);
+        assert_eq!(Some(upstream.path().to_string_lossy().to_string()), origin);
+        Ok(())
+    }
+
+    #[test]
+    fn test_installers_share_the_cache_in_parallel() -> Result<()> {
+        let ctx = TestContext::new();
+        let upstream = tempdir();
+        let spec = get_spec(&ctx.preset, &upstream);
+        let vendors = tempdir();
+
+        let results: Vec<_> = thread::scope(|s| {
+            let handles: Vec<_> = (0..4)
+                .map(|i| {
+                    let (spec, preset, vendors) = (&spec, &ctx.preset, &vendors);
+                    s.spawn(move || -> Result<()> {
+                        let cache = Cache::new(preset);
+                        cache.initialize()?;
+                        let _cache_lock = cache.lock_shared()?;
+                        for _ in 0..3 {
+                            let (_, metrics) =
+                                Installer::new(cache.clone(), spec, SpecLock::with_preset(preset))
+                                    .with_vendor(Some(vendors.path().join(i.to_string())))
+                                    .update()?;
+                            assert_eq!(0, metrics.failures);
+                        }
+                        Ok(())
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+
+        for result in results {
+            result?;
+        }
+        for i in 0..4 {
+            let vendored = vendors.path().join(format!("{i}/global/target/a/file.txt"));
+            assert_eq!("data", read_to_string(&vendored));
+        }
+        let repos = Path::new(ctx.preset.cache()).join("repos");
+        let cached = fs::read_dir(&repos)?.next().unwrap()?.path();
+        assert!(Repository::new(&cached).open().is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn test_installer_delta_updates_match_full_updates() -> Result<()> {
+        let ctx = TestContext::new();
+        let upstream = tempdir();
+        let git = git_init(
+            upstream.path(),
+            &[
+                ("global/target/a/modified.txt", "one"),
+                ("global/target/a/deleted.txt", "one"),
+                ("global/target/a/renamed.txt", "renamed contents"),
+                ("global/target/a/kept.txt", "one"),
+            ],
+        );
+        let vendors = tempdir();
+        let mut full = Spec::with_preset(&ctx.preset);
+        full.vendor = vendors.path().join("full").to_string_lossy().to_string();
+        full.add_dependency(Dependency::new(upstream.path().to_string_lossy(), "master"));
+        let mut delta = full.clone();
+        delta.vendor = vendors.path().join("delta").to_string_lossy().to_string();
+        delta.write.delta_updates = true;
+        delta.locking.lock_checksums = true;
+        let full_lock = get_installer(&ctx.preset, &full).install()?.0;
+        let delta_lock = get_installer(&ctx.preset, &delta).install()?.0;
+
+        git_commit(
+            &git,
+            &[
+                ("global/target/a/modified.txt", "two"),
+                ("global/target/a/added.txt", "two"),
+                ("global/target/a/moved.txt", "renamed contents"),
+            ],
+            "change",
+        );
+        crate::test_utils::git_remove(
+            &git,
+            &["global/target/a/deleted.txt", "global/target/a/renamed.txt"],
+            "remove",
+        );
+        let full_lock = Installer::new(Cache::new(&ctx.preset), &full, full_lock)
+            .update()?
+            .0;
+        let delta_lock = Installer::new(Cache::new(&ctx.preset), &delta, delta_lock)
+            .update()?
+            .0;
+
+        let files = |vendor: &str| -> Vec<(String, String)> {
+            let dir = Path::new(vendor).join("global/target/a");
+            let mut files: Vec<_> = fs::read_dir(&dir)
+                .unwrap()
+                .map(|entry| entry.unwrap().path())
+                .map(|path| {
+                    let name = path.file_name().unwrap().to_string_lossy().to_string();
+                    (name, read_to_string(&path))
+                })
+                .collect();
+            files.sort();
+            files
+        };
+        assert_eq!(
+            vec![
+                ("added.txt".to_string(), "two".to_string()),
+                ("kept.txt".to_string(), "one".to_string()),
+                ("modified.txt".to_string(), "two".to_string()),
+                ("moved.txt".to_string(), "renamed contents".to_string()),
+            ],
+            files(&full.vendor)
+        );
+        assert_eq!(files(&full.vendor), files(&delta.vendor));
+
+        let kept = Path::new(&delta.vendor).join("global/target/a/kept.txt");
+        write_to(&kept, "edited");
+        git_commit(&git, &[("global/target/a/added.txt", "three")], "three");
+        let full_lock = Installer::new(Cache::new(&ctx.preset), &full, full_lock)
+            .update()?
+            .0;
+        let delta_lock = Installer::new(Cache::new(&ctx.preset), &delta, delta_lock)
+            .update()?
+            .0;
+        assert_eq!("one", read_to_string(&kept));
+        assert_eq!(files(&full.vendor), files(&delta.vendor));
+
+        for spec in [&mut full, &mut delta] {
+            spec.source_comments.insert("txt".into(), "#".into());
+        }
+        let head = git_commit(&git, &[("global/target/a/added.txt", "four")], "four");
+        Installer::new(Cache::new(&ctx.preset), &full, full_lock).update()?;
+        Installer::new(Cache::new(&ctx.preset), &delta, delta_lock).update()?;
+
+        let kept = &files(&delta.vendor)[1];
+        assert_eq!("kept.txt", kept.0);
+        assert!(kept.1.contains(&head.to_string()), "{}", kept.1);
+        assert_eq!(files(&full.vendor), files(&delta.vendor));
+        Ok(())
+    }
+
+    #[test]
+    fn test_installer_writes_the_manifest() -> Result<()> {
+        let ctx = TestContext::new();
+        let first = tempdir();
+        let second = tempdir();
+        git_init(
+            first.path(),
+            &[
+                ("global/target/a/b.txt", "b"),
+                ("global/target/a/a.txt", "a"),
+            ],
+        );
+        git_init(second.path(), &[("global/target/a/c.txt", "c")]);
+        let mut spec = Spec::with_preset(&ctx.preset);
+        spec.manifest = Some("MANIFEST.yml".into());
+        spec.add_dependency(Dependency::new(second.path().to_string_lossy(), "master"));
+        spec.add_dependency(Dependency::new(first.path().to_string_lossy(), "master"));
+
+        let (spec_lock, _) = get_installer(&ctx.preset, &spec).install()?;
+
+        let commit_of = |dir: &TempDir| {
+            spec_lock
+                .get_locked_dependency(&Dependency::new(dir.path().to_string_lossy(), "master"))
+                .unwrap()
+                .refname
+                .clone()
+        };
+        let entry = |path: &str, dir: &TempDir| {
+            format!(
+                "{path}:\n  url: {}\n  refname: {}\n",
+                dir.path().display(),
+                commit_of(dir)
+            )
+        };
+        let expected = [
+            entry("global/target/a/a.txt", &first),
+            entry("global/target/a/b.txt", &first),
+            entry("global/target/a/c.txt", &second),
+        ]
+        .concat();
+        let actual = read_to_string(&Path::new(&spec.vendor).join("MANIFEST.yml"));
+        assert_eq!(expected, actual);
+        Ok(())
+    }
+
+    #[test]
+    fn test_installer_vendors_release_archives() -> Result<()> {
+        let ctx = TestContext::new();
+        let releases = tempdir();
+        let tree = releases.path().join("release-1.0.0");
+        fs::create_dir_all(tree.join("global/target/a"))?;
+        write_to(tree.join("global/target/a/file.txt"), "data");
+        let archive = releases.path().join("release-1.0.0.tar.gz");
+        let pack = || {
+            let status = std::process::Command::new("tar")
+                .arg("-czf")
+                .arg(&archive)
+                .arg("-C")
+                .arg(releases.path())
+                .arg("release-1.0.0")
+                .status()
+                .unwrap();
+            assert!(status.success());
+        };
+        pack();
+        let sha256 = format!("{:x}", Sha256::digest(fs::read(&archive)?));
+        let mut dependency = Dependency::new(archive.to_string_lossy(), "1.0.0");
+        dependency.sha256 = Some(sha256.clone());
+        let mut spec = Spec::with_preset(&ctx.preset);
+        spec.add_dependency(dependency.clone());
+
+        let (spec_lock, _) = get_installer(&ctx.preset, &spec).update()?;
+
+        let vendored = Path::new(&spec.vendor).join("global/target/a/file.txt");
+        assert_eq!("data", read_to_string(&vendored));
+        let locked = spec_lock
+            .get_locked_dependency(&dependency)
+            .unwrap()
+            .clone();
+        assert_eq!(Some(sha256.clone()), locked.sha256);
+
+        // A fresh cache extracts the archive into the locked commit.
+        Cache::new(&ctx.preset).clear()?;
+        fs::remove_dir_all(&spec.vendor)?;
+        let (installed, _) = Installer::new(Cache::new(&ctx.preset), &spec, spec_lock).install()?;
+        assert_eq!(Some(&locked), installed.get_locked_dependency(&dependency));
+        assert_eq!("data", read_to_string(&vendored));
+
+        // The url serves another archive since, which is not the locked one.
+        write_to(tree.join("global/target/a/file.txt"), "changed");
+        pack();
+        Cache::new(&ctx.preset).clear()?;
+        let mut unpinned = dependency.clone();
+        unpinned.sha256 = None;
+        spec.deps = vec![unpinned];
+        let (_, _, failures) = Installer::new(Cache::new(&ctx.preset), &spec, installed)
+            .install_reporting_failures()?;
+        assert!(
+            failures[0]
+                .1
+                .to_string()
+                .ends_with(&format!("but {sha256} is locked")),
+            "{}",
+            failures[0].1
+        );
+        assert_eq!("data", read_to_string(&vendored));
+
+        let sha256 = format!("{:x}", Sha256::digest(fs::read(&archive)?));
+        dependency.sha256 = Some("0123".into());
+        spec.deps = vec![dependency];
+        let (_, _, failures) = get_installer(&ctx.preset, &spec).install_reporting_failures()?;
+        assert_eq!(
+            format!(
+                "{}: the archive has sha256 {sha256}, but 0123 is expected",
+                archive.display()
+            ),
+            failures[0].1.to_string()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_installer_explain() -> Result<()> {
+        let ctx = TestContext::new();
+        let upstream = tempdir();
+        let spec = get_spec(&ctx.preset, &upstream);
+        let url = upstream.path().to_string_lossy();
+        let sut = get_installer(&ctx.preset, &spec);
+
+        assert_eq!(
+            CopyDecision::Copied(Some("global/target/a".into())),
+            sut.explain(&url, Path::new("global/target/a/file.txt"))?
+        );
+        assert_eq!(
+            CopyDecision::Ignored("global/ignore/a".into()),
+            sut.explain(&url, Path::new("global/ignore/a/file.txt"))?
+        );
+        assert_eq!(
+            "cannot find dependency missing-url in the spec",
+            sut.explain("missing-url", Path::new("file.txt"))
+                .unwrap_err()
+                .to_string()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_installer_hardlinked_files_survive_cache_updates() -> Result<()> {
+        let ctx = TestContext::new();
+        let upstream = tempdir();
+        let mut spec = get_spec(&ctx.preset, &upstream);
+        spec.write.hardlink = true;
+        get_installer(&ctx.preset, &spec).install()?;
+        let vendored = Path::new(&spec.vendor).join("global/target/a/file.txt");
+        assert_eq!("data", read_to_string(&vendored));
+
+        let git = git2::Repository::open(upstream.path())?;
+        git_commit(&git, &[("global/target/a/file.txt", "new data")], "update");
+        let other = tempdir();
+        get_installer(&ctx.preset, &spec)
+            .with_vendor(Some(other.path().join("vendor")))
+            .update()?;
+
+        let updated = other.path().join("vendor/global/target/a/file.txt");
+        assert_eq!("new data", read_to_string(&updated));
+        assert_eq!("data", read_to_string(&vendored));
+        Ok(())
+    }
+
+    #[test]
+    fn test_installer_locks_urls_as_written() -> Result<()> {
+        let ctx = TestContext::new();
+        let upstream = tempdir();
+        git_init(upstream.path(), &[("global/target/a/file.txt", "data")]);
+        let url = format!("${{UNSET_UPSTREAM:-{}}}", upstream.path().display());
+        let mut spec = Spec::with_preset(&ctx.preset);
+        spec.manifest = Some("MANIFEST.yml".into());
+        spec.add_dependency(Dependency::new(&url, "master"));
+
+        let (mut spec_lock, _) = get_installer(&ctx.preset, &spec).install()?;
+        spec_lock.save()?;
+
+        assert_eq!(url, spec_lock.deps[0].url);
+        let saved = read_to_string(ctx.preset.spec_lock());
+        assert!(saved.contains("url: ${UNSET_UPSTREAM:-"), "{saved}");
+        let manifest = read_to_string(&Path::new(&spec.vendor).join("MANIFEST.yml"));
+        assert!(manifest.contains("url: ${UNSET_UPSTREAM:-"), "{manifest}");
+        assert_eq!(
+            "data",
+            read_to_string(&Path::new(&spec.vendor).join("global/target/a/file.txt"))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_installer_update_one() -> Result<()> {
+        let ctx = TestContext::new();
+        let first = tempdir();
+        let second = tempdir();
+        let first_git = git_init(first.path(), &[("global/target/a/first.txt", "one")]);
+        let second_git = git_init(second.path(), &[("global/target/a/second.txt", "one")]);
+        let mut spec = Spec::with_preset(&ctx.preset);
+        spec.add_dependency(Dependency::new(first.path().to_string_lossy(), "master"));
+        spec.add_dependency(Dependency::new(second.path().to_string_lossy(), "master"));
+        let (spec_lock, _) = get_installer(&ctx.preset, &spec).install()?;
+        let vendor = Path::new(&spec.vendor).join("global/target/a");
+        let modified = fs::metadata(vendor.join("second.txt"))?.modified()?;
+        let second_lock = spec_lock.get_locked_dependency(&spec.deps[1]).cloned();
+        git_commit(&first_git, &[("global/target/a/first.txt", "two")], "two");
+        git_commit(&second_git, &[("global/target/a/second.txt", "two")], "two");
+
+        let url = first.path().to_string_lossy().to_uppercase();
+        let (actual, metrics) =
+            Installer::new(Cache::new(&ctx.preset), &spec, spec_lock).update_one(&url)?;
+
+        assert_eq!(0, metrics.failures);
+        assert_eq!("two", read_to_string(&vendor.join("first.txt")));
+        assert_eq!("one", read_to_string(&vendor.join("second.txt")));
+        assert_eq!(
+            modified,
+            fs::metadata(vendor.join("second.txt"))?.modified()?
+        );
+        assert_eq!(
+            first_git.head()?.peel_to_commit()?.id().to_string(),
+            actual.get_locked_dependency(&spec.deps[0]).unwrap().refname
+        );
+        assert_eq!(
+            second_lock.as_ref(),
+            actual.get_locked_dependency(&spec.deps[1])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_installer_resolves_semver_range_to_highest_tag() -> Result<()> {
+        let ctx = TestContext::new();
+        let upstream = tempdir();
+        let git = git_init(upstream.path(), &[("global/target/a/file.txt", "1.0.0")]);
+        let tag = |name: &str, contents: &str| -> Result<String> {
+            let commit = git_commit(&git, &[("global/target/a/file.txt", contents)], name);
+            git.tag_lightweight(name, &git.find_object(commit, None)?, false)?;
+            Ok(commit.to_string())
+        };
+        tag("v1.0.0", "1.0.0")?;
+        let v1_2 = tag("v1.2.0", "1.2.0")?;
+        tag("v1.3.0-rc.1", "1.3.0-rc.1")?;
+        tag("v2.0.0", "2.0.0")?;
+        let mut spec = Spec::with_preset(&ctx.preset);
+        spec.add_dependency(Dependency::new(
+            upstream.path().to_string_lossy(),
+            "semver:^1.2",
+        ));
+        let vendored = Path::new(&spec.vendor).join("global/target/a/file.txt");
+
+        let (spec_lock, _) = get_installer(&ctx.preset, &spec).install()?;
+
+        assert_eq!("1.2.0", read_to_string(&vendored));
+        assert_eq!(v1_2, spec_lock.deps[0].refname);
+        assert_eq!(Some("v1.2.0"), spec_lock.deps[0].resolved.as_deref());
+
+        let v1_4 = tag("v1.4.0", "1.4.0")?;
+        let (spec_lock, _) = Installer::new(Cache::new(&ctx.preset), &spec, spec_lock).install()?;
+        assert_eq!("1.2.0", read_to_string(&vendored), "install keeps the lock");
+        assert_eq!(Some("v1.2.0"), spec_lock.deps[0].resolved.as_deref());
+
+        let (spec_lock, _) = Installer::new(Cache::new(&ctx.preset), &spec, spec_lock).update()?;
+        assert_eq!("1.4.0", read_to_string(&vendored));
+        assert_eq!(v1_4, spec_lock.deps[0].refname);
+        assert_eq!(Some("v1.4.0"), spec_lock.deps[0].resolved.as_deref());
+
+        spec.deps[0].refname = "semver:^3".into();
+        let (_, _, failures) = get_installer(&ctx.preset, &spec).install_reporting_failures()?;
+        assert_eq!(
+            format!(
+                "{}: no tag matches the semver range '^3'",
+                upstream.path().display()
+            ),
+            failures[0].1.to_string()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_installer_resolves_refname_from_version_file() -> Result<()> {
+        let ctx = TestContext::new();
+        let upstream = tempdir();
+        let git = git_init(
+            upstream.path(),
+            &[
+                ("VERSION", "v1\n"),
+                ("global/target/a/file.txt", "released"),
+            ],
+        );
+        let released = git.head()?.peel_to_commit()?;
+        git.tag_lightweight("v1", released.as_object(), false)?;
+        git_commit(&git, &[("global/target/a/file.txt", "unreleased")], "wip");
+        let mut spec = Spec::with_preset(&ctx.preset);
+        spec.add_dependency(Dependency::new(
+            upstream.path().to_string_lossy(),
+            "version-file:VERSION",
+        ));
+
+        let (spec_lock, _) = get_installer(&ctx.preset, &spec).install()?;
+
+        let vendored = Path::new(&spec.vendor).join("global/target/a/file.txt");
+        assert_eq!("released", read_to_string(&vendored));
+        assert_eq!(released.id().to_string(), spec_lock.deps[0].refname);
+
+        git_commit(&git, &[("VERSION", "v9")], "bump");
+        let (_, _, failures) = get_installer(&ctx.preset, &spec).install_reporting_failures()?;
+        let actual = failures[0].1.to_string();
+        assert!(
+            actual.contains("VERSION names 'v9': cannot find refname 'v9'"),
+            "{actual}"
+        );
+
+        git.index()?.remove_path(Path::new("VERSION"))?;
+        git.index()?.write()?;
+        git_commit(&git, &[], "remove version");
+        let (_, _, failures) = get_installer(&ctx.preset, &spec).install_reporting_failures()?;
+        assert_eq!(
+            format!(
+                "{}: cannot find VERSION in master",
+                upstream.path().display()
+            ),
+            failures[0].1.to_string()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_installer_vendors_from_bundle() -> Result<()> {
+        let ctx = TestContext::new();
+        let upstream = tempdir();
+        let git = git_init(upstream.path(), &[("global/target/a/file.txt", "one")]);
+        let bundles = tempdir();
+        let bundle = bundles.path().join("upstream.bundle");
+        let create_bundle = || {
+            let status = Command::new("git")
+                .args(["bundle", "create"])
+                .arg(&bundle)
+                .arg("--all")
+                .current_dir(upstream.path())
+                .output()
+                .unwrap()
+                .status;
+            assert!(status.success());
+        };
+        create_bundle();
+        let url = format!("bundle://{}", bundle.display());
+        let mut spec = Spec::with_preset(&ctx.preset);
+        spec.add_dependency(Dependency::new(&url, "master"));
+
+        let (spec_lock, _) = get_installer(&ctx.preset, &spec).install()?;
+
+        let head = git.head()?.peel_to_commit()?.id().to_string();
+        let vendored = Path::new(&spec.vendor).join("global/target/a/file.txt");
+        assert_eq!("one", read_to_string(&vendored));
+        assert_eq!(head, spec_lock.deps[0].refname);
+        assert_eq!(url, spec_lock.deps[0].url);
+
+        let commit = git_commit(&git, &[("global/target/a/file.txt", "two")], "two");
+        create_bundle();
+        let outdated = Installer::new(Cache::new(&ctx.preset), &spec, spec_lock).outdated()?;
+        assert_eq!(commit.to_string(), outdated[0].latest);
+
+        let (spec_lock, _) = get_installer(&ctx.preset, &spec).update()?;
+
+        assert_eq!("two", read_to_string(&vendored));
+        assert_eq!(commit.to_string(), spec_lock.deps[0].refname);
+        Ok(())
+    }
+
+    #[test]
+    fn test_installer_vendors_submodules() -> Result<()> {
+        let ctx = TestContext::new();
+        let shared = tempdir();
+        git_init(shared.path(), &[("common.txt", "common")]);
+        let upstream = tempdir();
+        let mut spec = get_spec(&ctx.preset, &upstream);
+        let git = git2::Repository::open(upstream.path())?;
+        let mut submodule = git.submodule(
+            &shared.path().to_string_lossy(),
+            Path::new("global/target/a/shared"),
+            true,
+        )?;
+        submodule.clone(None)?;
+        submodule.add_finalize()?;
+        git_commit(&git, &[], "add submodule");
+        let vendored = Path::new(&spec.vendor).join("global/target/a/shared/common.txt");
+
+        get_installer(&ctx.preset, &spec).install()?;
+        assert!(!vendored.exists());
+
+        spec.checkout.submodules = true;
+        let (_, metrics) = get_installer(&ctx.preset, &spec).update()?;
+        assert_eq!(0, metrics.failures);
+        assert_eq!("common", read_to_string(&vendored));
+
+        spec.deps[0].submodules = Some(false);
+        get_installer(&ctx.preset, &spec).update()?;
+        assert!(!vendored.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_installer_vendors_from_bare_cache() -> Result<()> {
+        let ctx = TestContext::new();
+        let upstream = tempdir();
+        let mut spec = get_spec(&ctx.preset, &upstream);
+        spec.bare_cache = true;
+        spec.read.respect_export_ignore = true;
+        let git = git2::Repository::open(upstream.path())?;
+        git_commit(
+            &git,
+            &[
+                (".gitattributes", "global/target/a/tests/** export-ignore\n"),
+                ("global/target/a/tests/fixture.txt", "fixture"),
+            ],
+            "tests",
+        );
+
+        let (spec_lock, metrics) = get_installer(&ctx.preset, &spec).install()?;
+
+        assert_eq!(0, metrics.failures);
+        let vendor = Path::new(&spec.vendor).join("global/target/a");
+        assert_eq!("data", read_to_string(&vendor.join("file.txt")));
+        assert!(!vendor.join("tests").exists());
+        let cached = Cache::new(&ctx.preset).get_cached_repository(&spec.deps[0])?;
+        assert!(cached.is_bare());
+        assert!(!cached.path().join("global").exists());
+        let worktrees = Path::new(&ctx.preset.cache()).join("worktrees");
+        assert_eq!(
+            0,
+            fs::read_dir(&worktrees)?.count(),
+            "worktree should be removed"
+        );
+
+        git_commit(&git, &[("global/target/a/file.txt", "changed")], "change");
+        get_installer(&ctx.preset, &spec).update()?;
+        assert_eq!("changed", read_to_string(&vendor.join("file.txt")));
+
+        Installer::new(Cache::new(&ctx.preset), &spec, spec_lock).install()?;
+        assert_eq!("data", read_to_string(&vendor.join("file.txt")));
+        Ok(())
+    }
+
+    #[test]
+    fn test_installer_vendors_from_local_bare_repository() -> Result<()> {
+        let ctx = TestContext::new();
+        let upstream = tempdir();
+        let git = git_init(upstream.path(), &[("global/target/a/file.txt", "data")]);
+        let head = git.head()?.peel_to_commit()?;
+        let mut expected = LockedDependency::new("", head.id().to_string());
+        expected.committed_at = Some(head.time().seconds());
+        expected.requested = Some("master".into());
+        let bare = tempdir();
+        git2::build::RepoBuilder::new()
+            .bare(true)
+            .clone(&upstream.path().to_string_lossy(), bare.path())?;
+
+        for url in [
+            bare.path().display().to_string(),
+            format!("file://{}", bare.path().display()),
+        ] {
+            let mut spec = Spec::with_preset(&ctx.preset);
+            spec.add_dependency(Dependency::new(&url, "master"));
+
+            let (spec_lock, metrics) = get_installer(&ctx.preset, &spec).update()?;
+
+            assert_eq!(0, metrics.failures, "{url}");
+            expected.url = url.clone();
+            assert_eq!(vec![expected.clone()], spec_lock.deps);
+            assert!(Path::new(&spec.vendor)
+                .join("global/target/a/file.txt")
+                .exists());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_installer_sequential_matches_parallel() -> Result<()> {
+        let ctx = TestContext::new();
+        let upstreams = [tempdir(), tempdir(), tempdir()];
+        let mut spec = Spec::with_preset(&ctx.preset);
+        for (n, upstream) in upstreams.iter().enumerate() {
+            let file = format!("global/target/a/file-{n}.txt");
+            git_init(upstream.path(), &[(&file, "data")]);
+            spec.add_dependency(Dependency::new(upstream.path().to_string_lossy(), "master"));
+        }
+
+        let (parallel, parallel_metrics) = get_installer(&ctx.preset, &spec).install()?;
+        let (sequential, sequential_metrics) = get_installer(&ctx.preset, &spec)
+            .with_jobs(Some(1))
+            .install()?;
+
+        assert_eq!(parallel, sequential);
+        assert_eq!(3, sequential.deps.len());
+        assert_eq!(parallel_metrics.files, sequential_metrics.files);
+        assert_eq!(0, sequential_metrics.failures);
+        Ok(())
+    }
+
+    #[test]
+    fn test_installer_sequential_reports_panics() -> Result<()> {
+        let ctx = TestContext::new();
+        let mut spec = Spec::with_preset(&ctx.preset);
+        spec.add_dependency(Dependency::new("panicking-url", "master"));
+        let sut = get_installer(&ctx.preset, &spec).with_jobs(Some(1));
+
+        let (_, metrics, _) = sut.execute(|_, _| panic!("boom"))?;
+
+        assert_eq!(1, metrics.failures);
+        Ok(())
+    }
+
+    #[test]
+    fn test_installer_skips_inactive_dependencies() -> Result<()> {
+        let ctx = TestContext::new();
+        let (included, excluded) = (tempdir(), tempdir());
+        git_init(included.path(), &[("global/target/a/included.txt", "data")]);
+        git_init(excluded.path(), &[("global/target/a/excluded.txt", "data")]);
+        let mut spec = Spec::with_preset(&ctx.preset);
+        for (upstream, when_env) in [
+            (&included, "VENDIFY_TEST_WHEN_ENV=linux"),
+            (&excluded, "VENDIFY_TEST_WHEN_ENV=macos"),
+        ] {
+            let mut dep = Dependency::new(upstream.path().to_string_lossy(), "master");
+            dep.when_env = Some(when_env.into());
+            spec.add_dependency(dep);
+        }
+        let excluded_url = excluded.path().to_string_lossy();
+        let mut spec_lock = SpecLock::with_preset(&ctx.preset);
+        spec_lock.add_locked_dependency(LockedDependency::new(&*excluded_url, "locked"));
+
+        let mut sut = Installer::new(Cache::new(&ctx.preset), &spec, spec_lock);
+        sut.deps = active_deps(spec.resolved_deps(), |name| {
+            (name == "VENDIFY_TEST_WHEN_ENV").then(|| "linux".into())
+        });
+        let (spec_lock, metrics) = sut.install()?;
+
+        assert_eq!(1, metrics.dependencies);
+        assert_eq!(0, metrics.failures);
+        assert_eq!(
+            "locked",
+            spec_lock
+                .get_locked_dependency(&Dependency::new(&*excluded_url, "master"))
+                .unwrap()
+                .refname
+        );
+        assert_eq!(2, spec_lock.deps.len());
+        let vendor = Path::new(&spec.vendor);
+        assert!(vendor.join("global/target/a/included.txt").exists());
+        assert!(!vendor.join("global/target/a/excluded.txt").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_installer_creates_keep_dirs() -> Result<()> {
+        let ctx = TestContext::new();
+        let upstream = tempdir();
+        let mut spec = get_spec(&ctx.preset, &upstream);
+        spec.keep_dirs = svec!["plugins", "global/target/a"];
+
+        get_installer(&ctx.preset, &spec).install()?;
+
+        let vendor = Path::new(&spec.vendor);
+        assert!(vendor.join("plugins").is_dir());
+        assert!(vendor.join("global/target/a/file.txt").exists());
+
+        spec.keep_dirs = svec!["../outside"];
+        let actual = get_installer(&ctx.preset, &spec).install();
+        assert_eq!(
+            "cannot keep directory ../outside, it must be relative to the vendor folder",
+            actual.expect_err("should fail").to_string()
+        );
+        assert!(vendor.join("plugins").is_dir());
+        Ok(())
+    }
+
+    #[test]
+    fn test_installer_shallow_since() -> Result<()> {
+        let ctx = TestContext::new();
+        let upstream = tempdir();
+        let git = git2::Repository::init_opts(
+            upstream.path(),
+            git2::RepositoryInitOptions::new().initial_head("master"),
+        )?;
+        let old = git_commit_at(
+            &git,
+            &[("global/target/a/old.txt", "old")],
+            "old",
+            1_577_836_800,
+        );
+        let new = git_commit_at(
+            &git,
+            &[("global/target/a/new.txt", "new")],
+            "new",
+            1_717_200_000,
+        );
+        let url = format!("file://{}", upstream.path().display());
+        let mut spec = Spec::with_preset(&ctx.preset);
+        spec.shallow_since = Some("2023-01-01".into());
+        spec.add_dependency(Dependency::new(&url, "master"));
+
+        let (spec_lock, _) = get_installer(&ctx.preset, &spec).update()?;
+
+        let mut expected = LockedDependency::new(&url, new.to_string());
+        expected.committed_at = Some(1_717_200_000);
+        expected.requested = Some("master".into());
+        assert_eq!(vec![expected], spec_lock.deps);
+        assert!(Path::new(&spec.vendor)
+            .join("global/target/a/old.txt")
+            .exists());
+        let cached = Cache::new(&ctx.preset).get_cached_repository(&spec.deps[0])?;
+        let cached = git2::Repository::open(cached.path())?;
+        assert!(cached.is_shallow());
+        assert!(cached.find_commit(old).is_err());
+
+        let mut spec_lock = SpecLock::with_preset(&ctx.preset);
+        spec_lock.add_locked_dependency(LockedDependency::new(&url, old.to_string()));
+        let sut = Installer::new(Cache::new(&ctx.preset), &spec, spec_lock);
+        let err = sut.inner_install(&spec.deps[0]).unwrap_err().to_string();
+        assert!(
+            err.starts_with(&format!("{old} is not reachable since 2023-01-01")),
+            "{err}"
+        );
+
+        let ctx = TestContext::new();
+        spec.shallow_since = Some("2025-01-01".into());
+        let sut = get_installer(&ctx.preset, &spec);
+        sut.cache.initialize()?;
+        let err = sut.inner_update(&spec.deps[0]).unwrap_err().to_string();
+        assert!(err.contains("it might have no commits since then"), "{err}");
+        Ok(())
+    }
+
+    #[test]
+    fn test_installer_reports_collisions() -> Result<()> {
+        let ctx = TestContext::new();
+        let (first, second) = (tempdir(), tempdir());
+        git_init(first.path(), &[("global/target/a/file.txt", "first")]);
+        git_init(second.path(), &[("global/target/a/file.txt", "second")]);
+        let mut spec = Spec::with_preset(&ctx.preset);
+        for upstream in [&first, &second] {
+            spec.add_dependency(Dependency::new(upstream.path().to_string_lossy(), "master"));
+        }
+        let vendored = Path::new(&spec.vendor).join("global/target/a/file.txt");
+        let (listed_first, listed_last) = if spec.deps[0].url == first.path().to_string_lossy() {
+            ("first", "second")
+        } else {
+            ("second", "first")
+        };
+
+        get_installer(&ctx.preset, &spec).install()?;
+        assert_eq!(listed_last, fs::read_to_string(&vendored)?);
+
+        spec.collisions = Collisions::Error;
+        let actual = get_installer(&ctx.preset, &spec).install();
+        assert_eq!(
+            format!(
+                "dependencies vendor the same files:\n\tglobal/target/a/file.txt is vendored by \
+                 {} and {}",
+                spec.deps[0].url, spec.deps[1].url
+            ),
+            actual.expect_err("should fail").to_string()
+        );
+        assert_eq!(listed_last, fs::read_to_string(&vendored)?);
+
+        spec.collisions = Collisions::FirstWins;
+        get_installer(&ctx.preset, &spec).install()?;
+        assert_eq!(listed_first, fs::read_to_string(&vendored)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_installer_vendors_same_url_under_different_names() -> Result<()> {
+        let ctx = TestContext::new();
+        let upstream = tempdir();
+        let git = git_init(upstream.path(), &[("global/target/a/file.txt", "one")]);
+        let v1 = git.head()?.peel_to_commit()?;
+        git.branch("v1", &v1, false)?;
+        let v2 = git_commit(&git, &[("global/target/a/file.txt", "two")], "two");
+        let url = upstream.path().to_string_lossy();
+        let mut spec = Spec::with_preset(&ctx.preset);
+        for (name, refname) in [("v1", "v1"), ("v2", "master")] {
+            let mut dep = Dependency::new(&*url, refname);
+            dep.name = Some(name.into());
+            dep.vendor_dir = Some(name.into());
+            spec.add_new_dependency(dep)?;
+        }
+
+        let (spec_lock, metrics) = get_installer(&ctx.preset, &spec).update()?;
+
+        assert_eq!(0, metrics.failures);
+        let vendor = Path::new(&spec.vendor);
+        for (name, contents, commit) in [("v1", "one", v1.id()), ("v2", "two", v2)] {
+            assert_eq!(
+                contents,
+                fs::read_to_string(vendor.join(name).join("global/target/a/file.txt"))?
+            );
+            let dep = spec.deps.iter().find(|dep| dep.name() == name).unwrap();
+            let locked = spec_lock.get_locked_dependency(dep).unwrap();
+            assert_eq!(commit.to_string(), locked.refname);
+            assert_eq!(Some(name), locked.name.as_deref());
+        }
+        assert_eq!(2, spec_lock.deps.len());
+        Ok(())
+    }
+
+    #[test]
+    fn test_installer_times_out_unresponsive_remotes() -> Result<()> {
+        let ctx = TestContext::new();
+        let upstream = tempdir();
+        let mut spec = get_spec(&ctx.preset, &upstream);
+        let unresponsive = TcpListener::bind("127.0.0.1:0")?;
+        let url = format!("git://{}/slow", unresponsive.local_addr()?);
+        spec.add_dependency(Dependency::new(&url, "master"));
+        spec.timeout_secs = 1;
+
+        let started = Instant::now();
+        let (spec_lock, metrics) = get_installer(&ctx.preset, &spec).update()?;
+
+        assert!(started.elapsed() < Duration::from_secs(30));
+        assert_eq!(1, metrics.failures);
+        assert_eq!(1, spec_lock.deps.len());
+        assert!(spec_lock
+            .get_locked_dependency(&Dependency::new(&url, "master"))
+            .is_none());
+
+        // The clone is left behind, and keeps the repository locked.
+        let lock_path =
+            Cache::new(&ctx.preset).get_repository_lock_path(&Dependency::new(&url, "master"))?;
+        assert!(!Lock::new(lock_path).try_acquire()?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_join_import_identifies_panicking_dependency() {
+        let dep = Dependency::new("panicking-url", "master");
+
+        let actual = thread::scope(|s| join_import::<Imported>(&dep, s.spawn(|| panic!("boom"))));
+
+        assert_eq!(
+            "panicking-url panicked: boom",
+            actual.expect_err("should fail").to_string()
+        );
+    }
+
+    #[test]
+    fn test_installer_offline_install_from_warm_cache() -> Result<()> {
+        let ctx = TestContext::new();
+        let upstream = tempdir();
+        let spec = get_spec(&ctx.preset, &upstream);
+        get_installer(&ctx.preset, &spec).install()?;
+
+        let (spec_lock, metrics) = get_installer(&ctx.preset, &spec)
+            .with_offline(true)
+            .install()?;
+
+        assert_eq!(0, metrics.failures);
+        assert_eq!(1, spec_lock.deps.len());
+        assert!(Path::new(&spec.vendor)
+            .join("global/target/a/file.txt")
+            .exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_installer_install_from_warm_cache_does_not_fetch() -> Result<()> {
+        let ctx = TestContext::new();
+        let upstream = tempdir();
+        let spec = get_spec(&ctx.preset, &upstream);
+        let (spec_lock, _) = get_installer(&ctx.preset, &spec).install()?;
+        fs::remove_dir_all(upstream.path())?;
+
+        let (_, metrics) = get_installer(&ctx.preset, &spec).install()?;
+        assert_eq!(1, metrics.failures, "fetches without a lock");
+
+        let (_, metrics) = Installer::new(Cache::new(&ctx.preset), &spec, spec_lock).install()?;
+        assert_eq!(0, metrics.failures);
+        assert!(Path::new(&spec.vendor)
+            .join("global/target/a/file.txt")
+            .exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_installer_offline_install_from_empty_cache() -> Result<()> {
+        let ctx = TestContext::new();
+        let upstream = tempdir();
+        let spec = get_spec(&ctx.preset, &upstream);
+        let sut = get_installer(&ctx.preset, &spec).with_offline(true);
+        sut.cache.initialize()?;
+
+        let actual = sut.inner_install(&spec.deps[0]);
+
+        let err = actual.expect_err("install should fail").to_string();
+        assert!(err.contains("is not available in the cache"), "{err}");
+        Ok(())
+    }
+
+    #[test]
+    fn test_installer_with_vendor_override() -> Result<()> {
+        let ctx = TestContext::new();
+        let upstream = tempdir();
+        let spec = get_spec(&ctx.preset, &upstream);
+        let root = tempdir();
+        let vendor = root.path().join("vendor-override");
+
+        get_installer(&ctx.preset, &spec)
+            .with_vendor(Some(vendor.clone()))
+            .install()?;
+
+        assert!(vendor.join("global/target/a/file.txt").exists());
+        assert!(!Path::new(&spec.vendor).exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_installer_export_patches_since_lock() -> Result<()> {
+        let ctx = TestContext::new();
+        let upstream = tempdir();
+        let spec = get_spec(&ctx.preset, &upstream);
+        let url = spec.deps[0].url.clone();
+        let (spec_lock, _) = get_installer(&ctx.preset, &spec).install()?;
+        let git = git2::Repository::open(upstream.path())?;
+        git_commit(&git, &[("a.txt", "a")], "Add a");
+        git_commit(&git, &[("b.txt", "b")], "Add b");
+        let patches = tempdir();
+
+        let sut = Installer::new(Cache::new(&ctx.preset), &spec, spec_lock);
+        let actual = sut.export_patches(&url, None, None, patches.path())?;
+
+        assert_eq!(
+            vec![
+                patches.path().join("0001-Add-a.patch"),
+                patches.path().join("0002-Add-b.patch")
+            ],
+            actual
+        );
+
+        let sut = get_installer(&ctx.preset, &spec);
+        let err = sut.export_patches(&url, None, None, patches.path());
+        assert!(err
+            .unwrap_err()
+            .to_string()
+            .ends_with("is not locked, the starting refname is required"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_installer_locks_checksums() -> Result<()> {
+        let ctx = TestContext::new();
+        let upstream = tempdir();
+        let mut spec = get_spec(&ctx.preset, &upstream);
+        let (spec_lock, _) = get_installer(&ctx.preset, &spec).install()?;
+        assert!(spec_lock.checksums.is_empty());
+
+        spec.locking.lock_checksums = true;
+        let (spec_lock, _) = get_installer(&ctx.preset, &spec).install()?;
+        assert_eq!(
+            vec!["global/target/a/file.txt"],
+            spec_lock.checksums.keys().collect::<Vec<_>>()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_installer_checks_local_modifications() -> Result<()> {
+        let ctx = TestContext::new();
+        let upstream = tempdir();
+        let mut spec = get_spec(&ctx.preset, &upstream);
+        spec.locking.lock_checksums = true;
+        let (spec_lock, _) = get_installer(&ctx.preset, &spec).install()?;
+        let vendored = Path::new(&spec.vendor).join("global/target/a/file.txt");
+        write_to(&vendored, "hand edited");
+
+        let locked = |checksums: &Checksums| {
+            let mut spec_lock = SpecLock::with_preset(&ctx.preset);
+            spec_lock.checksums = checksums.clone();
+            spec_lock
+        };
+
+        spec.local_modifications = Severity::Error;
+        let actual =
+            Installer::new(Cache::new(&ctx.preset), &spec, locked(&spec_lock.checksums)).install();
+        assert_eq!(
+            format!(
+                "{} has 1 local modifications, which are about to be overwritten",
+                spec.vendor
+            ),
+            actual.expect_err("should fail").to_string()
+        );
+        assert_eq!("hand edited", read_to_string(&vendored));
+
+        spec.local_modifications = Severity::Warn;
+        Installer::new(Cache::new(&ctx.preset), &spec, locked(&spec_lock.checksums)).install()?;
+        assert_eq!("data", read_to_string(&vendored));
+        Ok(())
+    }
+
+    #[test]
+    fn test_installer_resolves_relative_urls() -> Result<()> {
+        let ctx = TestContext::new();
+        let upstream = tempdir();
+        let upstream_repo = upstream.path().join("protos-common");
+        git_init(&upstream_repo, &[("global/target/a/file.txt", "data")]);
+        let mut spec = Spec::with_preset(&ctx.preset);
+        spec.base_url = Some(format!("{}/", upstream.path().display()));
+        spec.add_dependency(Dependency::new("protos-common", "master"));
+
+        let (spec_lock, _) = get_installer(&ctx.preset, &spec).install()?;
+
+        let url = upstream_repo.to_string_lossy();
+        assert_eq!(url, spec_lock.deps[0].url);
+        assert_eq!("protos-common", spec.deps[0].url);
+        assert!(Path::new(&spec.vendor)
+            .join("global/target/a/file.txt")
+            .exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_installer_tracks_default_branch() -> Result<()> {
+        let ctx = TestContext::new();
+        let upstream = tempdir();
+        let git = git_init(upstream.path(), &[("global/target/a/master.txt", "master")]);
+        let head = git.head()?.peel_to_commit()?;
+        git.branch("trunk", &head, false)?;
+        git.set_head("refs/heads/trunk")?;
+        let trunk = git_commit(&git, &[("global/target/a/trunk.txt", "trunk")], "trunk");
+        let mut spec = Spec::with_preset(&ctx.preset);
+        spec.add_dependency(Dependency::new(
+            upstream.path().to_string_lossy(),
+            "default",
+        ));
+
+        let (spec_lock, _) = get_installer(&ctx.preset, &spec).update()?;
+
+        assert_eq!(trunk.to_string(), spec_lock.deps[0].refname);
+        assert_eq!("default", spec.deps[0].refname);
+        assert!(Path::new(&spec.vendor)
+            .join("global/target/a/trunk.txt")
+            .exists());
+
+        let (spec_lock, _) = get_installer(&ctx.preset, &spec)
+            .with_offline(true)
+            .install()?;
+        assert_eq!(trunk.to_string(), spec_lock.deps[0].refname);
+        Ok(())
+    }
+
+    #[test]
+    fn test_installer_reports_changes_between_runs() -> Result<()> {
+        let ctx = TestContext::new();
+        let upstream = tempdir();
+        let mut spec = get_spec(&ctx.preset, &upstream);
+        spec.locking.lock_checksums = true;
+
+        let (_, metrics) = get_installer(&ctx.preset, &spec).update()?;
+        assert_eq!(vec!["global/target/a/file.txt"], metrics.changes.added);
+
+        let git = git2::Repository::open(upstream.path())?;
+        git_commit(
+            &git,
+            &[
+                ("global/target/a/file.txt", "changed"),
+                ("global/target/a/new.txt", "new"),
+            ],
+            "change",
+        );
+        let (_, metrics) = get_installer(&ctx.preset, &spec).update()?;
+
+        assert_eq!(
+            Changes {
+                added: vec!["global/target/a/new.txt".into()],
+                removed: vec![],
+                modified: vec!["global/target/a/file.txt".into()],
+            },
+            metrics.changes
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_installer_diff_against_fresh_install() -> Result<()> {
+        let ctx = TestContext::new();
+        let upstream = tempdir();
+        let spec = get_spec(&ctx.preset, &upstream);
+        let git = git2::Repository::open(upstream.path())?;
+        git_commit(&git, &[("global/target/a/other.txt", "other")], "other");
+        let (spec_lock, _) = get_installer(&ctx.preset, &spec).update()?;
+        let vendor = Path::new(&spec.vendor).join("global/target/a");
+        fs::remove_file(vendor.join("other.txt"))?;
+        fs::write(vendor.join("file.txt"), "modified")?;
+        fs::write(vendor.join("stray.txt"), "stray")?;
+
+        let changes = Installer::new(Cache::new(&ctx.preset), &spec, spec_lock).diff()?;
+
+        assert_eq!(
+            Changes {
+                added: vec!["global/target/a/other.txt".into()],
+                removed: vec!["global/target/a/stray.txt".into()],
+                modified: vec!["global/target/a/file.txt".into()],
+            },
+            changes
+        );
+        assert_eq!("modified", read_to_string(&vendor.join("file.txt")));
+        assert!(vendor.join("stray.txt").exists());
+        assert!(!vendor.join("other.txt").exists());
+        assert!(!sibling_path(Path::new(&spec.vendor), "diff").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_installer_diff_removes_the_scratch_folder_on_errors() -> Result<()> {
+        let ctx = TestContext::new();
+        let upstream = tempdir();
+        let spec = get_spec(&ctx.preset, &upstream);
+        let scratch = sibling_path(Path::new(&spec.vendor), "diff");
+        fs::create_dir_all(scratch.join(".git"))?;
+
+        let actual = get_installer(&ctx.preset, &spec).diff();
+
+        assert!(actual.is_err(), "the scratch folder cannot be replaced");
+        assert!(!scratch.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_installer_diff_of_up_to_date_vendor_is_empty() -> Result<()> {
+        let ctx = TestContext::new();
+        let upstream = tempdir();
+        let spec = get_spec(&ctx.preset, &upstream);
+        let (spec_lock, _) = get_installer(&ctx.preset, &spec).update()?;
+
+        let changes = Installer::new(Cache::new(&ctx.preset), &spec, spec_lock).diff()?;
+
+        assert!(changes.is_empty(), "{changes:?}");
+        Ok(())
+    }
+
+    #[test]
+    fn test_installer_warm_cache_then_install_from_cache() -> Result<()> {
+        let ctx = TestContext::new();
+        let upstream = tempdir();
+        let spec = get_spec(&ctx.preset, &upstream);
+
+        get_installer(&ctx.preset, &spec).warm_cache()?;
+
+        assert!(!Path::new(&spec.vendor).exists());
+        assert!(Cache::new(&ctx.preset)
+            .get_cached_repository(&spec.deps[0])
+            .is_ok());
+
+        fs::remove_dir_all(upstream.path())?;
+        let (spec_lock, metrics) = get_installer(&ctx.preset, &spec).install_from_cache()?;
+        assert_eq!(0, metrics.failures);
+        assert_eq!(1, spec_lock.deps.len());
+        assert!(Path::new(&spec.vendor)
+            .join("global/target/a/file.txt")
+            .exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_installer_offline_update_is_rejected() {
+        let ctx = TestContext::new();
+        let upstream = tempdir();
+        let spec = get_spec(&ctx.preset, &upstream);
+        let sut = get_installer(&ctx.preset, &spec).with_offline(true);
+
+        let actual = sut.update();
+
+        assert_eq!(
+            "cannot update dependencies in offline mode",
+            actual.expect_err("update should fail").to_string()
+        );
+    }
+
+    #[test]
+    fn test_installer_keeps_vendor_when_a_dependency_fails() -> Result<()> {
+        let ctx = TestContext::new();
+        let upstream = tempdir();
+        let mut spec = get_spec(&ctx.preset, &upstream);
+        get_installer(&ctx.preset, &spec).install()?;
+        let vendored = Path::new(&spec.vendor).join("global/target/a/file.txt");
+        assert!(vendored.exists());
+
+        spec.add_dependency(Dependency::new("/non/existent/repository", "master"));
+        let (_, metrics) = get_installer(&ctx.preset, &spec).install()?;
+
+        assert_eq!(1, metrics.failures);
+        assert!(vendored.exists());
+        assert!(!sibling_path(Path::new(&spec.vendor), "staging").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_installer_keeps_modification_time_of_unchanged_files() -> Result<()> {
+        let ctx = TestContext::new();
+        let upstream = tempdir();
+        let spec = get_spec(&ctx.preset, &upstream);
+        get_installer(&ctx.preset, &spec).install()?;
+        let vendored = Path::new(&spec.vendor).join("global/target/a/file.txt");
+        let modified = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1000);
+        fs::File::options()
+            .write(true)
+            .open(&vendored)?
+            .set_modified(modified)?;
+
+        get_installer(&ctx.preset, &spec).install()?;
+
+        assert_eq!(modified, fs::metadata(&vendored)?.modified()?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_swap_vendor_path() -> Result<()> {
+        let root = tempdir();
+        let vendor = root.path().join("vendor");
+        let staging = sibling_path(&vendor, "staging");
+        fs::create_dir_all(vendor.join(".git"))?;
+        write_to(vendor.join("old.txt"), "");
+        fs::create_dir_all(&staging)?;
+        write_to(staging.join("new.txt"), "");
+
+        swap_vendor_path(&staging, &vendor)?;
+
+        assert!(vendor.join(".git").exists());
+        assert!(vendor.join("new.txt").exists());
+        assert!(!vendor.join("old.txt").exists());
+        assert!(!staging.exists());
+        assert!(!sibling_path(&vendor, "previous").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_copy_all() -> Result<()> {
+        let root = tempdir();
+        let from = root.path().join("from");
+        fs::create_dir_all(from.join("a/b"))?;
+        write_to(from.join("a/b/file.txt"), "data");
+
+        copy_all(&from, &root.path().join("to"))?;
+
+        assert_eq!(
+            "data",
+            fs::read_to_string(root.path().join("to/a/b/file.txt"))?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_recreate_vendor_refuses_nested_git() {
+        let root = tempdir();
+        let vendor = root.path().join("vendor");
+        fs::create_dir_all(vendor.join(".git")).unwrap();
+        write_to(vendor.join("file.txt"), "");
+
+        let actual = recreate_vendor_path(&vendor, VendorGit::Refuse);
+
+        assert_eq!(
+            format!(
+                "vendor path '{}' contains a .git entry, refusing to delete it",
+                vendor.display()
+            ),
+            actual.expect_err("should refuse").to_string()
+        );
+        assert!(vendor.join(".git").exists());
+        assert!(vendor.join("file.txt").exists());
+    }
+
+    #[test]
+    fn test_recreate_vendor_preserves_nested_git() -> Result<()> {
+        let root = tempdir();
+        let vendor = root.path().join("vendor");
+        fs::create_dir_all(vendor.join(".git"))?;
+        fs::create_dir_all(vendor.join("dir"))?;
+        write_to(vendor.join(".git/HEAD"), "");
+        write_to(vendor.join("file.txt"), "");
+
+        recreate_vendor_path(&vendor, VendorGit::Preserve)?;
+
+        assert!(vendor.join(".git/HEAD").exists());
+        assert!(!vendor.join("dir").exists());
+        assert!(!vendor.join("file.txt").exists());
+        Ok(())
+    }
 
     #[test]
     fn test_ensure_vendor_empty_root() {
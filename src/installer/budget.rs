@@ -0,0 +1,122 @@
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use anyhow::format_err;
+use anyhow::Result;
+
+/// Files and bytes vendored so far by all the dependencies, which are
+/// checked against the limits of the spec as each file is copied. It is
+/// shared by the dependencies that are imported at the same time.
+///
+/// Each attempt to import a dependency spends from its own budget, see
+/// [`Budget::attempt`], so what a failed attempt spent can be released.
+#[derive(Debug, Default)]
+pub struct Budget {
+    max_files: Option<usize>,
+    max_bytes: Option<u64>,
+    total: Arc<Spent>,
+    spent: Spent,
+}
+
+#[derive(Debug, Default)]
+struct Spent {
+    files: AtomicUsize,
+    bytes: AtomicU64,
+}
+
+impl Budget {
+    pub fn new(max_files: Option<usize>, max_bytes: Option<u64>) -> Self {
+        Self {
+            max_files,
+            max_bytes,
+            ..Self::default()
+        }
+    }
+
+    /// Returns the budget of an attempt to import a dependency, which spends
+    /// from the same total.
+    pub fn attempt(&self) -> Self {
+        Self {
+            max_files: self.max_files,
+            max_bytes: self.max_bytes,
+            total: Arc::clone(&self.total),
+            spent: Spent::default(),
+        }
+    }
+
+    /// Gives back to the total what the attempt spent, when it failed and
+    /// its files are discarded.
+    pub fn release(&self) {
+        let files = self.spent.files.swap(0, Ordering::SeqCst);
+        let bytes = self.spent.bytes.swap(0, Ordering::SeqCst);
+        self.total.files.fetch_sub(files, Ordering::SeqCst);
+        self.total.bytes.fetch_sub(bytes, Ordering::SeqCst);
+    }
+
+    /// Accounts for a file of `size` bytes that has been copied, failing when
+    /// it exceeds the maximum files or bytes in total.
+    pub fn spend(&self, size: u64) -> Result<()> {
+        self.spent.files.fetch_add(1, Ordering::SeqCst);
+        self.spent.bytes.fetch_add(size, Ordering::SeqCst);
+        let files = self.total.files.fetch_add(1, Ordering::SeqCst) + 1;
+        if let Some(max) = self.max_files.filter(|max| files > *max) {
+            return Err(format_err!(
+                "the dependencies exceed the maximum of {max} files in total"
+            ));
+        }
+        let bytes = self
+            .total
+            .bytes
+            .fetch_add(size, Ordering::SeqCst)
+            .saturating_add(size);
+        if let Some(max) = self.max_bytes.filter(|max| bytes > *max) {
+            return Err(format_err!(
+                "the dependencies exceed the maximum of {max} bytes in total"
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_budget_spend() {
+        let sut = Budget::new(Some(2), Some(10));
+
+        assert!(sut.spend(4).is_ok());
+        assert!(sut.spend(6).is_ok());
+        assert_eq!(
+            "the dependencies exceed the maximum of 2 files in total",
+            sut.spend(0).unwrap_err().to_string()
+        );
+
+        let sut = Budget::new(None, Some(10));
+        assert!(sut.spend(10).is_ok());
+        assert_eq!(
+            "the dependencies exceed the maximum of 10 bytes in total",
+            sut.spend(1).unwrap_err().to_string()
+        );
+        assert!(Budget::default().spend(u64::MAX).is_ok());
+    }
+
+    #[test]
+    fn test_budget_release() {
+        let sut = Budget::new(Some(2), None);
+        let failed = sut.attempt();
+        assert!(failed.spend(1).is_ok());
+        assert!(failed.spend(1).is_ok());
+
+        failed.release();
+
+        let attempt = sut.attempt();
+        assert!(attempt.spend(1).is_ok());
+        assert!(attempt.spend(1).is_ok());
+        assert!(attempt.spend(1).is_err());
+    }
+}
@@ -1,33 +1,57 @@
+use std::cell::RefCell;
+use std::collections::BTreeSet;
 use std::fs;
+use std::io;
 use std::path::Path;
 use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::time::SystemTime;
 
 use anyhow::Result;
+use sha2::Digest;
+use sha2::Sha256;
 use walkdir::DirEntry;
 
 use super::selector::Selector;
+use crate::filters::FilterKind;
+use crate::filters::Filters;
+
+/// Tells apart the temporary files of the objects written at the same time.
+static NEXT_OBJECT: AtomicUsize = AtomicUsize::new(0);
 
 /// Returns an iterator of [`CollectedPath`].
 pub struct Collector {
     selector: Selector,
+
+    /// The targets and ignores that matched the paths walked by the last
+    /// [`Collector::collect`].
+    matched: RefCell<Filters>,
 }
 
 /// Represents a file that has been collected, it allows to copy the file
 /// from the source to the destination path.
 ///
 /// Collected paths are aware of the relative path in respect to the
-/// source folder.
+/// source folder, and of the one in respect to the destination folder,
+/// which only differ when the file is renamed.
 pub struct CollectedPath {
     pub src: PathBuf,
     pub src_rel: PathBuf,
+    pub dst_rel: PathBuf,
 }
 
 impl Collector {
     pub fn from(selector: Selector) -> Self {
-        Self { selector }
+        Self {
+            selector,
+            matched: RefCell::new(Filters::new()),
+        }
     }
 
     pub fn collect<P: AsRef<Path>>(&self, from: &P) -> impl Iterator<Item = CollectedPath> + '_ {
+        self.matched.replace(Filters::new());
         let from = from.as_ref().to_owned();
         let from_copy = from.clone();
         walkdir::WalkDir::new(&from)
@@ -38,8 +62,45 @@ impl Collector {
             .map(move |entry| CollectedPath::new(&from_copy, &entry))
     }
 
+    /// Returns the extensions of the files that are targeted and not
+    /// ignored, regardless of the extension filters.
+    pub fn targeted_extensions<P: AsRef<Path>>(&self, from: &P) -> BTreeSet<String> {
+        let from = from.as_ref();
+        walkdir::WalkDir::new(from)
+            .into_iter()
+            .filter_entry(|entry| {
+                entry.file_name() != ".git"
+                    && (!entry.path().is_dir() || self.selector.select_dir(relative(&from, entry)))
+            })
+            .filter_map(Result::ok)
+            .filter(|entry| entry.path().is_file())
+            .filter(|entry| self.selector.select_target(relative(&from, entry)))
+            .filter_map(|entry| {
+                let extension = entry.path().extension()?.to_str()?;
+                Some(extension.to_ascii_lowercase())
+            })
+            .collect()
+    }
+
+    /// Returns the targets and ignores that matched none of the paths walked
+    /// by the last [`Collector::collect`], see [`Selector::unmatched`].
+    pub fn unmatched(&self) -> Vec<FilterKind> {
+        self.selector.unmatched(&self.matched.borrow())
+    }
+
+    /// Returns the configured extensions.
+    pub fn extensions(&self) -> &[String] {
+        self.selector.extensions()
+    }
+
     fn select_entry<P: AsRef<Path>>(&self, from: &P, entry: &DirEntry) -> bool {
         let rel = relative(from, entry);
+        if !rel.as_os_str().is_empty() {
+            let mut matched = self.matched.borrow_mut();
+            for kind in self.selector.matching(&rel) {
+                matched.add(kind);
+            }
+        }
         if entry.path().is_dir() {
             self.selector.select_dir(&rel)
         } else {
@@ -59,19 +120,182 @@ impl CollectedPath {
         Self {
             src: entry.path().to_owned(),
             src_rel: relative(from, entry),
+            dst_rel: relative(from, entry),
         }
     }
 
-    /// Copies the collected file and its contents from the source to the
-    /// destination path
-    pub fn copy<P: AsRef<Path>>(&self, to: &P) -> Result<()> {
-        let to = to.as_ref().join(&self.src_rel);
+    /// Copies the collected file from the source to the destination path,
+    /// writing `contents` instead of the source ones when given. Returns the
+    /// number of bytes of the destination file.
+    ///
+    /// When the file in the `previous` folder, or the destination file
+    /// itself, already has the same contents, it is not written again so its
+    /// modification time is kept.
+    ///
+    /// With an `objects` folder, the file is hardlinked rather than copied
+    /// when its contents are kept as they are, see [`link_or_copy`].
+    pub fn copy<P: AsRef<Path>>(
+        &self,
+        to: &P,
+        contents: Option<&[u8]>,
+        previous: Option<&Path>,
+        objects: Option<&Path>,
+    ) -> Result<u64> {
+        let to = to.as_ref().join(&self.dst_rel);
         if let Some(parent) = to.parent() {
             fs::create_dir_all(parent)?;
+        }
+
+        let size = match contents {
+            Some(contents) => contents.len() as u64,
+            None => fs::metadata(&self.src)?.len(),
         };
-        fs::copy(&self.src, &to)?;
-        Ok(())
+        let previous = previous.map_or_else(|| to.clone(), |dir| dir.join(&self.dst_rel));
+        if self.has_same_contents(&previous, contents, size)? {
+            if previous != to {
+                fs::copy(&previous, &to)?;
+                set_modified(&to, fs::metadata(&previous)?.modified()?)?;
+            }
+            return Ok(size);
+        }
+
+        if objects.is_some() {
+            // The destination may be a link to an object of the cache, which
+            // must not be written through.
+            remove_file(&to)?;
+        }
+        match (contents, objects) {
+            (Some(contents), _) => fs::write(&to, contents)?,
+            (None, Some(objects)) => link_or_copy(&self.src, &to, objects)?,
+            (None, None) => {
+                fs::copy(&self.src, &to)?;
+            }
+        }
+        Ok(size)
+    }
+
+    /// Copies the file that was already vendored into the `previous` folder,
+    /// as its source did not change, keeping its modification time. Returns
+    /// the number of bytes of the destination file.
+    pub fn copy_unchanged<P: AsRef<Path>>(&self, to: &P, previous: &Path) -> Result<u64> {
+        let to = to.as_ref().join(&self.dst_rel);
+        if let Some(parent) = to.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let previous = previous.join(&self.dst_rel);
+        let size = fs::copy(&previous, &to)?;
+        set_modified(&to, fs::metadata(&previous)?.modified()?)?;
+        Ok(size)
+    }
+
+    /// Sets the modification time of the copied file.
+    pub fn set_modified<P: AsRef<Path>>(&self, to: &P, modified: SystemTime) -> Result<()> {
+        set_modified(&to.as_ref().join(&self.dst_rel), modified)
+    }
+
+    /// Returns whether `path` has the contents the file would be copied
+    /// with. Sizes are compared first, contents only when they match.
+    fn has_same_contents(&self, path: &Path, contents: Option<&[u8]>, size: u64) -> Result<bool> {
+        match fs::metadata(path) {
+            Ok(metadata) if metadata.is_file() && metadata.len() == size => {}
+            _ => return Ok(false),
+        }
+        let existing = fs::read(path)?;
+        Ok(match contents {
+            Some(contents) => existing == contents,
+            None => existing == fs::read(&self.src)?,
+        })
+    }
+}
+
+/// Hardlinks `to` to the copy of the `from` file in the `objects` folder,
+/// named after the checksum of its contents, copying it instead when the link
+/// cannot be created, as it happens across filesystems.
+///
+/// The source itself is never linked, as checking out another commit in the
+/// cached repository writes its files in place. Objects are written under a
+/// temporary name that is then renamed, and they are read-only, so editing a
+/// vendored file does not write through into the cache, nor into the other
+/// vendor folders linked to it. Objects are verified before being reused, an
+/// object whose contents do not match its name is written again.
+fn link_or_copy(from: &Path, to: &Path, objects: &Path) -> Result<()> {
+    let object = objects.join(object_name(from)?);
+    if !is_intact(&object) {
+        fs::create_dir_all(objects)?;
+        let part = object.with_extension(format!(
+            "{}.{}.part",
+            process::id(),
+            NEXT_OBJECT.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::copy(from, &part)?;
+        let mut permissions = fs::metadata(&part)?.permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(&part, permissions)?;
+        fs::rename(&part, &object)?;
+    }
+    match fs::hard_link(&object, to) {
+        Ok(()) => return Ok(()),
+        Err(err) if err.kind() == io::ErrorKind::CrossesDevices => {
+            log::debug!(
+                "{} is on another filesystem, copying it instead",
+                from.display()
+            );
+        }
+        Err(err) => {
+            log::debug!(
+                "cannot hardlink {}, copying it instead: {err}",
+                from.display()
+            );
+        }
     }
+    fs::copy(from, to)?;
+    Ok(())
+}
+
+/// Returns whether the object exists and its contents still have the
+/// checksum it is named after.
+fn is_intact(object: &Path) -> bool {
+    let Some(checksum) = object
+        .file_name()
+        .and_then(|name| name.to_str())
+        .and_then(|name| name.split('-').next())
+    else {
+        return false;
+    };
+    fs::read(object).is_ok_and(|contents| format!("{:x}", Sha256::digest(contents)) == checksum)
+}
+
+/// Returns the name of the object of a file, the checksum of its contents,
+/// along with its permissions on unix so executables get their own object.
+fn object_name(path: &Path) -> Result<String> {
+    let checksum = format!("{:x}", Sha256::digest(fs::read(path)?));
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mode = fs::metadata(path)?.permissions().mode() & 0o777;
+        Ok(format!("{checksum}-{mode:o}"))
+    }
+    #[cfg(not(unix))]
+    Ok(checksum)
+}
+
+fn remove_file(path: &Path) -> Result<()> {
+    match fs::remove_file(path) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Sets the modification time of a file, which can be read-only, as the
+/// copies of hardlinked objects are. Only its owner needs to set it on unix.
+fn set_modified(path: &Path, modified: SystemTime) -> Result<()> {
+    #[cfg(unix)]
+    let file = fs::File::open(path)?;
+    #[cfg(not(unix))]
+    let file = fs::File::options().write(true).open(path)?;
+    file.set_modified(modified)?;
+    Ok(())
 }
 
 fn relative<P: AsRef<Path>>(from: &P, entry: &DirEntry) -> PathBuf {
@@ -85,6 +309,9 @@ fn relative<P: AsRef<Path>>(from: &P, entry: &DirEntry) -> PathBuf {
 #[cfg(test)]
 mod tests {
 
+    use std::time::Duration;
+    use std::time::SystemTime;
+
     use super::*;
     use crate::test_utils::read_to_string;
     use crate::test_utils::tempdir;
@@ -103,13 +330,94 @@ mod tests {
         let sut = CollectedPath {
             src: from,
             src_rel: "path/file.txt".into(),
+            dst_rel: "path/file.txt".into(),
         };
-        sut.copy(&to_parent_dir)?;
+        let bytes = sut.copy(&to_parent_dir, None, None, None)?;
         assert!(expected_to.exists());
+        assert_eq!(9, bytes);
 
         let contents = read_to_string(&expected_to);
         assert_eq!("some-data", contents);
 
         Ok(())
     }
+
+    #[test]
+    fn test_collected_path_copy_keeps_unchanged_files() -> Result<()> {
+        let root = tempdir();
+        let from = root.path().join("src/file.txt");
+        fs::create_dir_all(from.parent().unwrap())?;
+        write_to(&from, "some-data");
+        let previous = root.path().join("previous");
+        fs::create_dir_all(&previous)?;
+        write_to(previous.join("file.txt"), "some-data");
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let set_modified = |path: &Path| {
+            let file = fs::File::options().write(true).open(path).unwrap();
+            file.set_modified(modified).unwrap();
+        };
+        set_modified(&previous.join("file.txt"));
+        let sut = CollectedPath {
+            src: from.clone(),
+            src_rel: "file.txt".into(),
+            dst_rel: "file.txt".into(),
+        };
+        let modified_of = |path: PathBuf| fs::metadata(path).unwrap().modified().unwrap();
+
+        let to = root.path().join("staging");
+        assert_eq!(9, sut.copy(&to, None, Some(&previous), None)?);
+        assert_eq!(modified, modified_of(to.join("file.txt")));
+
+        sut.copy(&previous, None, None, None)?;
+        assert_eq!(modified, modified_of(previous.join("file.txt")));
+
+        write_to(&from, "other-data");
+        sut.copy(&to, None, Some(&previous), None)?;
+        assert_eq!("other-data", read_to_string(&to.join("file.txt")));
+        assert_ne!(modified, modified_of(to.join("file.txt")));
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_collected_path_copy_hardlinks() -> Result<()> {
+        use std::os::unix::fs::MetadataExt;
+        use std::os::unix::fs::PermissionsExt;
+
+        let root = tempdir();
+        let from = root.path().join("src/file.txt");
+        fs::create_dir_all(from.parent().unwrap())?;
+        write_to(&from, "some-data");
+        let sut = CollectedPath {
+            src: from.clone(),
+            src_rel: "file.txt".into(),
+            dst_rel: "file.txt".into(),
+        };
+        let to = root.path().join("dst");
+        let objects = root.path().join("objects");
+        let inode = |path: &Path| fs::metadata(path).unwrap().ino();
+
+        assert_eq!(9, sut.copy(&to, None, None, Some(&objects))?);
+        let object = objects.join(object_name(&from)?);
+        assert_eq!(inode(&object), inode(&to.join("file.txt")));
+        assert_ne!(inode(&from), inode(&to.join("file.txt")));
+
+        // The source changing in place does not change the vendored file.
+        write_to(&from, "new-data!");
+        assert_eq!("some-data", read_to_string(&to.join("file.txt")));
+
+        sut.copy(&to, Some(b"other-data"), None, Some(&objects))?;
+        assert_eq!("other-data", read_to_string(&to.join("file.txt")));
+        assert_eq!("some-data", read_to_string(&object));
+
+        // Objects are read-only, and written again when they were modified.
+        assert!(fs::metadata(&object)?.permissions().readonly());
+        fs::set_permissions(&object, fs::Permissions::from_mode(0o644))?;
+        write_to(&object, "tampered");
+        write_to(&from, "some-data");
+        sut.copy(&to, None, None, Some(&objects))?;
+        assert_eq!("some-data", read_to_string(&to.join("file.txt")));
+        assert_eq!("some-data", read_to_string(&object));
+        Ok(())
+    }
 }
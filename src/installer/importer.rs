@@ -1,20 +1,88 @@
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
 use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::SystemTime;
 
+use anyhow::format_err;
 use anyhow::Result;
+use ignore::gitignore::Gitignore;
+use ignore::gitignore::GitignoreBuilder;
 
+use super::budget::Budget;
+use super::collector::CollectedPath;
 use super::collector::Collector;
+use super::rename;
 use super::selector::Selector;
+use super::transform;
 use crate::deps::Dependency;
 use crate::deps::LockedDependency;
+use crate::error::prefixed;
+use crate::error::unreachable;
+use crate::filters::FilterKind;
+use crate::lock::Lock;
+use crate::repository::is_default_refname;
+use crate::repository::semver_range;
+use crate::repository::version_file;
 use crate::repository::Repository;
+use crate::retry::Retry;
+use crate::spec::CheckoutOptions;
+use crate::spec::LockOptions;
+use crate::spec::ReadOptions;
+use crate::spec::Severity;
 use crate::spec::Spec;
+use crate::timeout;
+
+/// File at the root of a dependency listing, with the `.gitignore` syntax,
+/// the files that should not be vendored.
+const VENDOR_IGNORE: &str = ".vendorignore";
+
+/// Outcome of importing a dependency into the vendor folder.
+#[derive(Debug)]
+pub struct Imported {
+    pub locked: LockedDependency,
+    pub files: usize,
+    pub bytes: u64,
+
+    /// Targets and ignores that match no file of the dependency.
+    pub unmatched: Vec<FilterKind>,
+}
+
+/// Decides whether a file is vendored from its path, relative to the root of
+/// the dependency, and its contents as they are in the repository.
+pub type FileFilter = dyn Fn(&Path, &[u8]) -> bool + Send + Sync;
 
 pub struct Importer<'a> {
     dependency: &'a Dependency,
     dependency_lock: Option<&'a LockedDependency>,
     repository: &'a Repository,
     collector: Collector,
+    file_filter: Option<&'a FileFilter>,
+    rename_template: Option<String>,
+    exclude_binary: bool,
     to: PathBuf,
+    previous: Option<PathBuf>,
+    delta_from: Option<String>,
+    extension_mismatch: Severity,
+    case_collisions: Severity,
+    read: ReadOptions,
+    max_files: Option<usize>,
+    budget: Option<&'a Budget>,
+    locking: LockOptions,
+    checkout: CheckoutOptions,
+    source_comments: &'a BTreeMap<String, String>,
+    strip_trailing_whitespace: bool,
+    transform_extensions: &'a [String],
+    require_license_header: &'a [String],
+    reproducible: bool,
+    objects: Option<&'a Path>,
+    repository_lock: Option<&'a Arc<Lock>>,
+    shallow_since: Option<&'a str>,
+    timeout: Duration,
+    retry: Retry,
 }
 
 impl<'a> Importer<'a> {
@@ -23,60 +91,869 @@ impl<'a> Importer<'a> {
         dependency: &'a Dependency,
         dependency_lock: Option<&'a LockedDependency>,
         repository: &'a Repository,
-    ) -> Self {
-        let mut combined_filters = spec.filters.clone();
-        combined_filters.merge(&dependency.filters);
-        Self {
+    ) -> Result<Self> {
+        let selector = Selector::for_dependency(spec, dependency)?;
+        let rename_template = selector.filters().rename_template.clone();
+        let exclude_binary = selector.filters().exclude_binary;
+        let dependency_lock = dependency_lock.filter(|lock| {
+            let is_outdated = lock.is_outdated(dependency);
+            if is_outdated {
+                log::info!(
+                    "[{}] the spec asks for {}, ignoring the lock",
+                    dependency.name(),
+                    dependency.refname
+                );
+            }
+            !is_outdated
+        });
+        Ok(Self {
             dependency,
             dependency_lock,
             repository,
-            collector: Selector::from(combined_filters).into(),
+            collector: selector.into(),
+            file_filter: None,
+            rename_template,
+            exclude_binary,
             to: PathBuf::from(&spec.vendor),
-        }
+            previous: None,
+            delta_from: None,
+            extension_mismatch: spec.extension_mismatch,
+            case_collisions: spec.case_collisions,
+            read: spec.read,
+            max_files: spec.max_files_per_dependency,
+            budget: None,
+            locking: spec.locking,
+            checkout: CheckoutOptions {
+                submodules: dependency.submodules.unwrap_or(spec.checkout.submodules),
+                ..spec.checkout
+            },
+            source_comments: &spec.source_comments,
+            strip_trailing_whitespace: spec.strip_trailing_whitespace,
+            transform_extensions: &spec.transform_extensions,
+            require_license_header: &spec.require_license_header,
+            reproducible: spec.write.reproducible,
+            objects: None,
+            repository_lock: None,
+            shallow_since: spec.shallow_since.as_deref(),
+            timeout: Duration::from_secs(spec.timeout_secs),
+            retry: spec.retry,
+        })
+    }
+
+    /// Copies the files into `to` instead of the vendor folder of the spec.
+    pub fn with_vendor(mut self, to: impl Into<PathBuf>) -> Self {
+        self.to = to.into();
+        self
+    }
+
+    /// Consults `file_filter` for each file that passes the filters of the
+    /// spec and is not ignored, the file is only vendored when it returns
+    /// true.
+    pub fn with_file_filter(mut self, file_filter: Option<&'a FileFilter>) -> Self {
+        self.file_filter = file_filter;
+        self
+    }
+
+    /// Accounts the copied files in `budget`, which is shared with the other
+    /// dependencies, aborting the copy once its limits are exceeded.
+    pub fn with_budget(mut self, budget: &'a Budget) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+
+    /// Hardlinks the vendored files to copies of them in the `objects`
+    /// folder rather than copying them, see [`CollectedPath::copy`].
+    pub fn with_objects(mut self, objects: Option<&'a Path>) -> Self {
+        self.objects = objects;
+        self
+    }
+
+    /// Holds the lock of the repository until the git operations end, even
+    /// those left behind after a timeout, see [`timeout::run`].
+    pub fn with_repository_lock(mut self, lock: &'a Arc<Lock>) -> Self {
+        self.repository_lock = Some(lock);
+        self
+    }
+
+    /// Compares the files against the ones in `previous`, the files that did
+    /// not change keep their modification time.
+    pub fn with_previous(mut self, previous: impl Into<PathBuf>) -> Self {
+        self.previous = Some(previous.into());
+        self
+    }
+
+    /// Only reads the files that changed since the `delta_from` commit from
+    /// the repository, the others are copied from the `previous` folder when
+    /// they are there.
+    pub fn with_delta_from(mut self, delta_from: Option<String>) -> Self {
+        self.delta_from = delta_from;
+        self
     }
 
     /// Install copies the files of the dependency into the vendor folder.
-    /// It respects the dependency lock, when passed.
-    pub fn install(&self) -> Result<LockedDependency> {
-        let refname = self.get_locked_refname();
+    /// It respects the dependency lock, when passed. Nothing is fetched when
+    /// the locked commit is already in the cached repository, unless the
+    /// ancestry of the locked commit is checked.
+    pub fn install(&self) -> Result<Imported> {
+        let is_cached = !self.locking.check_ancestry
+            && self
+                .dependency_lock
+                .is_some_and(|lock| self.repository.has_commit(&lock.refname));
+        if !is_cached {
+            self.fetch_default_branch()?;
+        }
+        let refname = self.resolve_refname(self.get_locked_refname())?;
+
+        log::info!(
+            "[{}] installing {}@{}",
+            self.name(),
+            self.dependency.url,
+            refname
+        );
+        if is_cached {
+            log::debug!("[{}] {refname} is in the cache, not fetching", self.name());
+        } else {
+            let tracked = self.resolve_refname(&self.dependency.refname)?;
+            self.fetch_refname(&tracked)?;
+            self.warn_unreachable_lock(&tracked);
+        }
+        self.repository
+            .checkout(&refname)
+            .map_err(|err| match self.shallow_since {
+                Some(since) => prefixed(err, format!("{refname} is not reachable since {since}")),
+                None => err,
+            })?;
+        self.checkout_submodules(false)?;
+        self.verify_tree()?;
+        self.checkout_lfs(false)?;
+        self.import()
+    }
+
+    /// Fetches the refname of the dependency into the cached repository,
+    /// without copying any file.
+    pub fn warm(&self) -> Result<()> {
+        self.fetch_default_branch()?;
+        let refname = self.resolve_refname(&self.dependency.refname)?;
+
+        log::info!(
+            "[{}] warming cache for {}@{}",
+            self.name(),
+            self.dependency.url,
+            refname
+        );
+        self.fetch_refname(&refname)
+    }
 
-        log::info!("installing {}@{}", self.dependency.url, refname);
-        self.repository.fetch(&self.dependency.refname)?;
-        self.repository.checkout(refname)?;
+    /// Install copies the files of the dependency into the vendor folder,
+    /// without fetching from the git remote. The locked reference must
+    /// already be present in the cached repository.
+    pub fn install_offline(&self) -> Result<Imported> {
+        let refname = self.resolve_refname(self.get_locked_refname())?;
+
+        log::info!(
+            "[{}] installing {}@{} (offline)",
+            self.name(),
+            self.dependency.url,
+            refname
+        );
+        self.repository
+            .checkout(&refname)
+            .map_err(|err| prefixed(err, format!("cannot find {refname} in the cache")))?;
+        self.checkout_submodules(true)?;
+        self.verify_tree()?;
+        self.checkout_lfs(true)?;
         self.import()
     }
 
     /// Update fetches latest changes from the git remote, against the
     /// reference. Then it installs the dependency. This will ignore the
     /// lock file and generate a new lock with the updated reference.
-    pub fn update(&self) -> Result<LockedDependency> {
-        let refname = self.dependency.refname.as_str();
+    pub fn update(&self) -> Result<Imported> {
+        self.fetch_default_branch()?;
+        let refname = self.resolve_refname(&self.dependency.refname)?;
 
-        log::info!("updating {}@{}", self.dependency.url, refname);
-        self.repository.fetch(refname)?;
-        self.repository.reset(refname)?;
+        log::info!(
+            "[{}] updating {}@{}",
+            self.name(),
+            self.dependency.url,
+            refname
+        );
+        if self.is_indirect() {
+            self.repository.checkout(&refname)?;
+        } else {
+            self.fetch(&refname)?;
+            self.repository.reset(&refname)?;
+        }
+        self.checkout_submodules(false)?;
+        self.checkout_lfs(false)?;
         self.import()
     }
 
-    fn import(&self) -> Result<LockedDependency> {
-        self.copy_files()?;
-        let locked = self.get_locked_dependency()?;
-        log::info!("\t🔒 {}", locked.refname);
-        Ok(locked)
+    /// Exports the commits between `from` and `to` as numbered patch files
+    /// into `dir`, instead of importing a snapshot of the files.
+    pub fn export_patches(
+        &self,
+        from: &str,
+        to: &str,
+        dir: &Path,
+        offline: bool,
+    ) -> Result<Vec<PathBuf>> {
+        if !offline {
+            self.fetch_default_branch()?;
+        }
+        let to = self.resolve_refname(to)?;
+
+        log::info!(
+            "[{}] exporting patches of {} from {from} to {to}",
+            self.name(),
+            self.dependency.url,
+        );
+        if !offline {
+            self.fetch_refname(&self.resolve_refname(&self.dependency.refname)?)?;
+        }
+        self.repository.format_patches(from, &to, dir)
+    }
+
+    fn import(&self) -> Result<Imported> {
+        let (files, bytes, failed_files) = self.copy_files()?;
+        if files == 0 && failed_files.is_empty() {
+            self.check_extension_mismatch()?;
+        }
+        let unmatched = self.unmatched();
+        let mut locked = self.get_locked_dependency()?;
+        if !failed_files.is_empty() {
+            log::warn!(
+                "[{}] {} files could not be copied, the dependency is partially vendored",
+                self.name(),
+                failed_files.len()
+            );
+            locked.failed_files = failed_files;
+        }
+        log::info!("[{}] 🔒 {}", self.name(), locked.refname);
+        Ok(Imported {
+            locked,
+            files,
+            bytes,
+            unmatched,
+        })
+    }
+
+    /// Returns the targets and ignores that match no file, warning about the
+    /// ones of the dependency. The ones of the spec are shared by all the
+    /// dependencies, so they are reported by the installer.
+    fn unmatched(&self) -> Vec<FilterKind> {
+        let unmatched = self.collector.unmatched();
+        let filters = &self.dependency.filters;
+        for kind in &unmatched {
+            let patterns = match kind {
+                FilterKind::Target(_) => &filters.targets,
+                FilterKind::Ignore(_) => &filters.ignores,
+                FilterKind::Extension(_) => &filters.extensions,
+            };
+            for pattern in kind.patterns() {
+                if patterns.contains(pattern) {
+                    log::warn!(
+                        "[{}] {} '{pattern}' matches no files",
+                        self.name(),
+                        kind.name()
+                    );
+                }
+            }
+        }
+        unmatched
     }
 
-    fn copy_files(&self) -> Result<()> {
-        for collected in self.collector.collect(&self.repository.path()) {
+    /// Copies the collected files into the vendor folder, returns the number
+    /// of files and bytes copied, and the files that could not be copied
+    /// when `skip_failed_files` is set.
+    fn copy_files(&self) -> Result<(usize, u64, Vec<String>)> {
+        let is_export_ignored: Box<dyn Fn(&Path) -> bool> = if self.read.respect_export_ignore {
+            Box::new(self.repository.export_ignore()?)
+        } else {
+            Box::new(|_| false)
+        };
+        let vendor_ignore = if self.read.respect_vendor_ignore {
+            self.vendor_ignore()?
+        } else {
+            Gitignore::empty()
+        };
+        let source = self.source()?;
+        let modified = self.modified()?;
+
+        let to = self.vendor_dir(&self.to);
+        let previous = self.previous.as_deref().map(|dir| self.vendor_dir(dir));
+        let mut files = 0;
+        let mut bytes = 0;
+        let mut failed = vec![];
+        let mut renamed = BTreeMap::new();
+        let mut folded = BTreeMap::new();
+        let mut unlicensed = vec![];
+        let changed = self.changed_paths(previous.as_deref());
+        for mut collected in self.collector.collect(&self.repository.workdir()) {
+            if self.is_skipped(&collected, &is_export_ignored, &vendor_ignore)? {
+                continue;
+            }
+            if let Some(max) = self.max_files.filter(|max| files >= *max) {
+                return Err(format_err!(
+                    "{} exceeds the maximum of {max} files per dependency",
+                    self.dependency.url,
+                ));
+            }
+            if let Some(template) = &self.rename_template {
+                collected.dst_rel = self.rename(template, &collected.src_rel, &mut renamed)?;
+            }
+            self.check_case_collision(&collected.dst_rel, &mut folded)?;
+            if self.requires_license_header(&collected.src_rel, &collected.src)?
+                && !transform::has_license_header(&collected.src, self.require_license_header)?
+            {
+                unlicensed.push(collected.src_rel.to_string_lossy().to_string());
+            }
             log::debug!(
-                "\t.../{} -> {}",
+                "[{}] .../{} -> {}",
+                self.name(),
                 collected.src_rel.display(),
-                self.to.join(&collected.src_rel).display()
+                to.join(&collected.dst_rel).display()
             );
-            collected.copy(&self.to)?;
+            let unchanged = previous
+                .as_deref()
+                .filter(|previous| is_unchanged(&collected, changed.as_ref(), previous));
+            match self.copy_file(
+                &collected,
+                &to,
+                previous.as_deref(),
+                unchanged,
+                &source,
+                modified,
+            ) {
+                Ok(size) => {
+                    if let Some(budget) = self.budget {
+                        budget.spend(size)?;
+                    }
+                    bytes += size;
+                    files += 1;
+                }
+                Err(err) if self.read.skip_failed_files => {
+                    log::warn!(
+                        "[{}] cannot copy .../{}: {err}",
+                        self.name(),
+                        collected.src_rel.display()
+                    );
+                    failed.push(collected.src_rel.to_string_lossy().to_string());
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        if !unlicensed.is_empty() {
+            return Err(format_err!(
+                "{} has files without an approved license header: {}",
+                self.dependency.url,
+                unlicensed.join(", ")
+            ));
+        }
+        Ok((files, bytes, failed))
+    }
+
+    /// Returns the origin of the files written into the source comments, the
+    /// url and the refname of the dependency, or nothing without them.
+    fn source(&self) -> Result<String> {
+        if self.source_comments.is_empty() {
+            return Ok(String::new());
+        }
+        Ok(format!(
+            "{}@{}",
+            self.dependency.url,
+            self.repository.get_current_refname()?
+        ))
+    }
+
+    /// Returns the modification time of the vendored files, the time of the
+    /// commit when they are reproducible, otherwise they keep the time they
+    /// are copied.
+    fn modified(&self) -> Result<Option<SystemTime>> {
+        if !self.reproducible {
+            return Ok(None);
+        }
+        let committed_at = self.repository.get_current_commit_time()?;
+        let secs = u64::try_from(committed_at).unwrap_or_default();
+        Ok(Some(SystemTime::UNIX_EPOCH + Duration::from_secs(secs)))
+    }
+
+    /// Returns whether the collected file is skipped, as it is export-ignored,
+    /// vendor-ignored, binary while binary files are excluded, or filtered
+    /// out by the file filter.
+    fn is_skipped(
+        &self,
+        collected: &CollectedPath,
+        is_export_ignored: &dyn Fn(&Path) -> bool,
+        vendor_ignore: &Gitignore,
+    ) -> Result<bool> {
+        let name = self.name();
+        let path = collected.src_rel.display();
+        if is_export_ignored(&collected.src_rel) {
+            log::debug!("[{name}] .../{path} is export-ignored");
+        } else if vendor_ignore
+            .matched_path_or_any_parents(&collected.src_rel, false)
+            .is_ignore()
+        {
+            log::debug!("[{name}] .../{path} is vendor-ignored");
+        } else if self.exclude_binary && transform::is_binary(&collected.src)? {
+            log::debug!("[{name}] [BINARY] .../{path} is excluded");
+        } else if !self.is_filtered(&collected.src_rel, &collected.src)? {
+            log::debug!("[{name}] .../{path} is filtered out");
+        } else {
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
+    /// Writes the collected file into `to`, from the `unchanged` folder when
+    /// it did not change since it was vendored there, otherwise transformed
+    /// from its source. Returns the number of bytes written.
+    fn copy_file(
+        &self,
+        collected: &CollectedPath,
+        to: &Path,
+        previous: Option<&Path>,
+        unchanged: Option<&Path>,
+        source: &str,
+        modified: Option<SystemTime>,
+    ) -> Result<u64> {
+        let size = if let Some(unchanged) = unchanged {
+            collected.copy_unchanged(&to, unchanged)?
+        } else {
+            let contents = self.transform(&collected.src_rel, &collected.src, source)?;
+            collected.copy(&to, contents.as_deref(), previous, self.objects)?
+        };
+        if let Some(modified) = modified {
+            collected.set_modified(&to, modified)?;
+        }
+        Ok(size)
+    }
+
+    /// Returns the files that changed since the `delta_from` commit, when
+    /// there is a `previous` folder to copy the others from. Every file is
+    /// read from the repository when they cannot be told apart.
+    fn changed_paths(&self, previous: Option<&Path>) -> Option<BTreeSet<PathBuf>> {
+        let from = self.delta_from.as_deref()?;
+        previous?;
+        if !self.source_comments.is_empty()
+            || self.strip_trailing_whitespace
+            || self.rename_template.is_some()
+        {
+            log::debug!(
+                "[{}] reading every file, the files are transformed or renamed",
+                self.name()
+            );
+            return None;
+        }
+        let changed = self
+            .repository
+            .get_current_refname()
+            .and_then(|to| self.repository.changed_paths(from, &to));
+        match changed {
+            Ok(changed) => {
+                log::debug!(
+                    "[{}] {} files changed since {from}",
+                    self.name(),
+                    changed.len()
+                );
+                Some(changed.into_iter().collect())
+            }
+            Err(err) => {
+                log::debug!(
+                    "[{}] reading every file, cannot diff {from}: {err}",
+                    self.name()
+                );
+                None
+            }
+        }
+    }
+
+    /// Returns whether the file filter, if any, keeps the file.
+    fn is_filtered(&self, src_rel: &Path, src: &Path) -> Result<bool> {
+        match self.file_filter {
+            Some(file_filter) => Ok(file_filter(src_rel, &fs::read(src)?)),
+            None => Ok(true),
+        }
+    }
+
+    /// Renders the destination path of a file with the rename template,
+    /// failing when another file of the dependency was already renamed to
+    /// the same path, as one would overwrite the other.
+    fn rename(
+        &self,
+        template: &str,
+        src_rel: &Path,
+        renamed: &mut BTreeMap<PathBuf, PathBuf>,
+    ) -> Result<PathBuf> {
+        let dst_rel = rename::render(template, self.name(), &self.dependency.refname, src_rel)
+            .map_err(|err| format_err!("{}: {err}", self.dependency.url))?;
+        if let Some(other) = renamed.insert(dst_rel.clone(), src_rel.to_path_buf()) {
+            return Err(format_err!(
+                "{}: rename template '{template}' renames both {} and {} to {}",
+                self.dependency.url,
+                other.display(),
+                src_rel.display(),
+                dst_rel.display()
+            ));
+        }
+        Ok(dst_rel)
+    }
+
+    /// Reports when a file is vendored into a path that only differs in case
+    /// from the one of another file. Paths are only compared lowercased, they
+    /// are vendored as they are.
+    fn check_case_collision(
+        &self,
+        dst_rel: &Path,
+        folded: &mut BTreeMap<String, PathBuf>,
+    ) -> Result<()> {
+        let key = dst_rel.to_string_lossy().to_lowercase();
+        let Some(other) = folded.insert(key, dst_rel.to_path_buf()) else {
+            return Ok(());
+        };
+        let message = format!(
+            "{} and {} only differ in case, one overwrites the other on case-insensitive \
+             filesystems",
+            other.display(),
+            dst_rel.display()
+        );
+        match self.case_collisions {
+            Severity::Warn => {
+                log::warn!("[{}] {message}", self.name());
+                Ok(())
+            }
+            Severity::Error => Err(format_err!("{}: {message}", self.dependency.url)),
+        }
+    }
+
+    /// Reports when the targets match files, but the extension filters
+    /// exclude all of them, as it is likely a wrong extension config.
+    fn check_extension_mismatch(&self) -> Result<()> {
+        let Some(message) = self.extension_mismatch() else {
+            return Ok(());
+        };
+        match self.extension_mismatch {
+            Severity::Warn => {
+                log::warn!("[{}] {message}", self.name());
+                Ok(())
+            }
+            Severity::Error => Err(format_err!(message)),
+        }
+    }
+
+    fn extension_mismatch(&self) -> Option<String> {
+        let present = self
+            .collector
+            .targeted_extensions(&self.repository.workdir());
+        if present.is_empty() {
+            return None;
+        }
+        Some(format!(
+            "{}: the targets match files with extensions [{}], but none of the configured \
+             extensions [{}]",
+            self.dependency.url,
+            present.into_iter().collect::<Vec<_>>().join(", "),
+            self.collector.extensions().join(", ")
+        ))
+    }
+
+    /// Returns the contents of a file after applying the content transforms,
+    /// or `None` when no transform applies to it.
+    fn transform(&self, path: &Path, src: &Path, source: &str) -> Result<Option<Vec<u8>>> {
+        let header = self.source_header(path, source);
+        let strip = self.strip_trailing_whitespace && self.is_transformable(path);
+        if header.is_none() && !strip {
+            return Ok(None);
+        }
+
+        let mut contents = fs::read(src)?;
+        if !transform::is_text(&contents) {
+            return Ok(None);
+        }
+        if strip {
+            contents = transform::strip_trailing_whitespace(&contents);
+        }
+        if let Some(header) = header {
+            contents.splice(0..0, header.into_bytes());
+        }
+        Ok(Some(contents))
+    }
+
+    /// Returns whether the extension of the file is in the allow-list of
+    /// the content transforms.
+    fn is_transformable(&self, path: &Path) -> bool {
+        path.extension().is_some_and(|extension| {
+            self.transform_extensions
+                .iter()
+                .any(|allowed| extension.eq_ignore_ascii_case(allowed.as_str()))
+        })
+    }
+
+    /// Returns whether the file must carry an approved license header, as
+    /// headers are required and the file has one of the filtered extensions.
+    /// Without extensions in the filters, only the exact targets are
+    /// vendored, and every one of them that is text must carry one.
+    fn requires_license_header(&self, path: &Path, src: &Path) -> Result<bool> {
+        if self.require_license_header.is_empty() {
+            return Ok(false);
+        }
+        let extensions = self.collector.extensions();
+        if extensions.is_empty() {
+            return Ok(!transform::is_binary(src)?);
+        }
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_ascii_lowercase())
+            .unwrap_or_default();
+        Ok(extensions
+            .iter()
+            .any(|extension| name.ends_with(&format!(".{}", extension.to_ascii_lowercase()))))
+    }
+
+    /// Returns the header to prepend to a vendored file, when the comment
+    /// syntax of its extension is configured. Files are read from a checkout
+    /// of the dependency, which never carries the header already.
+    fn source_header(&self, path: &Path, source: &str) -> Option<String> {
+        let extension = path.extension()?.to_str()?;
+        let comment = self.source_comments.get(extension)?;
+        Some(format!("{comment} vendored from {source}, do not edit\n"))
+    }
+
+    /// Returns the folder of the dependency within the vendor folder `dir`,
+    /// see [`Dependency::vendor_dir`].
+    fn vendor_dir(&self, dir: &Path) -> PathBuf {
+        match &self.dependency.vendor_dir {
+            Some(vendor_dir) => dir.join(vendor_dir),
+            None => dir.to_path_buf(),
+        }
+    }
+
+    /// Short identifier used to prefix the log lines of the dependency, so
+    /// the output of concurrent imports stays attributable.
+    fn name(&self) -> &str {
+        self.dependency.name()
+    }
+
+    fn fetch(&self, refname: &str) -> Result<()> {
+        let what = format!("[{}] fetching {refname}", self.name());
+        self.retry
+            .run(&what, || {
+                let repository = Repository::new(self.repository.path());
+                let refname = refname.to_string();
+                let since = self.shallow_since.map(ToString::to_string);
+                timeout::run(
+                    &what,
+                    self.timeout,
+                    self.repository_lock.cloned(),
+                    move || match since {
+                        Some(since) => repository.shallow_fetch(&refname, &since),
+                        None => repository.fetch(&refname),
+                    },
+                )
+            })
+            .map_err(unreachable)
+    }
+
+    /// Loads the `.vendorignore` file at the root of the repository, nothing
+    /// is ignored when there is none.
+    fn vendor_ignore(&self) -> Result<Gitignore> {
+        let root = self.repository.workdir();
+        let path = root.join(VENDOR_IGNORE);
+        if !path.is_file() {
+            return Ok(Gitignore::empty());
+        }
+        let mut builder = GitignoreBuilder::new(root);
+        if let Some(err) = builder.add(&path) {
+            return Err(format_err!(
+                "{}: cannot load {VENDOR_IGNORE}: {err}",
+                self.dependency.url
+            ));
+        }
+        builder.build().map_err(|err| {
+            format_err!(
+                "{}: cannot load {VENDOR_IGNORE}: {err}",
+                self.dependency.url
+            )
+        })
+    }
+
+    /// Checks out the submodules of the checked out commit, when enabled.
+    /// Otherwise, the submodules checked out by previous runs are emptied.
+    fn checkout_submodules(&self, offline: bool) -> Result<()> {
+        if !self.checkout.submodules {
+            return self.repository.clear_submodules();
+        }
+        let what = format!("[{}] updating submodules", self.name());
+        self.retry
+            .run(&what, || {
+                let repository = Repository::new(self.repository.path());
+                timeout::run(
+                    &what,
+                    self.timeout,
+                    self.repository_lock.cloned(),
+                    move || repository.update_submodules(offline),
+                )
+            })
+            .map_err(|err| prefixed(err, &self.dependency.url))
+    }
+
+    /// Replaces the Git LFS pointers of the checked out tree with their
+    /// contents, when enabled and the tree contains pointers.
+    fn checkout_lfs(&self, offline: bool) -> Result<()> {
+        if !self.checkout.lfs || !self.repository.has_lfs_pointers()? {
+            return Ok(());
+        }
+        log::info!("[{}] materializing LFS files", self.name());
+        self.repository
+            .lfs_checkout(offline)
+            .map_err(|err| format_err!("{}: {err}", self.dependency.url))
+    }
+
+    /// Verifies that the checked out tree matches the locked one, when the
+    /// lock has recorded a tree.
+    fn verify_tree(&self) -> Result<()> {
+        let Some(expected) = self.dependency_lock.and_then(|lock| lock.tree.as_ref()) else {
+            return Ok(());
+        };
+        let actual = self.repository.get_current_tree()?;
+        if &actual != expected {
+            return Err(format_err!(
+                "{}: checked out tree {actual} does not match the locked tree {expected}",
+                self.dependency.url
+            ));
+        }
+        Ok(())
+    }
+
+    /// Returns whether the refname of the dependency is resolved from the
+    /// cached repository, as it is read from a version file or matched
+    /// against the tags, instead of being fetched by name.
+    fn is_indirect(&self) -> bool {
+        version_file(&self.dependency.refname).is_some()
+            || semver_range(&self.dependency.refname).is_some()
+    }
+
+    /// Warns when the locked commit is not reachable from the refname that
+    /// the dependency tracks, as it happens when the history is rewritten
+    /// upstream, which leaves the lock pinned to an abandoned commit.
+    fn warn_unreachable_lock(&self, tracked: &str) {
+        let Some(lock) = self.dependency_lock.filter(|_| self.locking.check_ancestry) else {
+            return;
+        };
+        if self.is_indirect() {
+            return;
+        }
+        match self.repository.is_ancestor(&lock.refname, tracked) {
+            Ok(true) => {}
+            Ok(false) => log::warn!(
+                "[{}] the locked commit {} is not reachable from {tracked}, it may have been \
+                 rewritten upstream",
+                self.name(),
+                lock.refname
+            ),
+            Err(err) => log::warn!(
+                "[{}] cannot check whether {} is reachable from {tracked}: {err}",
+                self.name(),
+                lock.refname
+            ),
+        }
+    }
+
+    /// Fetches the resolved refname of the dependency. The commits named by
+    /// a version file, or tagged, are fetched along with its default branch,
+    /// see [`Importer::fetch_default_branch`].
+    fn fetch_refname(&self, refname: &str) -> Result<()> {
+        if self.is_indirect() {
+            return Ok(());
+        }
+        self.fetch(refname)
+    }
+
+    /// Asks the remote for its default branch, when the dependency tracks it.
+    /// The default branch is also fetched when it has the version file of
+    /// the dependency, so the file is up to date, and the tags when they are
+    /// matched against a semver range.
+    fn fetch_default_branch(&self) -> Result<()> {
+        let version_file = version_file(&self.dependency.refname);
+        if !is_default_refname(&self.dependency.refname) && !self.is_indirect() {
+            return Ok(());
+        }
+        let what = format!("[{}] fetching the default branch", self.name());
+        let default_branch = self
+            .retry
+            .run(&what, || {
+                let repository = Repository::new(self.repository.path());
+                timeout::run(
+                    &what,
+                    self.timeout,
+                    self.repository_lock.cloned(),
+                    move || repository.fetch_default_branch(),
+                )
+            })
+            .map_err(unreachable)?;
+        if version_file.is_some() {
+            self.fetch(&default_branch)?;
+        }
+        if semver_range(&self.dependency.refname).is_some() {
+            let what = format!("[{}] fetching the tags", self.name());
+            self.retry
+                .run(&what, || {
+                    let repository = Repository::new(self.repository.path());
+                    timeout::run(
+                        &what,
+                        self.timeout,
+                        self.repository_lock.cloned(),
+                        move || repository.fetch_tags(),
+                    )
+                })
+                .map_err(unreachable)?;
         }
         Ok(())
     }
 
+    /// Returns the commit of the refname that the version file at `path`
+    /// names, the file is read from the default branch.
+    fn resolve_version_file(&self, path: &str) -> Result<String> {
+        let default_branch = self.repository.default_branch()?;
+        let contents = self.repository.read_file(&default_branch, path)?;
+        let named = contents.trim();
+        if named.is_empty() || named.contains(char::is_whitespace) {
+            return Err(format_err!(
+                "{path} does not name a refname, found '{named}'"
+            ));
+        }
+        let named = if self.checkout.case_insensitive_refs {
+            self.repository.resolve_refname_ignore_case(named)?
+        } else {
+            named.to_string()
+        };
+        log::info!("[{}] {path} names {named}", self.name());
+        self.repository
+            .resolve_commit(&named)
+            .map_err(|err| prefixed(err, format!("{path} names '{named}'")))
+    }
+
+    /// Returns the refname as spelled in the repository, when refnames are
+    /// resolved ignoring their case. The default refname is resolved into
+    /// the default branch of the remote, version files into the commit of the
+    /// refname they name, and semver ranges into the highest matching tag.
+    fn resolve_refname(&self, refname: &str) -> Result<String> {
+        if let Some(path) = version_file(refname) {
+            self.resolve_version_file(path)
+        } else if let Some(range) = semver_range(refname) {
+            let tag = self.repository.highest_tag(range)?;
+            log::info!("[{}] {range} resolves to {tag}", self.name());
+            Ok(tag)
+        } else if is_default_refname(refname) {
+            self.repository.default_branch()
+        } else if self.checkout.case_insensitive_refs {
+            self.repository.resolve_refname_ignore_case(refname)
+        } else {
+            Ok(refname.to_string())
+        }
+    }
+
     fn get_locked_refname(&self) -> &str {
         match self.dependency_lock {
             Some(it) => &it.refname,
@@ -86,6 +963,673 @@ impl<'a> Importer<'a> {
 
     fn get_locked_dependency(&self) -> Result<LockedDependency> {
         let refname = self.repository.get_current_refname()?;
-        Ok(self.dependency.to_locked_dependency(refname))
+        let mut locked = self.dependency.to_locked_dependency(refname);
+        locked.requested = match self.dependency_lock {
+            Some(lock) => lock.requested.clone(),
+            None => Some(self.dependency.refname.clone()),
+        };
+        locked.resolved = match self.dependency_lock {
+            Some(lock) => lock.resolved.clone(),
+            None if semver_range(&self.dependency.refname).is_some() => {
+                Some(self.resolve_refname(&self.dependency.refname)?)
+            }
+            None => None,
+        };
+        locked.committed_at = Some(self.repository.get_current_commit_time()?);
+        if self.locking.lock_trees {
+            locked.tree = Some(self.repository.get_current_tree()?);
+        }
+        Ok(locked)
+    }
+}
+
+/// Returns whether the file did not change since it was vendored into the
+/// `previous` folder, so it can be copied from there.
+fn is_unchanged(
+    collected: &CollectedPath,
+    changed: Option<&BTreeSet<PathBuf>>,
+    previous: &Path,
+) -> bool {
+    changed.is_some_and(|changed| {
+        !changed.contains(&collected.src_rel) && previous.join(&collected.dst_rel).is_file()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::filters::Filters;
+    use crate::repository::CloneOptions;
+    use crate::repository::Git;
+    use crate::svec;
+    use crate::test_utils::git_commit;
+    use crate::test_utils::git_commit_at;
+    use crate::test_utils::git_init;
+    use crate::test_utils::tempdir;
+    use crate::test_utils::TestContext;
+
+    fn get_upstream_files() -> Vec<(&'static str, &'static str)> {
+        vec![
+            (".gitattributes", "global/target/a/tests/** export-ignore\n"),
+            ("global/target/a/api.txt", "api"),
+            ("global/target/a/tests/fixture.txt", "fixture"),
+        ]
+    }
+
+    #[test]
+    fn test_importer_skips_export_ignored_files() -> Result<()> {
+        let ctx = TestContext::new();
+        let upstream = tempdir();
+        git_init(upstream.path(), &get_upstream_files());
+        let mut spec = Spec::with_preset(&ctx.preset);
+        spec.read.respect_export_ignore = true;
+        let dependency = Dependency::new("some-url", "master");
+        let repository = Repository::new(upstream.path());
+
+        let sut = Importer::new(&spec, &dependency, None, &repository)?;
+        assert_eq!((1, 3, vec![]), sut.copy_files()?);
+
+        let vendor = Path::new(&spec.vendor);
+        assert!(vendor.join("global/target/a/api.txt").exists());
+        assert!(!vendor.join("global/target/a/tests/fixture.txt").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_importer_skips_vendor_ignored_files() -> Result<()> {
+        let ctx = TestContext::new();
+        let upstream = tempdir();
+        git_init(
+            upstream.path(),
+            &[
+                (
+                    ".vendorignore",
+                    "/global/target/a/internal/\n*.tmp.txt\n!keep.tmp.txt\n",
+                ),
+                ("global/target/a/api.txt", "api"),
+                ("global/target/a/internal/nested/secret.txt", "secret"),
+                ("global/target/a/scratch.tmp.txt", "scratch"),
+                ("global/target/a/keep.tmp.txt", "keep"),
+            ],
+        );
+        let mut spec = Spec::with_preset(&ctx.preset);
+        let dependency = Dependency::new("some-url", "master");
+        let repository = Repository::new(upstream.path());
+
+        let sut = Importer::new(&spec, &dependency, None, &repository)?;
+        assert_eq!(4, sut.copy_files()?.0);
+
+        spec.read.respect_vendor_ignore = true;
+        fs::remove_dir_all(&spec.vendor)?;
+        let sut = Importer::new(&spec, &dependency, None, &repository)?;
+        assert_eq!(2, sut.copy_files()?.0);
+
+        let vendor = Path::new(&spec.vendor).join("global/target/a");
+        assert!(vendor.join("api.txt").exists());
+        assert!(vendor.join("keep.tmp.txt").exists());
+        assert!(!vendor.join("internal").exists());
+        assert!(!vendor.join("scratch.tmp.txt").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_importer_skips_failed_files() -> Result<()> {
+        let ctx = TestContext::new();
+        let upstream = tempdir();
+        git_init(
+            upstream.path(),
+            &[
+                ("global/target/a/api.txt", "api"),
+                ("global/target/a/broken.txt", "broken"),
+            ],
+        );
+        let mut spec = Spec::with_preset(&ctx.preset);
+        let dependency = Dependency::new("some-url", "master");
+        let repository = Repository::new(upstream.path());
+        let vendor = Path::new(&spec.vendor).join("global/target/a");
+        fs::create_dir_all(vendor.join("broken.txt/occupied"))?;
+
+        let sut = Importer::new(&spec, &dependency, None, &repository)?;
+        assert!(sut.import().is_err());
+
+        spec.read.skip_failed_files = true;
+        let sut = Importer::new(&spec, &dependency, None, &repository)?;
+        let imported = sut.import()?;
+
+        assert_eq!(1, imported.files);
+        assert_eq!(
+            svec!["global/target/a/broken.txt"],
+            imported.locked.failed_files
+        );
+        assert_eq!("api", fs::read_to_string(vendor.join("api.txt"))?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_importer_prepends_source_comments() -> Result<()> {
+        let ctx = TestContext::new();
+        let upstream = tempdir();
+        let git = git_init(
+            upstream.path(),
+            &[
+                ("global/target/a/api.proto", "syntax = \"proto3\";\n"),
+                ("global/target/a/notes.txt", "notes"),
+            ],
+        );
+        let head = git.head()?.peel_to_commit()?.id();
+        let mut spec = Spec::with_preset(&ctx.preset);
+        spec.filters.add(FilterKind::Extension(svec!["proto"]));
+        spec.source_comments.insert("proto".into(), "//".into());
+        let dependency = Dependency::new("some-url", "master");
+        let repository = Repository::new(upstream.path());
+
+        let sut = Importer::new(&spec, &dependency, None, &repository)?;
+        sut.copy_files()?;
+
+        let vendor = Path::new(&spec.vendor).join("global/target/a");
+        assert_eq!(
+            format!("// vendored from some-url@{head}, do not edit\nsyntax = \"proto3\";\n"),
+            fs::read_to_string(vendor.join("api.proto"))?
+        );
+        assert_eq!("notes", fs::read_to_string(vendor.join("notes.txt"))?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_importer_budget_charges_the_copied_bytes() -> Result<()> {
+        let ctx = TestContext::new();
+        let upstream = tempdir();
+        let contents = "syntax = \"proto3\";\n";
+        git_init(upstream.path(), &[("global/target/a/api.proto", contents)]);
+        let mut spec = Spec::with_preset(&ctx.preset);
+        spec.filters.add(FilterKind::Extension(svec!["proto"]));
+        spec.source_comments.insert("proto".into(), "//".into());
+        let dependency = Dependency::new("some-url", "master");
+        let repository = Repository::new(upstream.path());
+        let budget = Budget::new(None, Some(contents.len() as u64));
+
+        let actual = Importer::new(&spec, &dependency, None, &repository)?
+            .with_budget(&budget)
+            .copy_files();
+
+        assert!(
+            actual.is_err_and(|err| err.to_string().contains("maximum of 19 bytes")),
+            "the header is charged as well"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_importer_source_comments_are_prepended_once() -> Result<()> {
+        let ctx = TestContext::new();
+        let upstream = tempdir();
+        let git = git_init(
+            upstream.path(),
+            &[
+                (
+                    "global/target/a/api.proto",
+                    "// vendored from other-url@abc, do not edit\nsyntax = \"proto3\";\n",
+                ),
+                ("global/target/a/image.proto", "\0binary\0"),
+            ],
+        );
+        let head = git.head()?.peel_to_commit()?.id();
+        let mut spec = Spec::with_preset(&ctx.preset);
+        spec.filters.add(FilterKind::Extension(svec!["proto"]));
+        spec.source_comments.insert("proto".into(), "//".into());
+        let dependency = Dependency::new("some-url", "master");
+        let repository = Repository::new(upstream.path());
+
+        let sut = Importer::new(&spec, &dependency, None, &repository)?;
+        sut.copy_files()?;
+        sut.copy_files()?;
+
+        let vendor = Path::new(&spec.vendor).join("global/target/a");
+        assert_eq!(
+            format!(
+                "// vendored from some-url@{head}, do not edit\n// vendored from other-url@abc, \
+                 do not edit\nsyntax = \"proto3\";\n"
+            ),
+            fs::read_to_string(vendor.join("api.proto"))?,
+            "the upstream contents are kept as they are"
+        );
+        assert_eq!(
+            b"\0binary\0".to_vec(),
+            fs::read(vendor.join("image.proto"))?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_importer_strips_trailing_whitespace() -> Result<()> {
+        let ctx = TestContext::new();
+        let upstream = tempdir();
+        let clean = "message A {\n  string a = 1;\n}\n";
+        git_init(
+            upstream.path(),
+            &[
+                (
+                    "global/target/a/dirty.txt",
+                    "message A {  \n  string a = 1;\t\n}\n",
+                ),
+                ("global/target/a/clean.txt", clean),
+                ("global/target/a/binary.txt", "binary  \n\0"),
+                ("global/target/a/other.md", "other  \n"),
+            ],
+        );
+        let mut spec = Spec::with_preset(&ctx.preset);
+        spec.filters.extensions = svec!["txt", "md"];
+        spec.strip_trailing_whitespace = true;
+        spec.transform_extensions = svec!["txt"];
+        let dependency = Dependency::new("some-url", "master");
+        let repository = Repository::new(upstream.path());
+
+        let sut = Importer::new(&spec, &dependency, None, &repository)?;
+        sut.copy_files()?;
+
+        let vendor = Path::new(&spec.vendor).join("global/target/a");
+        assert_eq!(clean, fs::read_to_string(vendor.join("dirty.txt"))?);
+        assert_eq!(clean.as_bytes(), fs::read(vendor.join("clean.txt"))?);
+        assert_eq!(
+            b"binary  \n\0".to_vec(),
+            fs::read(vendor.join("binary.txt"))?
+        );
+        assert_eq!("other  \n", fs::read_to_string(vendor.join("other.md"))?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_importer_reports_extension_mismatch() -> Result<()> {
+        let ctx = TestContext::new();
+        let upstream = tempdir();
+        git_init(
+            upstream.path(),
+            &[
+                ("global/target/a/one.txt", "one"),
+                ("global/target/a/two.TXT", "two"),
+                ("global/other/three.yml", "three"),
+            ],
+        );
+        let mut spec = Spec::with_preset(&ctx.preset);
+        spec.filters.extensions = svec!["proto"];
+        let dependency = Dependency::new("some-url", "master");
+        let repository = Repository::new(upstream.path());
+
+        let sut = Importer::new(&spec, &dependency, None, &repository)?;
+        let expected = "some-url: the targets match files with extensions [txt], but none of the \
+                        configured extensions [proto]";
+        assert_eq!(Some(expected.to_string()), sut.extension_mismatch());
+        sut.install_offline()?;
+
+        spec.extension_mismatch = Severity::Error;
+        let sut = Importer::new(&spec, &dependency, None, &repository)?;
+        assert_eq!(expected, sut.install_offline().unwrap_err().to_string());
+
+        spec.filters.extensions = svec!["txt"];
+        let sut = Importer::new(&spec, &dependency, None, &repository)?;
+        assert_eq!(2, sut.install_offline()?.files);
+        Ok(())
+    }
+
+    #[test]
+    fn test_importer_verifies_locked_tree() -> Result<()> {
+        let ctx = TestContext::new();
+        let upstream = tempdir();
+        let git = git_init(upstream.path(), &get_upstream_files());
+        let tree = git.head()?.peel_to_tree()?.id().to_string();
+        let same_tree = git_commit(&git, &[], "same contents").to_string();
+        let other_tree = git_commit(&git, &[("other.txt", "other")], "other").to_string();
+        let mut spec = Spec::with_preset(&ctx.preset);
+        spec.locking.lock_trees = true;
+        let dependency = Dependency::new("some-url", "master");
+        let repository = Repository::new(upstream.path());
+
+        let mut lock = LockedDependency::new("some-url", same_tree);
+        lock.tree = Some(tree.clone());
+        let sut = Importer::new(&spec, &dependency, Some(&lock), &repository)?;
+        let imported = sut.install_offline()?;
+        assert_eq!(Some(tree.clone()), imported.locked.tree);
+
+        let mut lock = LockedDependency::new("some-url", &other_tree);
+        lock.tree = Some(tree.clone());
+        let sut = Importer::new(&spec, &dependency, Some(&lock), &repository)?;
+        let err = sut.install_offline().unwrap_err().to_string();
+        assert!(
+            err.ends_with(&format!("does not match the locked tree {tree}")),
+            "{err}"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_importer_locks_commit_time() -> Result<()> {
+        let ctx = TestContext::new();
+        let upstream = tempdir();
+        let git = git_init(upstream.path(), &get_upstream_files());
+        let commit = git_commit_at(&git, &[("other.txt", "other")], "other", 1_700_000_000);
+        let spec = Spec::with_preset(&ctx.preset);
+        let dependency = Dependency::new("some-url", "master");
+        let repository = Repository::new(upstream.path());
+
+        let lock = LockedDependency::new("some-url", commit.to_string());
+        let sut = Importer::new(&spec, &dependency, Some(&lock), &repository)?;
+        let imported = sut.install_offline()?;
+
+        assert_eq!(commit.to_string(), imported.locked.refname);
+        assert_eq!(Some(1_700_000_000), imported.locked.committed_at);
+        Ok(())
+    }
+
+    #[test]
+    #[ignore = "requires git-lfs to be installed"]
+    fn test_importer_materializes_lfs_files() -> Result<()> {
+        let git = |dir: &Path, args: &[&str]| {
+            let status = std::process::Command::new("git")
+                .args(args)
+                .current_dir(dir)
+                .status()
+                .expect("git is installed");
+            assert!(status.success(), "git {args:?} failed");
+        };
+        let ctx = TestContext::new();
+        let upstream = tempdir();
+        let upstream = upstream.path();
+        git(upstream, &["init", "--initial-branch", "master"]);
+        git(upstream, &["lfs", "install", "--local"]);
+        git(upstream, &["lfs", "track", "*.txt"]);
+        fs::create_dir_all(upstream.join("global/target/a"))?;
+        fs::write(upstream.join("global/target/a/large.txt"), "real contents")?;
+        git(upstream, &["add", "."]);
+        git(
+            upstream,
+            &[
+                "-c",
+                "user.name=test",
+                "-c",
+                "user.email=t@t",
+                "commit",
+                "-m",
+                "lfs",
+            ],
+        );
+
+        let cache = tempdir();
+        Git::clone(
+            &upstream.to_string_lossy(),
+            "master",
+            &CloneOptions::default(),
+            cache.path(),
+        )?;
+        let mut spec = Spec::with_preset(&ctx.preset);
+        spec.checkout.lfs = true;
+        let dependency = Dependency::new(upstream.to_string_lossy(), "master");
+        let repository = Repository::new(cache.path());
+        assert!(repository.has_lfs_pointers()?);
+
+        let sut = Importer::new(&spec, &dependency, None, &repository)?;
+        sut.install()?;
+
+        let vendored = Path::new(&spec.vendor).join("global/target/a/large.txt");
+        assert_eq!("real contents", fs::read_to_string(vendored)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_importer_aborts_when_exceeding_max_files() {
+        let ctx = TestContext::new();
+        let upstream = tempdir();
+        git_init(upstream.path(), &get_upstream_files());
+        let mut spec = Spec::with_preset(&ctx.preset);
+        spec.max_files_per_dependency = Some(1);
+        let dependency = Dependency::new("some-url", "master");
+        let repository = Repository::new(upstream.path());
+
+        let sut = Importer::new(&spec, &dependency, None, &repository).unwrap();
+        let actual = sut.copy_files();
+
+        assert_eq!(
+            "some-url exceeds the maximum of 1 files per dependency",
+            actual.unwrap_err().to_string()
+        );
+        let copied = walkdir::WalkDir::new(&spec.vendor)
+            .into_iter()
+            .filter_map(Result::ok)
+            .filter(|entry| entry.path().is_file())
+            .count();
+        assert_eq!(1, copied, "should stop copying once the limit is exceeded");
+    }
+
+    #[test]
+    fn test_importer_reports_unmatched_patterns() -> Result<()> {
+        let ctx = TestContext::new();
+        let upstream = tempdir();
+        git_init(upstream.path(), &get_upstream_files());
+        let spec = Spec::with_preset(&ctx.preset);
+        let mut dependency = Dependency::new("some-url", "master");
+        dependency
+            .filters
+            .add(FilterKind::Target(svec!["global/target/typo"]))
+            .add(FilterKind::Ignore(svec!["global/target/a/tests"]));
+        let repository = Repository::new(upstream.path());
+
+        let sut = Importer::new(&spec, &dependency, None, &repository)?;
+        let actual = sut.import()?;
+
+        assert_eq!(1, actual.files);
+        assert_eq!(
+            vec![
+                FilterKind::Target(svec!["global/target/typo"]),
+                FilterKind::Ignore(svec!["global/ignore/a"]),
+            ],
+            actual.unmatched
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_importer_renames_files_with_template() -> Result<()> {
+        let ctx = TestContext::new();
+        let upstream = tempdir();
+        git_init(upstream.path(), &get_upstream_files());
+        let spec = Spec::with_preset(&ctx.preset);
+        let mut dependency = Dependency::new("some-url", "master");
+        dependency.name = Some("api".into());
+        dependency.filters.rename_template = Some("{name}/{path}".into());
+        let repository = Repository::new(upstream.path());
+
+        let sut = Importer::new(&spec, &dependency, None, &repository)?;
+        assert_eq!(2, sut.copy_files()?.0);
+
+        let vendor = Path::new(&spec.vendor);
+        assert!(vendor.join("api/global/target/a/api.txt").exists());
+        assert!(vendor
+            .join("api/global/target/a/tests/fixture.txt")
+            .exists());
+        assert!(!vendor.join("global").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_importer_rejects_colliding_rename_template() -> Result<()> {
+        let ctx = TestContext::new();
+        let upstream = tempdir();
+        git_init(upstream.path(), &get_upstream_files());
+        let spec = Spec::with_preset(&ctx.preset);
+        let mut dependency = Dependency::new("some-url", "master");
+        dependency.filters.rename_template = Some("{name}.txt".into());
+        let repository = Repository::new(upstream.path());
+
+        let sut = Importer::new(&spec, &dependency, None, &repository)?;
+        let actual = sut.copy_files();
+
+        let actual = actual.unwrap_err().to_string();
+        assert!(
+            actual.starts_with("some-url: rename template '{name}.txt' renames both "),
+            "{actual}"
+        );
+        assert!(actual.ends_with(" to some-url.txt"), "{actual}");
+        Ok(())
+    }
+
+    #[test]
+    fn test_importer_excludes_binary_files() -> Result<()> {
+        let ctx = TestContext::new();
+        let upstream = tempdir();
+        git_init(
+            upstream.path(),
+            &[
+                ("global/target/a/api.txt", "syntax = \"proto3\";"),
+                ("global/target/a/image.txt", "\0binary\0"),
+            ],
+        );
+        let spec = Spec::with_preset(&ctx.preset);
+        let mut dependency = Dependency::new("some-url", "master");
+        dependency.filters.exclude_binary = true;
+        let repository = Repository::new(upstream.path());
+
+        let sut = Importer::new(&spec, &dependency, None, &repository)?;
+        assert_eq!((1, 18, vec![]), sut.copy_files()?);
+
+        let vendor = Path::new(&spec.vendor).join("global/target/a");
+        assert!(vendor.join("api.txt").exists());
+        assert!(!vendor.join("image.txt").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_importer_skips_hidden_files_unless_included() -> Result<()> {
+        let ctx = TestContext::new();
+        let upstream = tempdir();
+        git_init(
+            upstream.path(),
+            &[
+                ("global/target/a/api.txt", "api"),
+                ("global/target/a/.github/workflows/ci.txt", "ci"),
+            ],
+        );
+        let spec = Spec::with_preset(&ctx.preset);
+        let mut dependency = Dependency::new("some-url", "master");
+        let repository = Repository::new(upstream.path());
+        let ci = Path::new(&spec.vendor).join("global/target/a/.github/workflows/ci.txt");
+
+        let sut = Importer::new(&spec, &dependency, None, &repository)?;
+        assert_eq!((1, 3, vec![]), sut.copy_files()?);
+        assert!(!ci.exists());
+
+        dependency.filters.include_hidden = true;
+        let sut = Importer::new(&spec, &dependency, None, &repository)?;
+        assert_eq!((2, 5, vec![]), sut.copy_files()?);
+        assert!(ci.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_importer_requires_license_headers() -> Result<()> {
+        let ctx = TestContext::new();
+        let upstream = tempdir();
+        git_init(
+            upstream.path(),
+            &[
+                (
+                    "global/target/a/licensed.txt",
+                    "// SPDX-License-Identifier: MIT\n",
+                ),
+                ("global/target/a/unlicensed.txt", "no license\n"),
+            ],
+        );
+        let mut spec = Spec::with_preset(&ctx.preset);
+        spec.require_license_header = svec!["SPDX-License-Identifier: MIT"];
+        let dependency = Dependency::new("some-url", "master");
+        let repository = Repository::new(upstream.path());
+
+        let sut = Importer::new(&spec, &dependency, None, &repository)?;
+        assert_eq!(
+            "some-url has files without an approved license header: global/target/a/unlicensed.txt",
+            sut.copy_files().unwrap_err().to_string()
+        );
+
+        spec.require_license_header.push("no license".into());
+        let sut = Importer::new(&spec, &dependency, None, &repository)?;
+        assert_eq!(2, sut.copy_files()?.0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_importer_requires_license_headers_without_extensions() -> Result<()> {
+        let ctx = TestContext::new();
+        let upstream = tempdir();
+        git_init(
+            upstream.path(),
+            &[
+                ("LICENSE", "SPDX-License-Identifier: MIT\n"),
+                ("scripts/run.sh", "make\n"),
+                ("logo.png", "\0binary"),
+            ],
+        );
+        let mut spec = Spec::with_preset(&ctx.preset);
+        spec.filters = Filters::new();
+        spec.filters.targets = svec!["LICENSE", "scripts/run.sh", "logo.png"];
+        spec.require_license_header = svec!["SPDX-License-Identifier: MIT"];
+        let dependency = Dependency::new("some-url", "master");
+        let repository = Repository::new(upstream.path());
+
+        let sut = Importer::new(&spec, &dependency, None, &repository)?;
+
+        assert_eq!(
+            "some-url has files without an approved license header: scripts/run.sh",
+            sut.copy_files().unwrap_err().to_string()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_importer_reports_case_collisions() -> Result<()> {
+        let ctx = TestContext::new();
+        let upstream = tempdir();
+        git_init(
+            upstream.path(),
+            &[
+                ("global/target/a/Api/Foo.txt", "upper"),
+                ("global/target/a/api/foo.txt", "lower"),
+            ],
+        );
+        let mut spec = Spec::with_preset(&ctx.preset);
+        let dependency = Dependency::new("some-url", "master");
+        let repository = Repository::new(upstream.path());
+
+        let sut = Importer::new(&spec, &dependency, None, &repository)?;
+        assert_eq!((2, 10, vec![]), sut.copy_files()?);
+
+        spec.case_collisions = Severity::Error;
+        let sut = Importer::new(&spec, &dependency, None, &repository)?;
+        let actual = sut.copy_files().unwrap_err().to_string();
+        assert!(actual.starts_with("some-url: global/target/a/"), "{actual}");
+        assert!(
+            actual.ends_with(
+                " only differ in case, one overwrites the other on case-insensitive filesystems"
+            ),
+            "{actual}"
+        );
+        assert!(actual.contains("Api/Foo.txt"), "{actual}");
+        assert!(actual.contains("api/foo.txt"), "{actual}");
+        Ok(())
+    }
+
+    #[test]
+    fn test_importer_copies_export_ignored_files_by_default() -> Result<()> {
+        let ctx = TestContext::new();
+        let upstream = tempdir();
+        git_init(upstream.path(), &get_upstream_files());
+        let spec = Spec::with_preset(&ctx.preset);
+        let dependency = Dependency::new("some-url", "master");
+        let repository = Repository::new(upstream.path());
+
+        let sut = Importer::new(&spec, &dependency, None, &repository)?;
+        assert_eq!((2, 10, vec![]), sut.copy_files()?);
+
+        let vendor = Path::new(&spec.vendor);
+        assert!(vendor.join("global/target/a/tests/fixture.txt").exists());
+        Ok(())
     }
 }
@@ -0,0 +1,95 @@
+use std::path::Component;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::format_err;
+use anyhow::Result;
+
+/// Renders the destination of a vendored file from a template, replacing
+/// `{name}` with the name of the dependency, `{ref}` with its refname and
+/// `{path}` with the path of the file within the repository.
+pub fn render(template: &str, name: &str, refname: &str, path: &Path) -> Result<PathBuf> {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        rendered.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .map(|end| start + end)
+            .ok_or_else(|| format_err!("rename template '{template}' has an unclosed '{{'"))?;
+        match &rest[start + 1..end] {
+            "name" => rendered.push_str(name),
+            "ref" => rendered.push_str(refname),
+            "path" => rendered.push_str(&path.to_string_lossy()),
+            other => {
+                return Err(format_err!(
+                    "rename template '{template}' has unknown placeholder '{{{other}}}', expected \
+                     {{name}}, {{ref}} or {{path}}"
+                ))
+            }
+        }
+        rest = &rest[end + 1..];
+    }
+    rendered.push_str(rest);
+
+    let rendered = PathBuf::from(rendered);
+    let is_relative = rendered
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+    if !is_relative || rendered.file_name().is_none() {
+        return Err(format_err!(
+            "rename template '{template}' renders '{}' for {}, it must be a file path relative to \
+             the vendor folder",
+            rendered.display(),
+            path.display()
+        ));
+    }
+    Ok(rendered)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_render() -> Result<()> {
+        let path = Path::new("protos/api.proto");
+
+        assert_eq!(
+            PathBuf::from("dep/v1.0.0/protos/api.proto"),
+            render("{name}/{ref}/{path}", "dep", "v1.0.0", path)?
+        );
+        assert_eq!(
+            PathBuf::from("protos/api.proto"),
+            render("{path}", "dep", "v1.0.0", path)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_rejects_invalid_templates() {
+        let path = Path::new("api.proto");
+
+        assert_eq!(
+            "rename template '{name}/{file}' has unknown placeholder '{file}', expected {name}, \
+             {ref} or {path}",
+            render("{name}/{file}", "dep", "master", path)
+                .unwrap_err()
+                .to_string()
+        );
+        assert_eq!(
+            "rename template '{name' has an unclosed '{'",
+            render("{name", "dep", "master", path)
+                .unwrap_err()
+                .to_string()
+        );
+        assert_eq!(
+            "rename template '../{path}' renders '../api.proto' for api.proto, it must be a file \
+             path relative to the vendor folder",
+            render("../{path}", "dep", "master", path)
+                .unwrap_err()
+                .to_string()
+        );
+    }
+}
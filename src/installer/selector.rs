@@ -1,16 +1,109 @@
+use std::fmt;
+use std::fmt::Display;
 use std::path::Path;
+use std::path::PathBuf;
+use std::path::MAIN_SEPARATOR;
 
+use anyhow::format_err;
+use anyhow::Result;
+use regex::Regex;
+
+use crate::deps::Dependency;
+use crate::filters::FilterKind;
 use crate::filters::Filters;
+use crate::spec::EmptyTargets;
+use crate::spec::Spec;
+
+/// Prefix of the targets and ignores that are matched as a regular expression
+/// against the whole path, instead of as a path prefix.
+const REGEX_PREFIX: &str = "regex:";
+
+/// Whether the filters copy a file, along with the reason, see
+/// [`Selector::explain`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum CopyDecision {
+    /// Copied as it matches the target, or as everything is targeted when
+    /// there are no targets.
+    Copied(Option<String>),
+
+    /// Not copied as it matches the ignore.
+    Ignored(String),
+
+    /// Not copied as it is hidden, and no target names it.
+    Hidden,
+
+    /// Not copied as it matches none of the targets.
+    NotTargeted,
+
+    /// Not copied as its extension is none of the extensions.
+    WrongExtension(Vec<String>),
+}
+
+impl Display for CopyDecision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Copied(Some(target)) => write!(f, "copied, it matches the target '{target}'"),
+            Self::Copied(None) => write!(f, "copied, there are no targets"),
+            Self::Ignored(ignore) => write!(f, "not copied, it matches the ignore '{ignore}'"),
+            Self::Hidden => write!(f, "not copied, it is hidden and no target names it"),
+            Self::NotTargeted => write!(f, "not copied, it matches none of the targets"),
+            Self::WrongExtension(extensions) => write!(
+                f,
+                "not copied, its extension is none of {}",
+                extensions.join(", ")
+            ),
+        }
+    }
+}
 
 /// Selects file or directory paths depending on whether the paths are allowed
 /// based on the provided filters.
 pub struct Selector {
     filters: Filters,
+    target_regexes: Vec<Regex>,
+    ignore_regexes: Vec<Regex>,
+    empty_targets: EmptyTargets,
 }
 
 impl Selector {
-    pub fn from(filters: Filters) -> Self {
-        Self { filters }
+    /// Builds a selector, compiling the regex targets and ignores upfront.
+    ///
+    /// Patterns prefixed with `regex:` are kept apart from the prefix ones.
+    /// Without targets, everything or nothing is targeted depending on
+    /// `empty_targets`. Extensions are accepted with or without their
+    /// leading dot.
+    pub fn new(mut filters: Filters, empty_targets: EmptyTargets) -> Result<Self> {
+        for extension in &mut filters.extensions {
+            *extension = extension.trim_start_matches('.').to_string();
+        }
+        let target_regexes = Self::compile(&mut filters.targets, "targets")?;
+        let ignore_regexes = Self::compile(&mut filters.ignores, "ignores")?;
+        Ok(Self {
+            filters,
+            target_regexes,
+            ignore_regexes,
+            empty_targets,
+        })
+    }
+
+    /// Builds the selector of the dependency, from the filters of the spec
+    /// merged with the ones of the dependency.
+    pub fn for_dependency(spec: &Spec, dependency: &Dependency) -> Result<Self> {
+        let mut filters = spec.filters.clone();
+        filters.merge(&dependency.filters);
+        if filters.is_empty() {
+            log::warn!(
+                "{}: has no filters, no files will be vendored",
+                dependency.url
+            );
+        }
+        Self::new(filters, spec.empty_targets)
+            .map_err(|err| format_err!("{}: {err}", dependency.url))
+    }
+
+    /// Returns the filters, with the extensions stripped of their dot.
+    pub fn filters(&self) -> &Filters {
+        &self.filters
     }
 
     /// Returns whether the path should be selected based on the filters.
@@ -19,7 +112,42 @@ impl Selector {
     /// If the filepath is a target, and has allowed extension, then select.
     pub fn select_file<P: AsRef<Path>>(&self, path: P) -> bool {
         let path = path.as_ref();
-        !self.is_ignored(path) && self.is_target(path) && self.is_extension(path)
+        !self.is_ignored(path)
+            && !self.is_hidden(path)
+            && self.is_target(path)
+            && self.is_extension(path)
+    }
+
+    /// Returns whether the path would be selected by [`Selector::select_file`],
+    /// with the target or the ignore that decides it.
+    pub fn explain<P: AsRef<Path>>(&self, path: P) -> CopyDecision {
+        let path = path.as_ref();
+        if let Some(ignore) = self.matched_ignore(path) {
+            return CopyDecision::Ignored(ignore);
+        }
+        if self.is_hidden(path) {
+            return CopyDecision::Hidden;
+        }
+        let target = self.matched_target(path);
+        if target.is_none() && !self.targets_all() {
+            return CopyDecision::NotTargeted;
+        }
+        if !self.is_extension(path) {
+            return CopyDecision::WrongExtension(self.filters.extensions.clone());
+        }
+        CopyDecision::Copied(target)
+    }
+
+    /// Returns whether the path is targeted and not ignored, regardless of
+    /// its extension.
+    pub fn select_target<P: AsRef<Path>>(&self, path: P) -> bool {
+        let path = path.as_ref();
+        !self.is_ignored(path) && !self.is_hidden(path) && self.is_target(path)
+    }
+
+    /// Returns the configured extensions.
+    pub fn extensions(&self) -> &[String] {
+        &self.filters.extensions
     }
 
     /// Returns whether the directory path should be selected based on the
@@ -48,57 +176,187 @@ impl Selector {
         }
 
         !self.is_ignored(dir)
-            && (self.filters.targets.is_empty()
-                || Self::inverse_has_prefix(
-                    &self.filters.targets,
-                    &dir.to_path_buf()
-                        .into_os_string()
-                        .into_string()
-                        .unwrap_or(String::new()),
-                ))
+            && !self.is_hidden(dir)
+            && (self.targets_all()
+                || !self.target_regexes.is_empty()
+                || Self::inverse_has_prefix(&self.filters.targets, &slashed(dir)))
     }
 
     /// Returns if the path is targeted.
     ///
-    /// If there are no explicit targets, everything is a target.
+    /// If there are no explicit targets, everything is a target unless
+    /// empty targets are set to select nothing.
     fn is_target(&self, path: &Path) -> bool {
-        self.filters
-            .targets
-            .iter()
-            .any(|target| path.starts_with(target))
-            || self.filters.targets.is_empty()
+        self.matched_target(path).is_some() || self.targets_all()
     }
 
     /// Returns if the path is ignored.
     fn is_ignored(&self, path: &Path) -> bool {
-        self.filters
-            .ignores
+        self.matched_ignore(path).is_some()
+    }
+
+    /// Returns the first target that the path matches, as configured.
+    fn matched_target(&self, path: &Path) -> Option<String> {
+        Self::matched(&self.filters.targets, &self.target_regexes, path)
+    }
+
+    /// Returns the first ignore that the path matches, as configured.
+    fn matched_ignore(&self, path: &Path) -> Option<String> {
+        Self::matched(&self.filters.ignores, &self.ignore_regexes, path)
+    }
+
+    /// Returns if the path is hidden, as any of its components starts with a
+    /// dot, and is excluded because hidden paths are not included.
+    ///
+    /// Hidden paths are still selected when a target explicitly names them,
+    /// eg. `.github/workflows/ci.yml` is selected by the `.github` target, but
+    /// not by a `src` target nor when there are no targets.
+    fn is_hidden(&self, path: &Path) -> bool {
+        if self.filters.include_hidden {
+            return false;
+        }
+        let components: Vec<_> = path.components().collect();
+        let Some(last_hidden) = components
+            .iter()
+            .rposition(|component| component.as_os_str().to_string_lossy().starts_with('.'))
+        else {
+            return false;
+        };
+        let hidden: PathBuf = components[..=last_hidden].iter().collect();
+        !self
+            .filters
+            .targets
+            .iter()
+            .any(|target| Path::new(target).starts_with(&hidden))
+    }
+
+    /// Returns whether everything is targeted, as there are no targets.
+    fn targets_all(&self) -> bool {
+        self.empty_targets == EmptyTargets::All
+            && self.filters.targets.is_empty()
+            && self.target_regexes.is_empty()
+    }
+
+    /// Returns the targets and ignores, as configured, that match the path.
+    pub fn matching<P: AsRef<Path>>(&self, path: P) -> [FilterKind; 2] {
+        let path = slashed(path.as_ref());
+        let matching = |prefixes: &[String], regexes: &[Regex]| {
+            let prefixes = prefixes
+                .iter()
+                .filter(|prefix| {
+                    Path::new(&path).starts_with(prefix) || path.eq_ignore_ascii_case(prefix)
+                })
+                .cloned();
+            let regexes = regexes
+                .iter()
+                .filter(|regex| regex.is_match(&path))
+                .map(|regex| format!("{REGEX_PREFIX}{}", regex.as_str()));
+            prefixes.chain(regexes).collect()
+        };
+        [
+            FilterKind::Target(matching(&self.filters.targets, &self.target_regexes)),
+            FilterKind::Ignore(matching(&self.filters.ignores, &self.ignore_regexes)),
+        ]
+    }
+
+    /// Returns the targets and ignores, as configured, that are not in
+    /// `matched`, as gathered with [`Selector::matching`]. The kinds whose
+    /// patterns all matched are left out.
+    pub fn unmatched(&self, matched: &Filters) -> Vec<FilterKind> {
+        let unmatched = |prefixes: &[String], regexes: &[Regex], matched: &[String]| {
+            prefixes
+                .iter()
+                .cloned()
+                .chain(
+                    regexes
+                        .iter()
+                        .map(|regex| format!("{REGEX_PREFIX}{}", regex.as_str())),
+                )
+                .filter(|pattern| !matched.contains(pattern))
+                .collect()
+        };
+        [
+            FilterKind::Target(unmatched(
+                &self.filters.targets,
+                &self.target_regexes,
+                &matched.targets,
+            )),
+            FilterKind::Ignore(unmatched(
+                &self.filters.ignores,
+                &self.ignore_regexes,
+                &matched.ignores,
+            )),
+        ]
+        .into_iter()
+        .filter(|kind| !kind.patterns().is_empty())
+        .collect()
+    }
+
+    fn matched(prefixes: &[String], regexes: &[Regex], path: &Path) -> Option<String> {
+        let path = slashed(path);
+        prefixes
             .iter()
-            .any(|ignore| path.starts_with(ignore))
+            .find(|prefix| Path::new(&path).starts_with(prefix))
+            .cloned()
+            .or_else(|| {
+                regexes
+                    .iter()
+                    .find(|regex| regex.is_match(&path))
+                    .map(|regex| format!("{REGEX_PREFIX}{}", regex.as_str()))
+            })
+    }
+
+    /// Removes the `regex:` patterns from `patterns`, and returns them
+    /// compiled.
+    fn compile(patterns: &mut Vec<String>, kind: &str) -> Result<Vec<Regex>> {
+        let (regexes, prefixes): (Vec<_>, Vec<_>) = patterns
+            .drain(..)
+            .partition(|pattern| pattern.starts_with(REGEX_PREFIX));
+        *patterns = prefixes;
+        regexes
+            .iter()
+            .map(|pattern| {
+                let expr = &pattern[REGEX_PREFIX.len()..];
+                Regex::new(expr)
+                    .map_err(|err| format_err!("invalid regex '{expr}' in {kind}: {err}"))
+            })
+            .collect()
     }
 
     /// Returns if the path contains a targeted extension.
     ///
-    /// If the path contains no extension, then we return true in case
-    /// that the candidate path exactly matches any of the targets.
+    /// Extensions with a dot, such as `pb.go`, are compared against the whole
+    /// suffix after the first dot of the file name, the others against its
+    /// last extension. If the path contains no extension, then we return
+    /// true in case that the candidate path exactly matches any of the
+    /// targets.
     fn is_extension(&self, path: &Path) -> bool {
-        path.extension().map_or_else(
-            || self.is_exact_target(path),
-            |ext| {
-                self.filters
-                    .extensions
-                    .iter()
-                    .any(|target| ext.eq_ignore_ascii_case(target))
-                    || self.is_exact_target(path)
-            },
-        )
+        let full_suffix = path
+            .file_name()
+            .map(|name| name.to_string_lossy())
+            .and_then(|name| {
+                name.trim_start_matches('.')
+                    .split_once('.')
+                    .map(|(_, suffix)| suffix.to_string())
+            });
+        let matches = |target: &String| {
+            if target.contains('.') {
+                full_suffix
+                    .as_ref()
+                    .is_some_and(|suffix| suffix.eq_ignore_ascii_case(target))
+            } else {
+                path.extension()
+                    .is_some_and(|ext| ext.eq_ignore_ascii_case(target))
+            }
+        };
+        self.filters.extensions.iter().any(matches) || self.is_exact_target(path)
     }
 
     fn is_exact_target(&self, path: &Path) -> bool {
         self.filters
             .targets
             .iter()
-            .any(|target| path.to_string_lossy().eq_ignore_ascii_case(target))
+            .any(|target| slashed(path).eq_ignore_ascii_case(target))
     }
 
     fn inverse_has_prefix(paths: &[String], prefix: &String) -> bool {
@@ -112,6 +370,21 @@ impl Selector {
     }
 }
 
+/// Returns the path with forward slashes as separators, as the targets and
+/// ignores are written in the spec, so they match on every platform.
+fn slashed(path: &Path) -> String {
+    slashed_with(path, MAIN_SEPARATOR)
+}
+
+fn slashed_with(path: &Path, separator: char) -> String {
+    let path = path.to_string_lossy();
+    if separator == '/' {
+        path.into_owned()
+    } else {
+        path.replace(separator, "/")
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -139,7 +412,7 @@ mod tests {
             .add(FilterKind::Ignore(svec!["ignored/a", "target/a/ignored"]))
             .add(FilterKind::Extension(svec!["proto"]));
 
-        let sut = Selector { filters };
+        let sut = Selector::new(filters, EmptyTargets::All).unwrap();
 
         assert_selection!(sut.select_file("target/a/file.proto"));
         assert_selection!(sut.select_file("readme.md"));
@@ -157,7 +430,7 @@ mod tests {
             .add(FilterKind::Ignore(svec!["ignored/a", "target/a/ignored"]))
             .add(FilterKind::Extension(svec!["proto"]));
 
-        let sut = Selector { filters };
+        let sut = Selector::new(filters, EmptyTargets::All).unwrap();
 
         assert_selection!(sut.select_file("target/a/file.proto"));
 
@@ -167,4 +440,211 @@ mod tests {
         assert_no_selection!(sut.select_file("target/noextension"));
         assert_no_selection!(sut.select_file("ignored/a/file.proto"));
     }
+
+    #[test]
+    fn test_selector_with_hidden_paths() {
+        let mut filters = Filters::new();
+        filters
+            .add(FilterKind::Target(svec!["src", ".github/workflows"]))
+            .add(FilterKind::Extension(svec!["txt", "yml"]));
+
+        let sut = Selector::new(filters.clone(), EmptyTargets::All).unwrap();
+
+        assert_selection!(sut.select_dir(".github"));
+        assert_selection!(sut.select_file(".github/workflows/ci.yml"));
+        assert_selection!(sut.select_file("src/file.txt"));
+
+        assert_no_selection!(sut.select_dir("src/.cache"));
+        assert_no_selection!(sut.select_file("src/.cache/file.txt"));
+        assert_no_selection!(sut.select_file("src/.config.txt"));
+        assert_no_selection!(sut.select_file(".github/workflows/.hidden.yml"));
+
+        filters.include_hidden = true;
+        let sut = Selector::new(filters, EmptyTargets::All).unwrap();
+
+        assert_selection!(sut.select_file("src/.cache/file.txt"));
+        assert_selection!(sut.select_file("src/.config.txt"));
+        assert_selection!(sut.select_file(".github/workflows/.hidden.yml"));
+    }
+
+    #[test]
+    fn test_selector_with_multi_part_extensions() {
+        let mut filters = Filters::new();
+        filters.add(FilterKind::Extension(svec![".pb.go", "tar.gz"]));
+
+        let sut = Selector::new(filters, EmptyTargets::All).unwrap();
+
+        assert_selection!(sut.select_file("api/service.pb.go"));
+        assert_selection!(sut.select_file("dist/release.TAR.GZ"));
+
+        assert_no_selection!(sut.select_file("api/service.go"));
+        assert_no_selection!(sut.select_file("api/service.grpc.pb.go"));
+        assert_no_selection!(sut.select_file("dist/release.gz"));
+        assert_no_selection!(sut.select_file("dist/release.v1.tar.gz"));
+    }
+
+    #[test]
+    fn test_selector_extensions_with_and_without_leading_dot() {
+        for extension in ["proto", ".proto"] {
+            let mut filters = Filters::new();
+            filters.add(FilterKind::Extension(svec![extension]));
+
+            let sut = Selector::new(filters, EmptyTargets::All).unwrap();
+
+            assert_selection!(sut.select_file("api/service.proto"));
+            assert_no_selection!(sut.select_file("api/service.txt"));
+            assert_eq!(svec!["proto"], sut.extensions());
+        }
+    }
+
+    #[test]
+    fn test_selector_with_regexes() {
+        let mut filters = Filters::new();
+        filters
+            .add(FilterKind::Target(svec![r"regex:^api/(v\d+)/.*\.proto$"]))
+            .add(FilterKind::Ignore(svec!["regex:deprecated"]))
+            .add(FilterKind::Extension(svec!["proto"]));
+
+        let sut = Selector::new(filters, EmptyTargets::All).unwrap();
+
+        assert_selection!(sut.select_dir("api"));
+        assert_selection!(sut.select_file("api/v1/service.proto"));
+        assert_selection!(sut.select_file("api/v12/nested/service.proto"));
+
+        assert_no_selection!(sut.select_dir("api/deprecated"));
+        assert_no_selection!(sut.select_file("api/v1/deprecated_service.proto"));
+        assert_no_selection!(sut.select_file("api/vx/service.proto"));
+        assert_no_selection!(sut.select_file("api/v1/service.txt"));
+        assert_no_selection!(sut.select_file("other/api/v1/service.proto"));
+    }
+
+    #[test]
+    fn test_selector_with_invalid_regex() {
+        let mut filters = Filters::new();
+        filters.add(FilterKind::Ignore(svec!["regex:api/(v1"]));
+
+        let err = Selector::new(filters, EmptyTargets::All)
+            .err()
+            .expect("should fail");
+
+        assert!(err
+            .to_string()
+            .starts_with("invalid regex 'api/(v1' in ignores: "));
+    }
+
+    #[test]
+    fn test_selector_unmatched() {
+        let mut filters = Filters::new();
+        filters
+            .add(FilterKind::Target(svec![
+                "target/a",
+                "target/typo",
+                r"regex:^api/v\d+/",
+                "regex:^none/"
+            ]))
+            .add(FilterKind::Ignore(svec!["target/a/ignored", "missing"]));
+        let sut = Selector::new(filters, EmptyTargets::All).unwrap();
+        let mut matched = Filters::new();
+        for path in ["target/a/file.proto", "target/a/ignored", "api/v1/a.proto"] {
+            for kind in sut.matching(path) {
+                matched.add(kind);
+            }
+        }
+
+        assert_eq!(
+            vec![
+                FilterKind::Target(svec!["target/typo", "regex:^none/"]),
+                FilterKind::Ignore(svec!["missing"]),
+            ],
+            sut.unmatched(&matched)
+        );
+    }
+
+    #[test]
+    fn test_selector_matches_paths_with_any_separator() {
+        assert_eq!("a/b/c.proto", slashed_with(Path::new(r"a\b\c.proto"), '\\'));
+        assert_eq!("a/b/c.proto", slashed_with(Path::new("a/b/c.proto"), '/'));
+
+        let mut filters = Filters::new();
+        filters
+            .add(FilterKind::Target(svec!["a/b", r"regex:^c/d/"]))
+            .add(FilterKind::Ignore(svec!["a/b/ignored"]))
+            .add(FilterKind::Extension(svec!["proto"]));
+        let sut = Selector::new(filters, EmptyTargets::All).unwrap();
+        let path = |components: &[&str]| components.iter().collect::<PathBuf>();
+
+        assert!(sut.select_dir(path(&["a", "b"])));
+        assert!(sut.select_file(path(&["a", "b", "file.proto"])));
+        assert!(sut.select_file(path(&["c", "d", "file.proto"])));
+        assert!(!sut.select_file(path(&["a", "b", "ignored", "file.proto"])));
+        assert!(!sut.select_file(path(&["a", "c", "file.proto"])));
+    }
+
+    #[test]
+    fn test_selector_explain() {
+        let mut filters = Filters::new();
+        filters
+            .add(FilterKind::Target(svec![
+                "a",
+                r"regex:^api/v\d+/",
+                "Makefile"
+            ]))
+            .add(FilterKind::Ignore(svec![
+                "a/ignored",
+                r"regex:_test\.proto$"
+            ]))
+            .add(FilterKind::Extension(svec!["proto"]));
+        let sut = Selector::new(filters, EmptyTargets::All).unwrap();
+
+        assert_eq!(
+            CopyDecision::Copied(Some("a".into())),
+            sut.explain("a/b/file.proto")
+        );
+        assert_eq!(
+            CopyDecision::Copied(Some(r"regex:^api/v\d+/".into())),
+            sut.explain("api/v1/file.proto")
+        );
+        assert_eq!(
+            CopyDecision::Copied(Some("Makefile".into())),
+            sut.explain("Makefile")
+        );
+        assert_eq!(
+            CopyDecision::Ignored("a/ignored".into()),
+            sut.explain("a/ignored/file.proto")
+        );
+        assert_eq!(
+            CopyDecision::Ignored(r"regex:_test\.proto$".into()),
+            sut.explain("a/file_test.proto")
+        );
+        assert_eq!(CopyDecision::Hidden, sut.explain("a/.hidden/file.proto"));
+        assert_eq!(CopyDecision::NotTargeted, sut.explain("b/file.proto"));
+        assert_eq!(
+            CopyDecision::WrongExtension(svec!["proto"]),
+            sut.explain("a/file.txt")
+        );
+        assert_eq!(
+            "not copied, it matches the ignore 'a/ignored'",
+            sut.explain("a/ignored/file.proto").to_string()
+        );
+
+        let mut filters = Filters::new();
+        filters.add(FilterKind::Extension(svec!["proto"]));
+        let sut = Selector::new(filters, EmptyTargets::All).unwrap();
+        assert_eq!(CopyDecision::Copied(None), sut.explain("b/file.proto"));
+    }
+
+    #[test]
+    fn test_selector_with_empty_targets_as_nothing() {
+        let mut filters = Filters::new();
+        filters
+            .add(FilterKind::Ignore(svec!["ignored/a"]))
+            .add(FilterKind::Extension(svec!["proto"]));
+
+        let sut = Selector::new(filters, EmptyTargets::Nothing).unwrap();
+
+        assert_selection!(sut.select_dir(""));
+        assert_no_selection!(sut.select_dir("target"));
+        assert_no_selection!(sut.select_file("target/a/file.proto"));
+        assert_no_selection!(sut.select_file("file.proto"));
+    }
 }
@@ -0,0 +1,117 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use anyhow::Result;
+
+const BINARY_PREFIX_LEN: usize = 8000;
+const LICENSE_HEADER_LEN: usize = 4096;
+
+/// Returns whether the contents look like text: valid UTF-8 without NUL
+/// bytes. Content transforms leave any other file untouched.
+pub fn is_text(contents: &[u8]) -> bool {
+    !contents.contains(&0) && std::str::from_utf8(contents).is_ok()
+}
+
+/// Returns whether a file looks binary, as git tells them apart: there is a
+/// NUL byte within its first 8000 bytes.
+pub fn is_binary(path: &Path) -> Result<bool> {
+    let mut head = Vec::with_capacity(BINARY_PREFIX_LEN);
+    File::open(path)?
+        .take(BINARY_PREFIX_LEN as u64)
+        .read_to_end(&mut head)?;
+    Ok(head.contains(&0))
+}
+
+/// Returns whether any of the `headers` is found within the first 4096 bytes
+/// of a file, where license headers are written.
+pub fn has_license_header(path: &Path, headers: &[String]) -> Result<bool> {
+    let mut head = Vec::with_capacity(LICENSE_HEADER_LEN);
+    File::open(path)?
+        .take(LICENSE_HEADER_LEN as u64)
+        .read_to_end(&mut head)?;
+    let head = String::from_utf8_lossy(&head);
+    Ok(headers.iter().any(|header| head.contains(header.as_str())))
+}
+
+/// Strips the spaces and tabs at the end of each line, keeping the line
+/// endings as they are.
+pub fn strip_trailing_whitespace(contents: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(contents.len());
+    for line in contents.split_inclusive(|byte| *byte == b'\n') {
+        let eol = match line {
+            [.., b'\r', b'\n'] => 2,
+            [.., b'\n'] => 1,
+            _ => 0,
+        };
+        let (body, eol) = line.split_at(line.len() - eol);
+        let end = body
+            .iter()
+            .rposition(|byte| !matches!(byte, b' ' | b'\t'))
+            .map_or(0, |pos| pos + 1);
+        out.extend_from_slice(&body[..end]);
+        out.extend_from_slice(eol);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::fs;
+
+    use super::*;
+    use crate::test_utils::tempdir;
+
+    #[test]
+    fn test_is_text() {
+        assert!(is_text(b"syntax = \"proto3\";\n"));
+        assert!(is_text(b""));
+        assert!(!is_text(b"binary\0data"));
+        assert!(!is_text(&[0xff, 0xfe, 0x41]));
+    }
+
+    #[test]
+    fn test_is_binary() -> Result<()> {
+        let dir = tempdir();
+        let text = dir.path().join("api.proto");
+        let binary = dir.path().join("image.png");
+        let late = dir.path().join("late.txt");
+        fs::write(&text, "syntax = \"proto3\";\n")?;
+        fs::write(&binary, b"\x89PNG\0\0")?;
+        fs::write(&late, [vec![b'a'; 8000], vec![0]].concat())?;
+
+        assert!(!is_binary(&text)?);
+        assert!(is_binary(&binary)?);
+        assert!(!is_binary(&late)?, "only the first 8000 bytes are sniffed");
+        Ok(())
+    }
+
+    #[test]
+    fn test_has_license_header() -> Result<()> {
+        let dir = tempdir();
+        let licensed = dir.path().join("licensed.proto");
+        let unlicensed = dir.path().join("unlicensed.proto");
+        fs::write(
+            &licensed,
+            "// Licensed under the Apache License, Version 2.0\nsyntax = \"proto3\";\n",
+        )?;
+        fs::write(&unlicensed, "syntax = \"proto3\";\n")?;
+        let headers = vec!["Apache License, Version 2.0".to_string(), "MIT".to_string()];
+
+        assert!(has_license_header(&licensed, &headers)?);
+        assert!(!has_license_header(&unlicensed, &headers)?);
+        assert!(!has_license_header(&licensed, &[])?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_strip_trailing_whitespace() {
+        assert_eq!(
+            b"a\n\nb\r\n  c\n\td".to_vec(),
+            strip_trailing_whitespace(b"a  \n \t\nb\t\r\n  c \n\td  ")
+        );
+        let clean = b"message A {\n  string a = 1;\n}\n";
+        assert_eq!(clean.to_vec(), strip_trailing_whitespace(clean));
+    }
+}
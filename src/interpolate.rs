@@ -0,0 +1,91 @@
+use std::env;
+
+use anyhow::format_err;
+use anyhow::Result;
+
+/// Returns the value with its `${VAR}` references replaced by the value of
+/// the environment variable. References spelled as `${VAR:-default}` fall
+/// back to the default when the variable is not set.
+///
+/// # Errors
+///
+/// Will return `Err` when a variable without default is not set, or when a
+/// reference is not closed.
+pub fn interpolate(value: &str) -> Result<String> {
+    interpolate_with(value, |name| env::var(name).ok())
+}
+
+/// Returns whether the value has references to be interpolated.
+pub fn has_references(value: &str) -> bool {
+    value.contains("${")
+}
+
+fn interpolate_with(value: &str, lookup: impl Fn(&str) -> Option<String>) -> Result<String> {
+    let mut interpolated = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find("${") {
+        interpolated.push_str(&rest[..start]);
+        let reference = &rest[start + 2..];
+        let end = reference
+            .find('}')
+            .ok_or_else(|| format_err!("unclosed reference in '{value}'"))?;
+        let (name, default) = match reference[..end].split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (&reference[..end], None),
+        };
+        let resolved = lookup(name)
+            .or_else(|| default.map(ToString::to_string))
+            .ok_or_else(|| format_err!("environment variable {name} is not set, in '{value}'"))?;
+        interpolated.push_str(&resolved);
+        rest = &reference[end + 1..];
+    }
+    interpolated.push_str(rest);
+    Ok(interpolated)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn lookup(name: &str) -> Option<String> {
+        (name == "GIT_HOST").then(|| "github.com".to_string())
+    }
+
+    #[test]
+    fn test_interpolate_defined_variables() -> Result<()> {
+        assert_eq!(
+            "https://github.com/org/repo",
+            interpolate_with("https://${GIT_HOST}/org/repo", lookup)?
+        );
+        assert_eq!(
+            "github.com-github.com",
+            interpolate_with("${GIT_HOST}-${GIT_HOST:-other}", lookup)?
+        );
+        assert_eq!("$HOME/a", interpolate_with("$HOME/a", lookup)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_interpolate_undefined_variables() {
+        assert_eq!(
+            "environment variable REFNAME is not set, in 'v${REFNAME}'",
+            interpolate_with("v${REFNAME}", lookup)
+                .unwrap_err()
+                .to_string()
+        );
+        assert_eq!(
+            "unclosed reference in 'https://${GIT_HOST/org'",
+            interpolate_with("https://${GIT_HOST/org", lookup)
+                .unwrap_err()
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn test_interpolate_defaulted_variables() -> Result<()> {
+        assert_eq!("v1.0.0", interpolate_with("${REFNAME:-v1.0.0}", lookup)?);
+        assert_eq!("", interpolate_with("${REFNAME:-}", lookup)?);
+        Ok(())
+    }
+}
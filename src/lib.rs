@@ -1,19 +1,259 @@
 use std::env;
+use std::path::Path;
 
+use self::cache::Cache;
+pub use self::deps::Dependency;
+pub use self::error::VendorError;
+pub use self::installer::CopyDecision;
+pub use self::installer::FileFilter;
+use self::installer::Installer;
+use self::installer::LockHook;
+pub use self::installer::Outdated;
+pub use self::logging::init_logging;
+pub use self::logging::LogFormat;
+pub use self::metrics::DependencyMetrics;
+pub use self::metrics::Metrics;
+pub use self::preset::default_cache_dir;
+pub use self::preset::Builder as PresetBuilder;
+pub use self::preset::Preset;
+pub use self::repository::RemoteRefs;
+pub use self::spec::Spec;
+pub use self::spec_lock::SpecLock;
+
+mod archive;
 mod cache;
+mod checksum;
 pub mod cli;
+mod config;
 mod control;
 mod deps;
+mod error;
 mod filters;
 mod installer;
+mod interpolate;
 mod lock;
+mod logging;
+mod metrics;
 mod preset;
+mod proxy;
 mod repository;
+mod retry;
 mod spec;
 mod spec_lock;
+mod suggest;
+mod timeout;
+mod url;
+mod version;
 mod yaml;
 
 #[cfg(test)]
 mod test_utils;
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Outcome of [`vendor`].
+#[derive(Debug)]
+pub struct VendorReport {
+    /// Lock pinning the commits that have been vendored.
+    pub spec_lock: SpecLock,
+
+    /// Metrics of the run, including the dependencies that failed.
+    pub metrics: Metrics,
+
+    /// Dependencies that failed to be vendored.
+    pub failures: Vec<Failure>,
+}
+
+/// Dependency that failed to be vendored, see [`VendorReport`].
+#[derive(Debug)]
+pub struct Failure {
+    /// Url of the dependency.
+    pub url: String,
+
+    /// Name of the dependency, when it has one.
+    pub name: Option<String>,
+
+    /// Reason why it failed.
+    pub error: VendorError,
+}
+
+/// Vendors the dependencies of the spec, respecting the pins of the lock,
+/// as the install command does. Repositories are cached under `cache`, see
+/// [`default_cache_dir`] for the directory the command line uses.
+///
+/// Dependencies are imported in parallel, the cache and repository locks are
+/// handled internally. Nothing is written besides the cache and the vendor
+/// folder, saving the lock is left to the caller.
+///
+/// # Errors
+///
+/// Will return `Err` if the cache cannot be locked or the vendor folder cannot
+/// be replaced. Failed dependencies are reported in the failures instead.
+pub fn vendor<P: AsRef<Path>>(
+    spec: &Spec,
+    spec_lock: SpecLock,
+    cache: P,
+) -> Result<VendorReport, VendorError> {
+    vendor_with_hooks(spec, spec_lock, cache, Hooks::new())
+}
+
+/// Vendors the dependencies of the spec as [`vendor`] does, only keeping the
+/// files for which `file_filter` returns true. It is called with the path of
+/// each file, relative to the root of its dependency, and its contents, so
+/// files can be selected with logic that the filters of the spec cannot
+/// express, such as parsing them.
+///
+/// The filter runs last, once a file has passed the targets, ignores and
+/// extensions of the spec and has not been ignored by the dependency. It is
+/// called from the threads that import the dependencies.
+///
+/// # Errors
+///
+/// Will return `Err` in the same cases as [`vendor`].
+pub fn vendor_with_filter<P, F>(
+    spec: &Spec,
+    spec_lock: SpecLock,
+    cache: P,
+    file_filter: F,
+) -> Result<VendorReport, VendorError>
+where
+    P: AsRef<Path>,
+    F: Fn(&Path, &[u8]) -> bool + Send + Sync + 'static,
+{
+    vendor_with_hooks(
+        spec,
+        spec_lock,
+        cache,
+        Hooks::new().with_file_filter(file_filter),
+    )
+}
+
+/// Callbacks that customise [`vendor_with_hooks`] and [`outdated`], none by
+/// default.
+#[derive(Default)]
+pub struct Hooks {
+    file_filter: Option<Box<FileFilter>>,
+    lock_hook: Option<Box<LockHook>>,
+    remote_refs: Option<Box<dyn RemoteRefs>>,
+}
+
+impl Hooks {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only keeps the files for which `file_filter` returns true, see
+    /// [`vendor_with_filter`].
+    #[must_use]
+    pub fn with_file_filter<F>(mut self, file_filter: F) -> Self
+    where
+        F: Fn(&Path, &[u8]) -> bool + Send + Sync + 'static,
+    {
+        self.file_filter = Some(Box::new(file_filter));
+        self
+    }
+
+    /// Calls `lock_hook` with the lock once all the dependencies have been
+    /// imported, before the vendor folder is replaced, so the lock can be
+    /// rewritten or rejected. An error fails the run and leaves the vendor
+    /// folder untouched. The checksums of the vendored files are recorded
+    /// after it runs.
+    #[must_use]
+    pub fn with_lock_hook<H>(mut self, lock_hook: H) -> Self
+    where
+        H: Fn(&mut SpecLock) -> Result<(), VendorError> + Send + Sync + 'static,
+    {
+        self.lock_hook = Some(Box::new(move |spec_lock| Ok(lock_hook(spec_lock)?)));
+        self
+    }
+
+    /// Lists the references of the remotes with `remote_refs`, instead of
+    /// talking the git protocol, see [`outdated`]. It allows to query the
+    /// APIs of the git hosting services instead.
+    #[must_use]
+    pub fn with_remote_refs<R>(mut self, remote_refs: R) -> Self
+    where
+        R: RemoteRefs + 'static,
+    {
+        self.remote_refs = Some(Box::new(remote_refs));
+        self
+    }
+}
+
+/// Vendors the dependencies of the spec as [`vendor`] does, calling the
+/// `hooks` along the way, see [`Hooks`].
+///
+/// # Errors
+///
+/// Will return `Err` in the same cases as [`vendor`], or when the lock hook
+/// fails.
+pub fn vendor_with_hooks<P: AsRef<Path>>(
+    spec: &Spec,
+    spec_lock: SpecLock,
+    cache: P,
+    hooks: Hooks,
+) -> Result<VendorReport, VendorError> {
+    let cache = Cache::from_root(cache);
+    cache.initialize()?;
+    let _cache_lock = cache.lock_shared()?;
+    let (spec_lock, metrics, failures) = Installer::new(cache, spec, spec_lock)
+        .with_file_filter(hooks.file_filter)
+        .with_lock_hook(hooks.lock_hook)
+        .install_reporting_failures()?;
+    let failures = failures
+        .into_iter()
+        .map(|(dep, err)| Failure {
+            url: dep.url,
+            name: dep.name,
+            error: err.into(),
+        })
+        .collect();
+    Ok(VendorReport {
+        spec_lock,
+        metrics,
+        failures,
+    })
+}
+
+/// Explains whether the file at `path`, relative to the root of the
+/// dependency of `url`, is copied by the targets, ignores and extensions of
+/// the spec, as the explain command does. The dependency is found by its
+/// name or its url. Nothing is cloned, the filters are evaluated on the path
+/// alone, so a file can still be skipped for other reasons, such as the
+/// `.vendorignore` of the dependency.
+///
+/// # Errors
+///
+/// Will return `Err` if the dependency is not in the spec, or its filters are
+/// invalid.
+pub fn explain<P: AsRef<Path>>(
+    spec: &Spec,
+    url: &str,
+    path: P,
+) -> Result<CopyDecision, VendorError> {
+    let cache = Cache::from_root(default_cache_dir());
+    let spec_lock = SpecLock::with_preset(&Preset::default());
+    Ok(Installer::new(cache, spec, spec_lock).explain(url, path.as_ref())?)
+}
+
+/// Resolves the refname of each dependency of the spec against its remote,
+/// as the outdated command does, and returns the dependencies whose locked
+/// commit is not the latest one. Nothing is cloned, the references of the
+/// remotes are listed with the remote refs of the `hooks`, see
+/// [`Hooks::with_remote_refs`], or with the git protocol by default.
+///
+/// # Errors
+///
+/// Will return `Err` if the references of a remote cannot be listed, or a
+/// refname cannot be found in them.
+pub fn outdated(
+    spec: &Spec,
+    spec_lock: SpecLock,
+    hooks: Hooks,
+) -> Result<Vec<Outdated>, VendorError> {
+    let cache = Cache::from_root(default_cache_dir());
+    Ok(Installer::new(cache, spec, spec_lock)
+        .with_remote_refs(hooks.remote_refs)
+        .outdated()?)
+}
@@ -28,7 +28,26 @@ impl Lock {
         self
     }
 
+    /// Acquires the lock only when it is free, returns whether it has been
+    /// acquired.
+    pub fn try_acquire(&mut self) -> Result<bool> {
+        if self.file.is_none() {
+            self.file = Some(File::create(&self.path)?);
+        }
+        unix::try_exclusive_lock(self.file.as_ref().unwrap())
+    }
+
     pub fn acquire(&mut self) -> Result<()> {
+        self.acquire_with(unix::exclusive_lock)
+    }
+
+    /// Acquires the lock along with any other holder of the shared lock,
+    /// waiting only while it is held exclusively.
+    pub fn acquire_shared(&mut self) -> Result<()> {
+        self.acquire_with(unix::shared_lock)
+    }
+
+    fn acquire_with(&mut self, lock: fn(&File) -> Result<()>) -> Result<()> {
         if self.file.is_none() {
             self.file = Some(File::create(&self.path)?);
         }
@@ -36,12 +55,13 @@ impl Lock {
         thread::scope(|s| -> Result<()> {
             let (sender, receiver): (Sender<Result<()>>, Receiver<Result<()>>) = mpsc::channel();
             s.spawn(move || {
-                let result = unix::exclusive_lock(file);
+                let result = lock(file);
                 sender.send(result)
             });
             if let Some((warn, after)) = &self.warn {
-                if receiver.recv_timeout(*after).is_err() {
-                    log::error!("{}", warn);
+                match receiver.recv_timeout(*after) {
+                    Ok(result) => return result,
+                    Err(_) => log::error!("{warn}"),
                 }
             }
             match receiver.recv() {
@@ -54,6 +74,7 @@ impl Lock {
 
 mod unix {
     use std::fs::File;
+    use std::io;
     use std::os::unix::prelude::AsRawFd;
 
     use anyhow::format_err;
@@ -63,6 +84,21 @@ mod unix {
         flock(file, libc::LOCK_EX)
     }
 
+    pub fn shared_lock(file: &File) -> Result<()> {
+        flock(file, libc::LOCK_SH)
+    }
+
+    pub fn try_exclusive_lock(file: &File) -> Result<bool> {
+        let ret = unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) };
+        if ret == 0 {
+            return Ok(true);
+        }
+        match io::Error::last_os_error().raw_os_error() {
+            Some(libc::EWOULDBLOCK) => Ok(false),
+            _ => Err(format_err!("cannot lock file")),
+        }
+    }
+
     fn flock(file: &File, flag: libc::c_int) -> Result<()> {
         let ret = unsafe { libc::flock(file.as_raw_fd(), flag) };
         if ret < 0 {
@@ -72,3 +108,53 @@ mod unix {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::test_utils::tempdir;
+
+    #[test]
+    fn test_lock_acquire_with_warn() -> Result<()> {
+        let dir = tempdir();
+        let mut sut = Lock::new(dir.path().join(".LOCK")).with_warn("slow", Duration::from_secs(1));
+
+        sut.acquire()?;
+
+        assert!(dir.path().join(".LOCK").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_lock_try_acquire() -> Result<()> {
+        let dir = tempdir();
+        let mut held = Lock::new(dir.path().join(".LOCK"));
+        held.acquire()?;
+
+        let mut sut = Lock::new(dir.path().join(".LOCK"));
+        assert!(!sut.try_acquire()?);
+
+        drop(held);
+        assert!(sut.try_acquire()?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_lock_acquire_shared() -> Result<()> {
+        let dir = tempdir();
+        let mut first = Lock::new(dir.path().join(".LOCK"));
+        let mut second = Lock::new(dir.path().join(".LOCK"));
+        first.acquire_shared()?;
+        second.acquire_shared()?;
+
+        let mut sut = Lock::new(dir.path().join(".LOCK"));
+        assert!(!sut.try_acquire()?);
+
+        drop(first);
+        assert!(!sut.try_acquire()?);
+        drop(second);
+        assert!(sut.try_acquire()?);
+        Ok(())
+    }
+}
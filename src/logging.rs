@@ -0,0 +1,95 @@
+use std::str::FromStr;
+
+use anyhow::format_err;
+use log::LevelFilter;
+use simplelog::ColorChoice;
+use simplelog::ConfigBuilder;
+use simplelog::LevelPadding;
+use simplelog::TermLogger;
+use simplelog::TerminalMode;
+
+/// How log records are written to the terminal.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub enum LogFormat {
+    /// Only the level and the message, colored when the terminal allows it.
+    #[default]
+    Human,
+
+    /// The time, level, module and message of each record, without colors,
+    /// so the output can be parsed or ingested by log collectors.
+    Structured,
+}
+
+impl FromStr for LogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "human" => Ok(Self::Human),
+            "structured" => Ok(Self::Structured),
+            _ => Err(format_err!(
+                "unknown log format '{value}', expected human or structured"
+            )),
+        }
+    }
+}
+
+/// Initializes the logging of the crate to the terminal, only showing the
+/// records up to `level`, such as [`LevelFilter::Debug`] to see every file
+/// that is vendored.
+///
+/// Returns whether the logger has been initialized. When a logger is already
+/// set, by a previous call or by the application, it is left as it is.
+#[must_use]
+pub fn init_logging(level: LevelFilter, format: LogFormat) -> bool {
+    let mut config = ConfigBuilder::default();
+    let colors = match format {
+        LogFormat::Human => {
+            config.set_time_level(LevelFilter::Off);
+            ColorChoice::Auto
+        }
+        LogFormat::Structured => {
+            config
+                .set_time_level(LevelFilter::Error)
+                .set_time_format_rfc3339()
+                .set_target_level(LevelFilter::Error)
+                .set_level_padding(LevelPadding::Right);
+            ColorChoice::Never
+        }
+    };
+    // The max level is set before the logger, restore it when there is one.
+    let previous = log::max_level();
+    let initialized = TermLogger::init(level, config.build(), TerminalMode::Mixed, colors).is_ok();
+    if !initialized {
+        log::set_max_level(previous);
+    }
+    initialized
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_log_format_from_str() {
+        assert_eq!(LogFormat::Human, "human".parse().unwrap());
+        assert_eq!(LogFormat::Structured, "Structured".parse().unwrap());
+        assert_eq!(
+            "unknown log format 'json', expected human or structured",
+            "json".parse::<LogFormat>().unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn test_init_logging_sets_the_level_once() {
+        assert!(init_logging(LevelFilter::Warn, LogFormat::Human));
+
+        assert_eq!(LevelFilter::Warn, log::max_level());
+        assert!(log::log_enabled!(log::Level::Warn));
+        assert!(!log::log_enabled!(log::Level::Info));
+
+        assert!(!init_logging(LevelFilter::Debug, LogFormat::Structured));
+        assert_eq!(LevelFilter::Warn, log::max_level());
+    }
+}
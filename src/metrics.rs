@@ -0,0 +1,206 @@
+use std::fmt::Display;
+use std::fmt::Write;
+use std::time::Duration;
+
+use crate::checksum::Changes;
+
+/// Metrics collected during an install or update run.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Metrics {
+    /// Number of dependencies that have been processed.
+    pub dependencies: usize,
+
+    /// Number of dependencies that failed to be vendored.
+    pub failures: usize,
+
+    /// Number of files copied into the vendor folder.
+    pub files: usize,
+
+    /// Number of bytes copied into the vendor folder.
+    pub bytes: u64,
+
+    /// Wall time spent vendoring the dependencies.
+    pub duration: Duration,
+
+    /// Changes of the vendor folder compared to the previous run.
+    pub changes: Changes,
+
+    /// Files and bytes copied by each dependency that was vendored, in spec
+    /// order.
+    pub per_dependency: Vec<DependencyMetrics>,
+}
+
+/// Files and bytes copied by a single dependency, see [`Metrics`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DependencyMetrics {
+    /// Url of the dependency.
+    pub url: String,
+
+    /// Name of the dependency, when it has one.
+    pub name: Option<String>,
+
+    /// Number of files copied into the vendor folder.
+    pub files: usize,
+
+    /// Number of bytes copied into the vendor folder.
+    pub bytes: u64,
+}
+
+impl Metrics {
+    /// Formats the metrics using the Prometheus text exposition format, so
+    /// they can be scraped or written to a textfile collector.
+    #[must_use]
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+        write_metric(
+            &mut out,
+            "vendify_dependencies_total",
+            "counter",
+            "Number of dependencies processed.",
+            self.dependencies,
+        );
+        write_metric(
+            &mut out,
+            "vendify_failures_total",
+            "counter",
+            "Number of dependencies that failed to be vendored.",
+            self.failures,
+        );
+        write_metric(
+            &mut out,
+            "vendify_files_copied_total",
+            "counter",
+            "Number of files copied into the vendor folder.",
+            self.files,
+        );
+        write_metric(
+            &mut out,
+            "vendify_bytes_copied_total",
+            "counter",
+            "Number of bytes copied into the vendor folder.",
+            self.bytes,
+        );
+        write_metric(
+            &mut out,
+            "vendify_duration_seconds",
+            "gauge",
+            "Time spent vendoring the dependencies.",
+            self.duration.as_secs_f64(),
+        );
+        if !self.per_dependency.is_empty() {
+            write_dependency_metrics(
+                &mut out,
+                "vendify_dependency_files_copied_total",
+                "Number of files copied into the vendor folder by the dependency.",
+                &self.per_dependency,
+                |dep| dep.files as u64,
+            );
+            write_dependency_metrics(
+                &mut out,
+                "vendify_dependency_bytes_copied_total",
+                "Number of bytes copied into the vendor folder by the dependency.",
+                &self.per_dependency,
+                |dep| dep.bytes,
+            );
+        }
+        out
+    }
+}
+
+/// Writes a counter with a sample per dependency, labelled with its url and
+/// name.
+fn write_dependency_metrics(
+    out: &mut String,
+    name: &str,
+    help: &str,
+    deps: &[DependencyMetrics],
+    value: impl Fn(&DependencyMetrics) -> u64,
+) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} counter");
+    for dep in deps {
+        let _ = writeln!(
+            out,
+            "{name}{{url=\"{}\",name=\"{}\"}} {}",
+            escape_label(&dep.url),
+            escape_label(dep.name.as_deref().unwrap_or_default()),
+            value(dep)
+        );
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn write_metric(out: &mut String, name: &str, kind: &str, help: &str, value: impl Display) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+    let _ = writeln!(out, "{name} {value}");
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_metrics_to_prometheus() {
+        let sut = Metrics {
+            dependencies: 3,
+            failures: 1,
+            files: 12,
+            bytes: 2048,
+            duration: Duration::from_millis(1500),
+            changes: Changes::default(),
+            per_dependency: vec![],
+        };
+
+        let actual = sut.to_prometheus();
+
+        assert!(actual.contains("# TYPE vendify_dependencies_total counter\n"));
+        assert!(actual.contains("vendify_dependencies_total 3\n"));
+        assert!(actual.contains("vendify_failures_total 1\n"));
+        assert!(actual.contains("vendify_files_copied_total 12\n"));
+        assert!(actual.contains("vendify_bytes_copied_total 2048\n"));
+        assert!(actual.contains("# TYPE vendify_duration_seconds gauge\n"));
+        assert!(actual.contains("vendify_duration_seconds 1.5\n"));
+    }
+
+    #[test]
+    fn test_metrics_per_dependency_to_prometheus() {
+        let sut = Metrics {
+            per_dependency: vec![DependencyMetrics {
+                url: "https://host/a\"b".into(),
+                name: Some("protos".into()),
+                files: 2,
+                bytes: 10,
+            }],
+            ..Metrics::default()
+        };
+
+        let actual = sut.to_prometheus();
+
+        assert!(actual.contains("# TYPE vendify_dependency_files_copied_total counter\n"));
+        assert!(actual.contains(
+            "vendify_dependency_files_copied_total{url=\"https://host/a\\\"b\",name=\"protos\"} \
+             2\n"
+        ));
+        assert!(actual.contains(
+            "vendify_dependency_bytes_copied_total{url=\"https://host/a\\\"b\",name=\"protos\"} \
+             10\n"
+        ));
+    }
+
+    #[test]
+    fn test_metrics_default_to_prometheus() {
+        let actual = Metrics::default().to_prometheus();
+
+        assert_eq!(15, actual.lines().count());
+        assert!(actual.contains("vendify_dependencies_total 0\n"));
+        assert!(actual.contains("vendify_duration_seconds 0\n"));
+    }
+}
@@ -1,11 +1,53 @@
+use std::collections::BTreeMap;
+use std::env;
 use std::env::temp_dir;
+use std::ffi::OsString;
 use std::fmt;
+use std::path::PathBuf;
 
 use crate::deps::Dependency;
+use crate::filters::FilterKind;
 use crate::filters::Filters;
 
 type DependencyFiltersProvider = fn(&Dependency) -> Filters;
 
+/// Registry of the presets that dependencies can reference by name.
+pub struct Registry {
+    presets: BTreeMap<String, Preset>,
+}
+
+impl Registry {
+    /// Creates a registry with the presets that are built into the tool.
+    #[must_use]
+    pub fn builtin() -> Self {
+        let mut registry = Self {
+            presets: BTreeMap::new(),
+        };
+        registry
+            .register(Preset::default())
+            .register(Builder::protobuf().build());
+        registry
+    }
+
+    /// Registers a preset under its name, replacing any preset with the same
+    /// name.
+    pub fn register(&mut self, preset: Preset) -> &mut Self {
+        self.presets.insert(preset.name().clone(), preset);
+        self
+    }
+
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<&Preset> {
+        self.presets.get(name)
+    }
+
+    /// Returns the names of the registered presets, sorted.
+    #[must_use]
+    pub fn names(&self) -> Vec<&str> {
+        self.presets.keys().map(String::as_str).collect()
+    }
+}
+
 #[derive(Clone)]
 pub struct Preset {
     name: String,
@@ -14,6 +56,8 @@ pub struct Preset {
     spec: String,
     spec_lock: String,
     force_filters: bool,
+    preserve_unknown_fields: bool,
+    strict_version: bool,
     spec_filters: Filters,
     dependency_filters: DependencyFiltersProvider,
 }
@@ -26,6 +70,8 @@ impl PartialEq for Preset {
             && self.spec == other.spec
             && self.spec_lock == other.spec_lock
             && self.force_filters == other.force_filters
+            && self.preserve_unknown_fields == other.preserve_unknown_fields
+            && self.strict_version == other.strict_version
             && self.spec_filters == other.spec_filters
             && self.dependency_filters as usize == other.dependency_filters as usize
     }
@@ -42,8 +88,10 @@ impl fmt::Debug for Preset {
             .field("spec", &self.spec)
             .field("spec_lock", &self.spec_lock)
             .field("force_filters", &self.force_filters)
+            .field("preserve_unknown_fields", &self.preserve_unknown_fields)
+            .field("strict_version", &self.strict_version)
             .field("spec_filters", &self.spec_filters)
-            .finish()
+            .finish_non_exhaustive()
     }
 }
 
@@ -92,6 +140,20 @@ impl Preset {
     pub fn force_filters(&self) -> bool {
         self.force_filters
     }
+
+    /// Returns whether unknown fields found in the spec file are kept when
+    /// the spec is saved back, instead of being dropped. Enabled by default.
+    #[must_use]
+    pub fn preserve_unknown_fields(&self) -> bool {
+        self.preserve_unknown_fields
+    }
+
+    /// Returns whether loading a lock file generated by a newer version of
+    /// the tool is an error, instead of a warning.
+    #[must_use]
+    pub fn strict_version(&self) -> bool {
+        self.strict_version
+    }
 }
 
 impl Default for Preset {
@@ -107,6 +169,8 @@ pub struct Builder {
     spec: String,
     spec_lock: String,
     force_filters: bool,
+    preserve_unknown_fields: bool,
+    strict_version: bool,
     global_filters: Filters,
     dependency_filters: DependencyFiltersProvider,
 }
@@ -121,11 +185,22 @@ impl Builder {
             spec: ".vendor.yml".into(),
             spec_lock: ".vendor-lock.yml".into(),
             force_filters: false,
+            preserve_unknown_fields: true,
+            strict_version: false,
             global_filters: Filters::new(),
             dependency_filters: Self::default_dependency_filters,
         }
     }
 
+    /// Returns a builder for the `protobuf` preset, which vendors the proto
+    /// files of the dependencies.
+    #[must_use]
+    pub fn protobuf() -> Self {
+        Self::new()
+            .name("protobuf")
+            .dependency_filters(Self::protobuf_dependency_filters)
+    }
+
     #[must_use]
     pub fn build(self) -> Preset {
         Preset {
@@ -137,6 +212,8 @@ impl Builder {
             spec_filters: self.global_filters,
             dependency_filters: self.dependency_filters,
             force_filters: self.force_filters,
+            preserve_unknown_fields: self.preserve_unknown_fields,
+            strict_version: self.strict_version,
         }
     }
 
@@ -188,16 +265,20 @@ impl Builder {
         self
     }
 
+    #[must_use]
+    pub fn preserve_unknown_fields(mut self, preserve: bool) -> Self {
+        self.preserve_unknown_fields = preserve;
+        self
+    }
+
+    #[must_use]
+    pub fn strict_version(mut self, strict: bool) -> Self {
+        self.strict_version = strict;
+        self
+    }
+
     fn default_cache() -> String {
-        home::home_dir()
-            .map_or_else(
-                || {
-                    log::warn!("Cannot find user home directory, using tempdir as home");
-                    temp_dir()
-                },
-                |home| home,
-            )
-            .join(".vendify")
+        default_cache_dir()
             .into_os_string()
             .into_string()
             .unwrap_or_else(|_| ".vendify".into())
@@ -206,6 +287,12 @@ impl Builder {
     fn default_dependency_filters(_: &Dependency) -> Filters {
         Filters::new()
     }
+
+    fn protobuf_dependency_filters(_: &Dependency) -> Filters {
+        let mut filters = Filters::new();
+        filters.add(FilterKind::Extension(vec!["proto".into()]));
+        filters
+    }
 }
 
 impl Default for Builder {
@@ -214,11 +301,80 @@ impl Default for Builder {
     }
 }
 
+/// Environment variable that overrides the default cache directory.
+pub const CACHE_ENV: &str = "VENDOR_CACHE";
+
+/// Returns the default cache directory, resolved in order from:
+///
+///  1) The `VENDOR_CACHE` environment variable.
+///  2) The `vendify` folder of `$XDG_CACHE_HOME`.
+///  3) The `.vendify` folder of the home directory, or of the temporary
+///     directory when there is no home directory.
+#[must_use]
+pub fn default_cache_dir() -> PathBuf {
+    cache_dir_with(|name| env::var_os(name), home::home_dir)
+}
+
+fn cache_dir_with(
+    lookup: impl Fn(&str) -> Option<OsString>,
+    home_dir: impl FnOnce() -> Option<PathBuf>,
+) -> PathBuf {
+    let lookup = |name| lookup(name).filter(|value| !value.is_empty());
+    if let Some(dir) = lookup(CACHE_ENV) {
+        return dir.into();
+    }
+    if let Some(dir) = lookup("XDG_CACHE_HOME") {
+        return PathBuf::from(dir).join("vendify");
+    }
+    home_dir()
+        .unwrap_or_else(|| {
+            log::warn!("Cannot find user home directory, using tempdir as home");
+            temp_dir()
+        })
+        .join(".vendify")
+}
+
 #[cfg(test)]
 mod tests {
 
     use super::*;
 
+    fn lookup<'a>(vars: &'a [(&str, &str)]) -> impl Fn(&str) -> Option<OsString> + 'a {
+        move |name| {
+            vars.iter()
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| value.into())
+        }
+    }
+
+    #[test]
+    fn test_cache_dir_resolution() {
+        let home = || Some(PathBuf::from("/home/user"));
+        let env = [(CACHE_ENV, "/env/cache"), ("XDG_CACHE_HOME", "/xdg")];
+
+        assert_eq!(
+            PathBuf::from("/env/cache"),
+            cache_dir_with(lookup(&env), home)
+        );
+        assert_eq!(
+            PathBuf::from("/xdg/vendify"),
+            cache_dir_with(lookup(&env[1..]), home)
+        );
+        assert_eq!(
+            PathBuf::from("/home/user/.vendify"),
+            cache_dir_with(lookup(&[]), home)
+        );
+        assert_eq!(
+            PathBuf::from("/home/user/.vendify"),
+            cache_dir_with(lookup(&[(CACHE_ENV, ""), ("XDG_CACHE_HOME", "")]), home),
+            "empty variables are ignored"
+        );
+        assert_eq!(
+            temp_dir().join(".vendify"),
+            cache_dir_with(lookup(&[]), || None)
+        );
+    }
+
     #[test]
     fn test_default_preset_equals_itself() {
         assert_eq!(Preset::default(), Preset::default());
@@ -242,6 +398,8 @@ mod tests {
         assert_eq!(".vendor.yml", sut.spec());
         assert_eq!(".vendor-lock.yml", sut.spec_lock());
         assert!(!sut.force_filters());
+        assert!(sut.preserve_unknown_fields());
+        assert!(!sut.strict_version());
 
         assert_eq!(Filters::new(), sut.global_filters());
         assert_eq!(".vendor-lock.yml", sut.spec_lock());
@@ -249,4 +407,29 @@ mod tests {
         let dep = &Dependency::new("some-url", "some-branch");
         assert_eq!(Filters::new(), sut.dependency_filters(dep));
     }
+
+    #[test]
+    fn test_registry_builtin() {
+        let sut = Registry::builtin();
+
+        assert_eq!(vec!["default", "protobuf"], sut.names());
+        assert_eq!(Some(&Preset::default()), sut.get("default"));
+        assert_eq!(None, sut.get("missing"));
+
+        let protobuf = sut.get("protobuf").unwrap();
+        let dep = &Dependency::new("some-url", "some-branch");
+        assert_eq!("protobuf", protobuf.name());
+        assert_eq!(vec!["proto"], protobuf.dependency_filters(dep).extensions);
+    }
+
+    #[test]
+    fn test_registry_register_replaces_by_name() {
+        let mut sut = Registry::builtin();
+        let custom = Builder::new().name("protobuf").force_filters(true).build();
+
+        sut.register(custom.clone());
+
+        assert_eq!(vec!["default", "protobuf"], sut.names());
+        assert_eq!(Some(&custom), sut.get("protobuf"));
+    }
 }
@@ -0,0 +1,148 @@
+use std::env;
+
+/// Returns the proxy to reach `url` through, if any.
+///
+/// Only http and https urls are proxied. Hosts listed in `NO_PROXY` are
+/// reached directly, otherwise the `explicit` proxy of the spec takes
+/// precedence over the `HTTPS_PROXY` or `HTTP_PROXY` environment variables.
+pub fn resolve(url: &str, explicit: Option<&str>) -> Option<String> {
+    resolve_with(url, explicit, |name| env::var(name).ok())
+}
+
+fn resolve_with(
+    url: &str,
+    explicit: Option<&str>,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Option<String> {
+    let lookup = |name: &str| {
+        lookup(name)
+            .or_else(|| lookup(&name.to_ascii_lowercase()))
+            .filter(|value| !value.trim().is_empty())
+    };
+    let (scheme, rest) = url.split_once("://")?;
+    let is_https = match scheme.to_ascii_lowercase().as_str() {
+        "https" => true,
+        "http" => false,
+        _ => return None,
+    };
+    let authority = rest.split('/').next().unwrap_or_default();
+    let host = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+    let host = host.split(':').next().unwrap_or_default();
+    if lookup("NO_PROXY").is_some_and(|no_proxy| is_excluded(&no_proxy, host)) {
+        return None;
+    }
+    explicit.map(ToString::to_string).or_else(|| {
+        lookup(if is_https {
+            "HTTPS_PROXY"
+        } else {
+            "HTTP_PROXY"
+        })
+    })
+}
+
+/// Returns whether the host matches any entry of a `NO_PROXY` list, which
+/// matches the host itself and its subdomains, or every host with `*`.
+fn is_excluded(no_proxy: &str, host: &str) -> bool {
+    no_proxy
+        .split(',')
+        .map(|entry| entry.trim().trim_start_matches('.'))
+        .map(|entry| entry.split(':').next().unwrap_or_default())
+        .filter(|entry| !entry.is_empty())
+        .any(|entry| {
+            entry == "*"
+                || host.eq_ignore_ascii_case(entry)
+                || host
+                    .to_ascii_lowercase()
+                    .ends_with(&format!(".{}", entry.to_ascii_lowercase()))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn lookup<'a>(vars: &'a [(&str, &str)]) -> impl Fn(&str) -> Option<String> + 'a {
+        move |name| {
+            vars.iter()
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| (*value).to_string())
+        }
+    }
+
+    #[test]
+    fn test_proxy_from_environment() {
+        let env = [
+            ("HTTPS_PROXY", "http://secure:3128"),
+            ("http_proxy", "http://plain:3128"),
+        ];
+
+        assert_eq!(
+            Some("http://secure:3128".into()),
+            resolve_with("https://github.com/a/b", None, lookup(&env))
+        );
+        assert_eq!(
+            Some("http://plain:3128".into()),
+            resolve_with("http://github.com/a/b", None, lookup(&env))
+        );
+        assert_eq!(None, resolve_with("git@github.com:a/b", None, lookup(&env)));
+        assert_eq!(None, resolve_with("/srv/git/a.git", None, lookup(&env)));
+    }
+
+    #[test]
+    fn test_proxy_explicit_overrides_environment() {
+        let env = [("HTTPS_PROXY", "http://env:3128")];
+
+        assert_eq!(
+            Some("http://spec:8080".into()),
+            resolve_with(
+                "https://github.com/a/b",
+                Some("http://spec:8080"),
+                lookup(&env)
+            )
+        );
+        assert_eq!(
+            Some("http://spec:8080".into()),
+            resolve_with(
+                "https://github.com/a/b",
+                Some("http://spec:8080"),
+                lookup(&[])
+            )
+        );
+    }
+
+    #[test]
+    fn test_proxy_respects_no_proxy() {
+        let env = [
+            ("HTTPS_PROXY", "http://env:3128"),
+            ("NO_PROXY", "localhost, .corp.example.com,git.internal:8443"),
+        ];
+
+        for url in [
+            "https://corp.example.com/a/b",
+            "https://git.corp.example.com/a/b",
+            "https://user@GIT.INTERNAL:8443/a/b",
+            "https://localhost/a/b",
+        ] {
+            assert_eq!(
+                None,
+                resolve_with(url, Some("http://spec:8080"), lookup(&env)),
+                "{url}"
+            );
+        }
+        assert_eq!(
+            Some("http://env:3128".into()),
+            resolve_with("https://notcorp.example.com/a/b", None, lookup(&env))
+        );
+        assert_eq!(
+            None,
+            resolve_with(
+                "https://github.com/a/b",
+                None,
+                lookup(&[("HTTPS_PROXY", "http://env:3128"), ("no_proxy", "*")])
+            )
+        );
+    }
+}
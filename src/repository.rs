@@ -1,51 +1,492 @@
+use std::fs;
 use std::path::Path;
 use std::path::PathBuf;
 
 use anyhow::format_err;
 use anyhow::Result;
 
-use self::git::Git;
+pub use self::git::is_default_refname;
+pub use self::git::semver_range;
+pub use self::git::version_file;
+pub use self::git::Git;
 use crate::deps::Dependency;
+use crate::error::prefixed;
+use crate::error::VendorError;
+use crate::suggest::suggestions;
 
 mod git;
+mod tags;
+
+/// Lists the references of remote repositories without cloning them. The
+/// default implementation talks the git protocol, but it can be replaced by
+/// implementations backed by the APIs of the git hosting services.
+pub trait RemoteRefs: Send + Sync {
+    /// Returns the references advertised by the remote, as pairs of the full
+    /// reference name and the commit id it points to.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the remote cannot be listed.
+    fn list_refs(&self, url: &str) -> Result<Vec<(String, String)>>;
+
+    /// Resolves a branch or tag name into the commit id advertised by the
+    /// remote. Annotated tags are peeled to the commit they point to.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the refname cannot be found.
+    fn resolve(&self, url: &str, refname: &str) -> Result<String> {
+        let refs = self.list_refs(url)?;
+        [
+            format!("refs/heads/{refname}"),
+            format!("refs/tags/{refname}^{{}}"),
+            format!("refs/tags/{refname}"),
+            refname.to_string(),
+        ]
+        .iter()
+        .find_map(|candidate| refs.iter().find(|(name, _)| name == candidate))
+        .map(|(_, oid)| oid.clone())
+        .ok_or_else(|| {
+            VendorError::RefNotFound(format!("cannot find refname '{refname}' in {url}")).into()
+        })
+    }
+}
+
+/// How the repositories are cloned into the cache.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CloneOptions {
+    /// Only clone the history committed since this date.
+    pub shallow_since: Option<String>,
+
+    /// Clone without the file contents, they are fetched from the remote
+    /// when a commit is checked out.
+    pub partial: bool,
+
+    /// Proxy to reach the remote through, see [`crate::proxy::resolve`].
+    pub proxy: Option<String>,
+
+    /// Name of the environment variable holding the token offered to the
+    /// remote, see [`crate::spec::Spec::token_env`].
+    pub token_env: Option<String>,
+
+    /// Clone without a working tree, see [`Git::clone_bare`].
+    pub bare: bool,
+}
 
 pub struct Repository {
     path: PathBuf,
+    worktree: Option<PathBuf>,
 }
 
 impl Repository {
     pub fn new<P: AsRef<Path>>(path: P) -> Self {
         Self {
             path: path.as_ref().to_owned(),
+            worktree: None,
         }
     }
 
+    /// Checks out the files of a bare repository into `worktree`, which is
+    /// removed once the repository is dropped.
+    pub fn with_worktree(mut self, worktree: impl Into<PathBuf>) -> Self {
+        self.worktree = Some(worktree.into());
+        self
+    }
+
     pub fn path(&self) -> &Path {
         &self.path
     }
 
+    /// Returns the folder with the checked out files, the worktree of bare
+    /// repositories.
+    pub fn workdir(&self) -> &Path {
+        self.worktree.as_deref().unwrap_or(&self.path)
+    }
+
+    /// Returns whether the repository has no working tree, see
+    /// [`Git::is_bare`].
+    pub fn is_bare(&self) -> bool {
+        Git::is_bare(&self.path)
+    }
+
     pub fn checkout(&self, refname: &str) -> Result<()> {
-        Git::checkout(&self.path, refname)
+        Git::checkout(&self.path, refname)?;
+        self.checkout_worktree()
     }
 
     pub fn fetch(&self, refname: &str) -> Result<()> {
         Git::fetch(&self.path, refname)
     }
 
+    /// Returns whether the commit is already in the repository, see
+    /// [`Git::has_commit`].
+    pub fn has_commit(&self, oid: &str) -> bool {
+        Git::has_commit(&self.path, oid)
+    }
+
+    /// Fetches the history of the refname committed since `since`, see
+    /// [`Git::shallow_fetch`].
+    pub fn shallow_fetch(&self, refname: &str, since: &str) -> Result<()> {
+        Git::shallow_fetch(&self.path, refname, since)
+    }
+
     pub fn reset(&self, refname: &str) -> Result<()> {
-        Git::reset(&self.path, refname)
+        Git::reset(&self.path, refname)?;
+        self.checkout_worktree()
+    }
+
+    pub fn get_current_tree(&self) -> Result<String> {
+        Git::get_current_tree(&self.path).map(|oid| oid.to_string())
+    }
+
+    /// Returns whether the checked out tree contains Git LFS pointer files.
+    pub fn has_lfs_pointers(&self) -> Result<bool> {
+        Git::has_lfs_pointers(&self.path)
+    }
+
+    /// Replaces the Git LFS pointer files with their contents, see
+    /// [`Git::lfs_checkout`].
+    pub fn lfs_checkout(&self, offline: bool) -> Result<()> {
+        Git::lfs_checkout(&self.path, offline)
+    }
+
+    /// Restores the working tree to the checked out commit, see
+    /// [`Git::clean`].
+    pub fn clean(&self) -> Result<()> {
+        Git::clean(&self.path)
+    }
+
+    /// Compacts the repository, see [`Git::gc`].
+    pub fn gc(&self) -> Result<()> {
+        Git::gc(&self.path)
+    }
+
+    /// Initializes and updates the submodules of the checked out commit, see
+    /// [`Git::update_submodules`].
+    pub fn update_submodules(&self, offline: bool) -> Result<()> {
+        Git::update_submodules(&self.path, offline)
+    }
+
+    /// Empties the folders of the submodules, see [`Git::clear_submodules`].
+    pub fn clear_submodules(&self) -> Result<()> {
+        Git::clear_submodules(&self.path)
+    }
+
+    /// Returns the contents of a file of the refname, see [`Git::read_file`].
+    pub fn read_file(&self, refname: &str, path: &str) -> Result<String> {
+        Git::read_file(&self.path, refname, path)
+    }
+
+    /// Returns the commit that the refname points to, see
+    /// [`Git::resolve_commit`].
+    pub fn resolve_commit(&self, refname: &str) -> Result<String> {
+        Git::resolve_commit(&self.path, refname)
+    }
+
+    /// Returns the files that changed from one commit to the other, see
+    /// [`Git::changed_paths`].
+    pub fn changed_paths(&self, from: &str, to: &str) -> Result<Vec<PathBuf>> {
+        Git::changed_paths(&self.path, from, to)
+    }
+
+    /// Returns whether the commit is reachable from the refname, see
+    /// [`Git::is_ancestor`].
+    pub fn is_ancestor(&self, commit: &str, refname: &str) -> Result<bool> {
+        Git::is_ancestor(&self.path, commit, refname)
+    }
+
+    /// Fetches the tags of the remote, see [`Git::fetch_tags`].
+    pub fn fetch_tags(&self) -> Result<()> {
+        Git::fetch_tags(&self.path)
+    }
+
+    /// Returns the highest tag that matches the semver range, see
+    /// [`tags::highest_matching`].
+    pub fn highest_tag(&self, range: &str) -> Result<String> {
+        let tags = Git::list_tags(&self.path)?;
+        tags::highest_matching(range, &tags).map(ToString::to_string)
+    }
+
+    /// Returns the default branch of the remote, see [`Git::default_branch`].
+    pub fn default_branch(&self) -> Result<String> {
+        Git::default_branch(&self.path)
+    }
+
+    /// Refreshes the default branch of the remote, see
+    /// [`Git::fetch_default_branch`].
+    pub fn fetch_default_branch(&self) -> Result<String> {
+        Git::fetch_default_branch(&self.path)
+    }
+
+    /// Returns the branches and tags of the repository, see
+    /// [`Git::list_refs`].
+    pub fn list_refs(&self) -> Result<Vec<String>> {
+        Git::list_refs(&self.path)
+    }
+
+    /// Resolves a refname ignoring its case, returning the name of the
+    /// matching branch or tag as spelled in the repository. Exact matches take
+    /// precedence, and ambiguous matches are rejected.
+    pub fn resolve_refname_ignore_case(&self, refname: &str) -> Result<String> {
+        if Git::has_refname(&self.path, refname)? {
+            return Ok(refname.to_string());
+        }
+
+        let refs = self.list_refs()?;
+        let candidates: Vec<&str> = refs
+            .iter()
+            .filter(|name| name.eq_ignore_ascii_case(refname))
+            .map(String::as_str)
+            .collect();
+        match candidates.as_slice() {
+            [name] => Ok((*name).to_string()),
+            [] => Err(format_err!(
+                "cannot find refname '{refname}'{}",
+                suggestions(refname, &refs)
+            )),
+            _ => Err(format_err!(
+                "refname '{refname}' is ambiguous, it matches: {}",
+                candidates.join(", ")
+            )),
+        }
     }
 
     pub fn get_current_refname(&self) -> Result<String> {
         Git::get_current_refname(&self.path).map(|oid| oid.to_string())
     }
 
-    pub fn ensure(self, dep: &Dependency) -> Result<Self> {
-        let result = Git::open_or_clone(&dep.url, &dep.refname, &self.path);
+    /// Returns the commit time of the current commit, in seconds since the
+    /// unix epoch.
+    pub fn get_current_commit_time(&self) -> Result<i64> {
+        Git::get_current_commit_time(&self.path)
+    }
+
+    /// Exports the commits between two refnames as patch files, see
+    /// [`Git::format_patches`].
+    pub fn format_patches(&self, from: &str, to: &str, dir: &Path) -> Result<Vec<PathBuf>> {
+        Git::format_patches(&self.path, from, to, dir)
+    }
+
+    /// Returns a predicate that tells whether a path is marked as
+    /// `export-ignore` in the `.gitattributes` of the repository.
+    pub fn export_ignore(&self) -> Result<impl Fn(&Path) -> bool> {
+        Git::export_ignore(&self.path, self.worktree.as_deref())
+    }
+
+    /// Opens the repository without reaching the network, fails when the
+    /// repository has not been cloned yet.
+    pub fn open(self) -> Result<Self> {
+        match Git::open(&self.path) {
+            Ok(()) => Ok(self),
+            Err(err) => Err(prefixed(err, "cannot open repository")),
+        }
+    }
+
+    /// Opens the repository, cloning it with `options` when it is not cached
+    /// yet. Refnames read from a version file, or matched against a semver
+    /// range, are cloned from the default branch.
+    pub fn ensure(self, dep: &Dependency, options: &CloneOptions) -> Result<Self> {
+        let is_indirect =
+            version_file(&dep.refname).is_some() || semver_range(&dep.refname).is_some();
+        let refname = if is_indirect { "" } else { &dep.refname };
+        let result = Git::open_or_clone(&dep.url, refname, options, &self.path);
 
         match result {
-            Ok(_) => Ok(self),
-            Err(err) => Err(format_err!("cannot open repository: {}", err)),
+            Ok(()) => Ok(self),
+            Err(err) => Err(prefixed(err, "cannot open repository")),
+        }
+    }
+
+    fn checkout_worktree(&self) -> Result<()> {
+        match &self.worktree {
+            Some(worktree) => Git::checkout_worktree(&self.path, worktree),
+            None => Ok(()),
         }
     }
 }
+
+impl Drop for Repository {
+    fn drop(&mut self) {
+        if let Some(worktree) = self.worktree.as_ref().filter(|worktree| worktree.exists()) {
+            if let Err(err) = fs::remove_dir_all(worktree) {
+                log::warn!("cannot remove worktree {}: {err}", worktree.display());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::svec;
+    use crate::test_utils::git_init;
+    use crate::test_utils::tempdir;
+
+    struct StaticRefs(Vec<(&'static str, &'static str)>);
+
+    impl RemoteRefs for StaticRefs {
+        fn list_refs(&self, _url: &str) -> Result<Vec<(String, String)>> {
+            Ok(self
+                .0
+                .iter()
+                .map(|(name, oid)| ((*name).to_string(), (*oid).to_string()))
+                .collect())
+        }
+    }
+
+    #[test]
+    fn test_remote_refs_resolve() -> Result<()> {
+        let sut = StaticRefs(vec![
+            ("HEAD", "1"),
+            ("refs/heads/main", "2"),
+            ("refs/tags/v1.0.0", "3"),
+            ("refs/tags/v2.0.0", "4"),
+            ("refs/tags/v2.0.0^{}", "5"),
+        ]);
+
+        assert_eq!("1", sut.resolve("url", "HEAD")?);
+        assert_eq!("2", sut.resolve("url", "main")?);
+        assert_eq!("3", sut.resolve("url", "v1.0.0")?);
+        assert_eq!("5", sut.resolve("url", "v2.0.0")?);
+        assert_eq!(
+            "cannot find refname 'v3.0.0' in url",
+            sut.resolve("url", "v3.0.0").unwrap_err().to_string()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_repository_has_lfs_pointers() -> Result<()> {
+        let pointer = "version https://git-lfs.github.com/spec/v1
+oid sha256:4d7a214614ab2935c943f9e0ff69d22eadbb8f32b1258daaa5e2ca24d17e2393
+size 12345
+";
+        let dir = tempdir();
+        let git = git_init(dir.path(), &[("file.txt", "data")]);
+        let sut = Repository::new(dir.path());
+        assert!(!sut.has_lfs_pointers()?);
+
+        crate::test_utils::git_commit(&git, &[("nested/large.bin", pointer)], "lfs");
+        assert!(sut.has_lfs_pointers()?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_git_remote_refs_resolve() -> Result<()> {
+        let dir = tempdir();
+        let git = git_init(dir.path(), &[("file.txt", "data")]);
+        let head = git.head()?.peel_to_commit()?.id().to_string();
+
+        let actual = Git.resolve(&dir.path().to_string_lossy(), "master")?;
+
+        assert_eq!(head, actual);
+        Ok(())
+    }
+
+    #[test]
+    fn test_repository_format_patches() -> Result<()> {
+        let upstream = tempdir();
+        let git = git_init(upstream.path(), &[("file.txt", "data")]);
+        let from = git.head()?.peel_to_commit()?.id().to_string();
+        crate::test_utils::git_commit(&git, &[("a.txt", "a")], "Add a");
+        crate::test_utils::git_commit(&git, &[("b.txt", "b")], "Add b");
+        crate::test_utils::git_commit(&git, &[("file.txt", "changed")], "Change file: v2");
+        let patches = tempdir();
+        let sut = Repository::new(upstream.path());
+
+        let actual = sut.format_patches(&from, "master", patches.path())?;
+
+        let names: Vec<_> = actual
+            .iter()
+            .map(|path| path.file_name().unwrap().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(
+            vec![
+                "0001-Add-a.patch",
+                "0002-Add-b.patch",
+                "0003-Change-file-v2.patch"
+            ],
+            names
+        );
+        let first = std::fs::read_to_string(&actual[0])?;
+        assert!(first.contains("Subject: [PATCH 1/3] Add a"), "{first}");
+        assert!(first.contains("+++ b/a.txt"), "{first}");
+        let last = std::fs::read_to_string(&actual[2])?;
+        assert!(
+            last.contains("Subject: [PATCH 3/3] Change file: v2"),
+            "{last}"
+        );
+        assert!(last.contains("+changed"), "{last}");
+        Ok(())
+    }
+
+    #[test]
+    fn test_repository_is_ancestor() -> Result<()> {
+        let (dir, sut) = get_repository_with_tags(&["v1"]);
+        let git = git2::Repository::open(dir.path())?;
+        let first = git.head()?.peel_to_commit()?.id().to_string();
+        let second = crate::test_utils::git_commit(&git, &[("a.txt", "a")], "Add a").to_string();
+
+        assert!(sut.is_ancestor(&first, "master")?);
+        assert!(sut.is_ancestor(&second, "master")?);
+        assert!(sut.is_ancestor(&first, "v1")?);
+        assert!(!sut.is_ancestor(&second, "v1")?);
+        Ok(())
+    }
+
+    fn get_repository_with_tags(tags: &[&str]) -> (tempfile::TempDir, Repository) {
+        let dir = tempdir();
+        let git = git_init(dir.path(), &[("file.txt", "data")]);
+        let head = git.head().unwrap().peel_to_commit().unwrap();
+        for tag in tags {
+            git.tag_lightweight(tag, head.as_object(), false).unwrap();
+        }
+        let repository = Repository::new(dir.path());
+        (dir, repository)
+    }
+
+    #[test]
+    fn test_repository_resolve_refname_ignore_case() -> Result<()> {
+        let (_dir, sut) = get_repository_with_tags(&["v1.2.0"]);
+
+        assert_eq!("v1.2.0", sut.resolve_refname_ignore_case("V1.2.0")?);
+        assert_eq!("v1.2.0", sut.resolve_refname_ignore_case("v1.2.0")?);
+        assert_eq!("master", sut.resolve_refname_ignore_case("MASTER")?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_repository_list_refs() {
+        let (_dir, sut) = get_repository_with_tags(&["v1.2.0", "v1.0.0"]);
+
+        assert_eq!(
+            svec!["master", "v1.0.0", "v1.2.0"],
+            sut.list_refs().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_repository_resolve_refname_ignore_case_not_found() {
+        let (_dir, sut) = get_repository_with_tags(&["v1.2.0"]);
+
+        let actual = sut.resolve_refname_ignore_case("v2.0.0");
+
+        assert_eq!(
+            "cannot find refname 'v2.0.0', did you mean 'v1.2.0'?",
+            actual.expect_err("should not resolve").to_string()
+        );
+    }
+
+    #[test]
+    fn test_repository_resolve_refname_ignore_case_ambiguous() {
+        let (_dir, sut) = get_repository_with_tags(&["release", "Release"]);
+
+        let actual = sut.resolve_refname_ignore_case("RELEASE");
+
+        assert_eq!(
+            "refname 'RELEASE' is ambiguous, it matches: Release, release",
+            actual.expect_err("should not resolve").to_string()
+        );
+    }
+}
@@ -0,0 +1,71 @@
+use anyhow::Result;
+use semver::Version;
+use semver::VersionReq;
+
+use crate::error::VendorError;
+
+/// Returns the highest of the tags that matches the semver `range`. Tags are
+/// parsed as versions, with an optional `v` prefix, the other tags are
+/// skipped. As in Cargo, pre-releases only match the ranges that name a
+/// pre-release of the same major, minor and patch version.
+pub fn highest_matching<'t>(range: &str, tags: &'t [String]) -> Result<&'t str> {
+    let req = VersionReq::parse(range)
+        .map_err(|err| VendorError::Other(format!("invalid semver range '{range}': {err}")))?;
+    tags.iter()
+        .filter_map(|tag| {
+            let version = Version::parse(tag.strip_prefix('v').unwrap_or(tag)).ok()?;
+            Some((version, tag))
+        })
+        .filter(|(version, _)| req.matches(version))
+        .max_by(|(a, _), (b, _)| a.cmp(b))
+        .map(|(_, tag)| tag.as_str())
+        .ok_or_else(|| {
+            VendorError::RefNotFound(format!("no tag matches the semver range '{range}'")).into()
+        })
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::svec;
+
+    #[test]
+    fn test_highest_matching() -> Result<()> {
+        let tags = svec![
+            "v1.0.0",
+            "v1.2.0",
+            "1.2.5",
+            "v1.3.0-beta.1",
+            "v2.0.0",
+            "latest"
+        ];
+
+        assert_eq!("1.2.5", highest_matching("^1.2", &tags)?);
+        assert_eq!("v1.0.0", highest_matching("~1.0", &tags)?);
+        assert_eq!("v2.0.0", highest_matching(">=1", &tags)?);
+        assert_eq!(
+            "1.2.5",
+            highest_matching("<2", &tags)?,
+            "skips pre-releases"
+        );
+        assert_eq!("v1.3.0-beta.1", highest_matching("^1.3.0-beta", &tags)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_highest_matching_errors() {
+        let tags = svec!["v1.0.0"];
+
+        let actual = highest_matching("^3", &tags).unwrap_err();
+        assert_eq!("no tag matches the semver range '^3'", actual.to_string());
+        assert!(matches!(
+            actual.downcast_ref::<VendorError>(),
+            Some(VendorError::RefNotFound(_))
+        ));
+        assert!(highest_matching("not a range", &tags)
+            .unwrap_err()
+            .to_string()
+            .starts_with("invalid semver range 'not a range': "));
+    }
+}
@@ -0,0 +1,152 @@
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::thread;
+use std::time::Duration;
+
+use anyhow::Result;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::timeout;
+
+/// Retry policy for the operations that reach the network. Delays grow
+/// exponentially between attempts, plus a random jitter that spreads the
+/// retries of parallel runs against the same server.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Retry {
+    /// Number of retries after the first failed attempt.
+    #[serde(default)]
+    pub attempts: u32,
+
+    /// Delay before the first retry, in milliseconds. It doubles on every
+    /// subsequent retry.
+    #[serde(default)]
+    pub backoff_ms: u64,
+
+    /// Upper bound of the random delay added to each retry, in milliseconds.
+    #[serde(default)]
+    pub jitter_ms: u64,
+}
+
+impl Retry {
+    /// Returns the delay to wait before the given retry, starting at zero.
+    pub fn delay(&self, retry: u32) -> Duration {
+        let backoff = self.backoff_ms.saturating_mul(1 << retry.min(16));
+        let jitter = if self.jitter_ms == 0 {
+            0
+        } else {
+            RandomState::new().hash_one(retry) % (self.jitter_ms + 1)
+        };
+        Duration::from_millis(backoff.saturating_add(jitter))
+    }
+
+    /// Runs the operation until it succeeds or the retries are exhausted,
+    /// returning the last error. Timeouts are not retried, as the operation
+    /// that timed out may still be running, see [`timeout::run`].
+    pub fn run<T>(&self, what: &str, mut operation: impl FnMut() -> Result<T>) -> Result<T> {
+        let mut retry = 0;
+        loop {
+            match operation() {
+                Ok(value) => return Ok(value),
+                Err(err) if retry < self.attempts && !timeout::is_timed_out(&err) => {
+                    let delay = self.delay(retry);
+                    log::warn!("{what} failed, retrying in {delay:?}: {err}");
+                    thread::sleep(delay);
+                    retry += 1;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use anyhow::format_err;
+
+    use super::*;
+
+    #[test]
+    fn test_retry_delay_without_jitter() {
+        let sut = Retry {
+            attempts: 3,
+            backoff_ms: 100,
+            jitter_ms: 0,
+        };
+
+        assert_eq!(Duration::from_millis(100), sut.delay(0));
+        assert_eq!(Duration::from_millis(200), sut.delay(1));
+        assert_eq!(Duration::from_millis(400), sut.delay(2));
+    }
+
+    #[test]
+    fn test_retry_delay_with_jitter_within_bounds() {
+        let sut = Retry {
+            attempts: 5,
+            backoff_ms: 10,
+            jitter_ms: 50,
+        };
+
+        for retry in 0..5 {
+            for _ in 0..20 {
+                let backoff = 10 << retry;
+                let delay = sut.delay(retry).as_millis();
+                assert!(
+                    (backoff..=backoff + 50).contains(&delay),
+                    "retry {retry} delay {delay}ms out of bounds"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_retry_run() {
+        let sut = Retry {
+            attempts: 2,
+            ..Retry::default()
+        };
+
+        let mut calls = 0;
+        let actual = sut.run("operation", || {
+            calls += 1;
+            if calls < 3 {
+                Err(format_err!("failure {calls}"))
+            } else {
+                Ok(calls)
+            }
+        });
+        assert_eq!(3, actual.unwrap());
+
+        let mut calls = 0;
+        let actual: Result<()> = sut.run("operation", || {
+            calls += 1;
+            Err(format_err!("failure {calls}"))
+        });
+        assert_eq!("failure 3", actual.unwrap_err().to_string());
+    }
+
+    #[test]
+    fn test_retry_run_does_not_retry_timeouts() {
+        let sut = Retry {
+            attempts: 2,
+            ..Retry::default()
+        };
+
+        let mut calls = 0;
+        let actual: Result<()> = sut.run("operation", || {
+            calls += 1;
+            timeout::run("operation", Duration::from_millis(10), (), || {
+                thread::sleep(Duration::from_millis(500));
+                Ok(())
+            })
+        });
+
+        assert!(timeout::is_timed_out(&actual.unwrap_err()));
+        assert_eq!(1, calls);
+    }
+}
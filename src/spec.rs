@@ -1,16 +1,261 @@
+use std::collections::BTreeMap;
 use std::fmt::Debug;
+use std::mem;
+use std::path::Component;
+use std::path::Path;
+use std::path::PathBuf;
 
+use anyhow::format_err;
 use anyhow::Result;
 use serde::Deserialize;
 use serde::Serialize;
 
 use crate::deps::Dependency;
 use crate::filters::Filters;
+use crate::interpolate::has_references;
+use crate::interpolate::interpolate;
 use crate::preset::Preset;
+use crate::preset::Registry;
+use crate::repository::semver_range;
+use crate::repository::version_file;
+use crate::repository::CloneOptions;
+use crate::retry::Retry;
+use crate::suggest::suggestions;
+use crate::timeout;
+use crate::url;
+use crate::version;
 use crate::yaml;
 use crate::VERSION;
 
-#[derive(Debug, Eq, PartialEq, Serialize, Deserialize)]
+/// Prefix of the refnames that are aliases of a version, see
+/// [`Spec::versions`].
+const ALIAS_PREFIX: char = '@';
+
+/// Top-level keys of the spec file, including the ones of the flattened
+/// [`Filters`], unknown fields are checked against them to suggest what was
+/// meant.
+const FIELDS: &[&str] = &[
+    "version",
+    "preset",
+    "vendor",
+    "vendor_git",
+    "base_url",
+    "targets",
+    "ignores",
+    "extensions",
+    "rename_template",
+    "exclude_binary",
+    "include_hidden",
+    "empty_targets",
+    "extension_mismatch",
+    "case_collisions",
+    "respect_export_ignore",
+    "respect_vendor_ignore",
+    "max_files_per_dependency",
+    "max_files",
+    "max_total_bytes",
+    "skip_failed_files",
+    "case_insensitive_refs",
+    "lock_trees",
+    "lock_checksums",
+    "check_ancestry",
+    "local_modifications",
+    "lfs",
+    "submodules",
+    "source_comments",
+    "strip_trailing_whitespace",
+    "transform_extensions",
+    "require_license_header",
+    "reproducible",
+    "delta_updates",
+    "hardlink",
+    "shallow_since",
+    "partial_clone",
+    "bare_cache",
+    "proxy",
+    "token_env",
+    "timeout_secs",
+    "retry",
+    "collisions",
+    "keep_dirs",
+    "manifest",
+    "post_install",
+    "includes",
+    "versions",
+    "deps",
+];
+
+/// Behaviour when the vendor folder contains a `.git` entry, as it happens
+/// when the vendor folder is a git submodule or a nested repository.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VendorGit {
+    /// Refuse to recreate the vendor folder.
+    #[default]
+    Refuse,
+
+    /// Recreate the vendor folder, keeping its `.git` entry.
+    Preserve,
+}
+
+impl VendorGit {
+    // Takes a reference, as serde passes one to `skip_serializing_if`.
+    #[allow(clippy::trivially_copy_pass_by_ref)]
+    fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// How a condition that is likely a configuration mistake is reported.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// Log a warning and carry on.
+    #[default]
+    Warn,
+
+    /// Fail the import of the dependency.
+    Error,
+}
+
+impl Severity {
+    #[allow(clippy::trivially_copy_pass_by_ref)]
+    fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// What a dependency without targets vendors.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EmptyTargets {
+    /// Every file that is not ignored, and has one of the extensions.
+    #[default]
+    All,
+
+    /// No files at all, targets have to be listed explicitly.
+    Nothing,
+}
+
+impl EmptyTargets {
+    #[allow(clippy::trivially_copy_pass_by_ref)]
+    fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// How a file that is vendored by more than one dependency is handled.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Collisions {
+    /// Keep the file of the dependency listed last in the spec, as it
+    /// overwrites the others.
+    #[default]
+    LastWins,
+
+    /// Fail the run, leaving the vendor folder untouched.
+    Error,
+
+    /// Keep the file of the dependency listed first in the spec, and log a
+    /// warning.
+    FirstWins,
+}
+
+impl Collisions {
+    #[allow(clippy::trivially_copy_pass_by_ref)]
+    fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// How the files of the dependencies are read.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ReadOptions {
+    /// Skip the files marked as `export-ignore` in the `.gitattributes` of
+    /// the dependencies, as `git archive` would do.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub respect_export_ignore: bool,
+
+    /// Skip the files matched by the `.vendorignore` file at the root of the
+    /// dependencies, which uses the `.gitignore` syntax.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub respect_vendor_ignore: bool,
+
+    /// Keep copying the other files of a dependency when one of them cannot
+    /// be copied, the files that failed are recorded in the lock.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub skip_failed_files: bool,
+}
+
+/// What the lock file records and verifies.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+pub struct LockOptions {
+    /// Record the id of the vendored git tree in the lock file. Installs
+    /// verify that the checked out tree matches the locked one.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub lock_trees: bool,
+
+    /// Record the checksums of the vendored files in the lock file, so the
+    /// vendor folder can be verified against them.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub lock_checksums: bool,
+
+    /// Warn on install when the locked commit of a dependency is no longer
+    /// reachable from its refname, as it happens when the history is
+    /// rewritten upstream. The refname is fetched even when the locked
+    /// commit is cached.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub check_ancestry: bool,
+}
+
+/// How the dependencies are resolved and checked out.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+pub struct CheckoutOptions {
+    /// Resolve the refnames of the dependencies ignoring their case, when
+    /// there is no exact match.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub case_insensitive_refs: bool,
+
+    /// Materialize the Git LFS files of the dependencies before copying them,
+    /// instead of vendoring their pointer files. Requires `git-lfs`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub lfs: bool,
+
+    /// Initialize and update the submodules of the dependencies after
+    /// checking them out, so their files are vendored too. Dependencies can
+    /// override it.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub submodules: bool,
+}
+
+/// How the vendored files are written.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+pub struct WriteOptions {
+    /// Set the modification time of the vendored files to the time of the
+    /// commit they are vendored from, instead of the time they are copied,
+    /// so vendoring the same commit twice yields identical files.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub reproducible: bool,
+
+    /// On update, only read the files that changed since the locked commit
+    /// from the repository, the others are copied from the vendor folder. It
+    /// needs `lock_checksums`, so the vendor folder is known to have no local
+    /// modifications, and it does not apply with source comments, trailing
+    /// whitespace stripping or a rename template, as the vendored files then
+    /// depend on more than their source.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub delta_updates: bool,
+
+    /// Hardlink the vendored files to copies of them kept in the cache,
+    /// instead of copying them, when both are on the same filesystem. Files
+    /// are copied when they cannot be hardlinked. Hardlinked files are
+    /// read-only, and it cannot be combined with `reproducible`, as setting
+    /// their modification time would set it on the copies of the cache.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub hardlink: bool,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub struct Spec {
     /// Version that was used to generate the spec.
     pub version: String,
@@ -22,24 +267,235 @@ pub struct Spec {
     /// Vendor directory path.
     pub vendor: String,
 
+    /// Behaviour when the vendor folder contains a `.git` entry.
+    #[serde(default, skip_serializing_if = "VendorGit::is_default")]
+    pub vendor_git: VendorGit,
+
+    /// Base url that relative dependency urls, such as `protos-common`, are
+    /// resolved against. The lock records the resolved urls.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_url: Option<String>,
+
     #[serde(flatten)]
     pub filters: Filters,
 
+    #[serde(flatten)]
+    pub read: ReadOptions,
+
+    #[serde(flatten)]
+    pub locking: LockOptions,
+
+    #[serde(flatten)]
+    pub checkout: CheckoutOptions,
+
+    #[serde(flatten)]
+    pub write: WriteOptions,
+
+    /// What the dependencies vendor when neither the spec nor the dependency
+    /// list any target.
+    #[serde(default, skip_serializing_if = "EmptyTargets::is_default")]
+    pub empty_targets: EmptyTargets,
+
+    /// Reported when the targets of a dependency match files, but none of
+    /// them has one of the configured extensions.
+    #[serde(default, skip_serializing_if = "Severity::is_default")]
+    pub extension_mismatch: Severity,
+
+    /// Reported when two files of a dependency are vendored into paths that
+    /// only differ in case, as one overwrites the other on case-insensitive
+    /// filesystems.
+    #[serde(default, skip_serializing_if = "Severity::is_default")]
+    pub case_collisions: Severity,
+
+    /// Maximum number of files that a single dependency can vendor, the copy
+    /// is aborted as soon as the limit is exceeded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_files_per_dependency: Option<usize>,
+
+    /// Maximum number of files vendored by all the dependencies together,
+    /// the dependency that exceeds it is aborted before copying more files.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_files: Option<usize>,
+
+    /// Maximum number of bytes vendored by all the dependencies together,
+    /// the dependency that exceeds it is aborted before copying more files.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_total_bytes: Option<u64>,
+
+    /// Reported before installing, when the vendor folder has files that do
+    /// not match the checksums of the lock file, as the install overwrites
+    /// those local modifications.
+    #[serde(default, skip_serializing_if = "Severity::is_default")]
+    pub local_modifications: Severity,
+
+    /// Comment syntax per file extension, used to prepend a header with the
+    /// origin of the vendored files. Files of other extensions, and binary
+    /// files, are copied unmodified.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub source_comments: BTreeMap<String, String>,
+
+    /// Strip the trailing whitespace of each line of the vendored text files
+    /// whose extension is in `transform_extensions`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub strip_trailing_whitespace: bool,
+
+    /// Extensions of the files that content transforms are applied to.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub transform_extensions: Vec<String>,
+
+    /// License headers that are approved, the vendored files with one of
+    /// the filtered extensions must contain any of them within their first
+    /// bytes, otherwise the dependency fails once all its files are copied.
+    /// Without extensions in the filters, every vendored text file must.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub require_license_header: Vec<String>,
+
+    /// Only clone and fetch the history committed since this date, such as
+    /// `2024-01-01`. Locked commits older than that cannot be installed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shallow_since: Option<String>,
+
+    /// Clone the dependencies without their file contents, which are only
+    /// fetched for the commits that are checked out. Requires the git
+    /// command line tool, and remotes that support partial clones.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub partial_clone: bool,
+
+    /// Keep the repositories of the cache as bare clones, which are smaller
+    /// to archive and restore. The files of a dependency are checked out
+    /// into a transient worktree while they are vendored. Only applies to the
+    /// repositories cloned afterwards. They are not `--mirror` clones, only
+    /// the branches and tags of the remotes are kept.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub bare_cache: bool,
+
+    /// Proxy to reach the http and https remotes through, instead of the one
+    /// of the `HTTPS_PROXY` or `HTTP_PROXY` environment variables. Hosts in
+    /// `NO_PROXY` are reached directly.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<String>,
+
+    /// Name of the environment variable holding a token, offered as the
+    /// password to the remotes that ask for credentials and have none in
+    /// their url. Credentials of git itself are tried first.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_env: Option<String>,
+
+    /// Seconds after which cloning or fetching a dependency is aborted, so an
+    /// unresponsive remote fails the dependency instead of hanging the run.
+    #[serde(
+        default = "timeout::default_secs",
+        skip_serializing_if = "timeout::is_default_secs"
+    )]
+    pub timeout_secs: u64,
+
+    /// Retry policy for cloning and fetching the dependencies.
+    #[serde(default, skip_serializing_if = "Retry::is_default")]
+    pub retry: Retry,
+
+    /// How a file that is vendored by more than one dependency is handled.
+    #[serde(default, skip_serializing_if = "Collisions::is_default")]
+    pub collisions: Collisions,
+
+    /// Directories created in the vendor folder even when no file is vendored
+    /// into them, relative to the vendor folder.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub keep_dirs: Vec<String>,
+
+    /// File written into the vendor folder, relative to it, that lists each
+    /// vendored file along with the url and the locked commit of the
+    /// dependency it comes from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub manifest: Option<String>,
+
+    /// Commands run once all the dependencies have been vendored, from the
+    /// vendor folder. Each command is a program followed by its arguments,
+    /// it is not interpreted by a shell.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub post_install: Vec<Vec<String>>,
+
+    /// Files containing one dependency each, relative to the spec file. The
+    /// included dependencies are merged into `deps` when loading the spec.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub includes: Vec<String>,
+
+    /// Versions shared by several dependencies, by alias. A dependency whose
+    /// refname is `@alias` is resolved to the version of the alias when the
+    /// spec is loaded, so they are all bumped at once.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub versions: BTreeMap<String, String>,
+
     /// List of dependencies.
     pub deps: Vec<Dependency>,
 
+    // Dependencies that have been merged from the includes, they are written
+    // back into their include file instead of the spec file.
+    #[serde(skip)]
+    included: Vec<Included>,
+
+    // Refnames of the dependencies as written before interpolating their
+    // environment variables and resolving their aliases, they are written
+    // back instead of the interpolated ones.
+    #[serde(skip)]
+    interpolated: Vec<Interpolated>,
+
+    /// Unknown fields found when loading the spec, they are only kept when
+    /// the preset asks to preserve them.
+    #[serde(flatten)]
+    extra: BTreeMap<String, serde_yaml::Value>,
+
+    /// Comments at the top of the spec file, they are written back when the
+    /// spec is saved.
+    #[serde(skip)]
+    header: String,
+
     #[serde(skip)]
     preset: Preset,
 }
 
 impl Spec {
+    #[must_use]
     pub fn with_preset(preset: &Preset) -> Self {
         let mut spec = Self {
             version: VERSION.to_string(),
             vendor: String::new(),
+            vendor_git: VendorGit::default(),
+            base_url: None,
             filters: Filters::new(),
+            read: ReadOptions::default(),
+            locking: LockOptions::default(),
+            checkout: CheckoutOptions::default(),
+            write: WriteOptions::default(),
+            empty_targets: EmptyTargets::default(),
+            extension_mismatch: Severity::default(),
+            case_collisions: Severity::default(),
+            max_files_per_dependency: None,
+            max_files: None,
+            max_total_bytes: None,
+            local_modifications: Severity::default(),
+            source_comments: BTreeMap::new(),
+            strip_trailing_whitespace: false,
+            transform_extensions: vec![],
+            require_license_header: vec![],
+            shallow_since: None,
+            partial_clone: false,
+            bare_cache: false,
+            proxy: None,
+            token_env: None,
+            timeout_secs: timeout::DEFAULT_SECS,
+            retry: Retry::default(),
+            collisions: Collisions::default(),
+            keep_dirs: vec![],
+            manifest: None,
+            post_install: vec![],
+            includes: vec![],
+            versions: BTreeMap::new(),
             deps: vec![],
-            preset_name: preset.name().to_string(),
+            included: vec![],
+            interpolated: vec![],
+            extra: BTreeMap::new(),
+            header: String::new(),
+            preset_name: preset.name().clone(),
             preset: preset.clone(),
         };
         spec.apply_preset();
@@ -47,7 +503,7 @@ impl Spec {
     }
 
     pub fn add_dependency(&mut self, mut dep: Dependency) {
-        dep.apply_preset(&self.preset);
+        dep.apply_presets(&self.dependency_presets(&dep));
         if let Some(existing) = self.get_mut_dependency(&dep) {
             existing.update_from(&dep);
         } else {
@@ -55,59 +511,508 @@ impl Spec {
         }
     }
 
+    /// Adds a dependency like [`Spec::add_dependency`], but fails when the
+    /// spec already has a dependency with the same url and name.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` when the dependency is already in the spec.
+    pub fn add_new_dependency(&mut self, dep: Dependency) -> Result<()> {
+        if self.get_mut_dependency(&dep).is_some() {
+            return Err(format_err!("dependency {} is already in the spec", dep.url));
+        }
+        self.add_dependency(dep);
+        Ok(())
+    }
+
+    /// Loads the spec file of the preset, along with its includes.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` when the spec file, or any of its includes, cannot
+    /// be read or parsed, or when the spec is not valid, see
+    /// [`Spec::validate`].
     pub fn load_from(preset: &Preset) -> Result<Self> {
         let mut spec: Self = yaml::load(preset.spec())?;
+        spec.header = yaml::leading_comments(preset.spec());
         spec.preset = preset.clone();
+        spec.interpolate()?;
+        spec.load_includes()?;
+        spec.validate()?;
         spec.apply_preset();
         Ok(spec)
     }
 
+    /// Saves the spec file, and the included dependencies into their include
+    /// files, the ones that did not change are not written.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` when any of the files cannot be written.
     pub fn save(&mut self) -> Result<()> {
         self.lint();
-        yaml::save(self, self.preset.spec())
+        let all = mem::take(&mut self.deps);
+        let (included, own): (Vec<_>, Vec<_>) = all
+            .into_iter()
+            .partition(|dep| self.find_include(dep).is_some());
+        self.deps = own;
+        self.swap_interpolated(true);
+        let result = yaml::save_with_header(self, self.preset.spec(), &self.header)
+            .and_then(|()| self.save_includes(&included));
+        self.swap_interpolated(false);
+        self.deps.extend(included);
+        self.lint();
+        result
+    }
+
+    /// Writes each dependency into its own file, inside `dir` relative to the
+    /// spec file, and returns a spec that includes those files instead of
+    /// listing the dependencies. Global settings and filters are preserved.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` when any of the files cannot be written, or when
+    /// their path is not valid UTF-8.
+    pub fn split(&self, dir: &str) -> Result<Self> {
+        let mut root = self.clone();
+        root.includes.clear();
+        root.included.clear();
+
+        for dep in &self.deps {
+            let mut include = PathBuf::from(dir).join(format!("{}.yml", dep.name()));
+            let mut n = 1;
+            while root.includes.contains(&path_to_string(&include)?) {
+                n += 1;
+                include = PathBuf::from(dir).join(format!("{}-{n}.yml", dep.name()));
+            }
+
+            let path = self.spec_dir().join(&include);
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            yaml::save(dep, path)?;
+            root.includes.push(path_to_string(&include)?);
+            root.included
+                .push(Included::new(dep, path_to_string(&include)?));
+        }
+        Ok(root)
+    }
+
+    /// Returns the url resolved against the base url, when the url is a
+    /// relative identifier and there is a base url. Otherwise it is returned
+    /// as is.
+    #[must_use]
+    pub fn resolve_url(&self, url: &str) -> String {
+        let is_relative =
+            !url.contains(':') && !url.starts_with('.') && !Path::new(url).is_absolute();
+        match &self.base_url {
+            Some(base_url) if is_relative => {
+                format!("{}/{url}", base_url.trim_end_matches('/'))
+            }
+            _ => url.to_string(),
+        }
+    }
+
+    /// Returns the dependencies with their urls resolved, see
+    /// [`Spec::resolve_url`].
+    #[must_use]
+    pub fn resolved_deps(&self) -> Vec<Dependency> {
+        self.deps
+            .iter()
+            .map(|dep| {
+                let mut dep = dep.clone();
+                dep.url = self.resolve_url(&dep.url);
+                for mirror in &mut dep.mirrors {
+                    *mirror = self.resolve_url(mirror);
+                }
+                dep
+            })
+            .collect()
+    }
+
+    /// Returns how the repositories of the dependencies are cloned.
+    #[must_use]
+    pub fn clone_options(&self) -> CloneOptions {
+        CloneOptions {
+            shallow_since: self.shallow_since.clone(),
+            partial: self.partial_clone,
+            proxy: self.proxy.clone(),
+            token_env: self.token_env.clone(),
+            bare: self.bare_cache,
+        }
+    }
+
+    /// Verifies that every preset referenced by the dependencies is defined,
+    /// either as the preset of the spec or as a built-in preset, and that the
+    /// dependencies are not listed more than once.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` describing the first setting that is not valid.
+    pub fn validate(&self) -> Result<()> {
+        if let Some(since) = self
+            .shallow_since
+            .as_deref()
+            .filter(|since| !is_date(since))
+        {
+            return Err(format_err!(
+                "shallow_since must be a date like 2024-01-01, found '{since}'"
+            ));
+        }
+        if let Some(manifest) = self
+            .manifest
+            .as_deref()
+            .filter(|path| path.is_empty() || !is_relative_dir(path))
+        {
+            return Err(format_err!(
+                "manifest '{manifest}' must be a path relative to the vendor folder"
+            ));
+        }
+        let submodules =
+            self.checkout.submodules || self.deps.iter().any(|dep| dep.submodules == Some(true));
+        if self.bare_cache
+            && (self.shallow_since.is_some()
+                || self.partial_clone
+                || self.checkout.lfs
+                || submodules)
+        {
+            return Err(format_err!(
+                "bare_cache cannot be combined with shallow_since, partial_clone, lfs nor \
+                 submodules"
+            ));
+        }
+        if self.write.hardlink && self.write.reproducible {
+            return Err(format_err!("hardlink cannot be combined with reproducible"));
+        }
+        if let Some(name) = self.token_env.as_deref().filter(|name| !is_env_name(name)) {
+            return Err(format_err!(
+                "token_env must be the name of an environment variable, found '{name}'"
+            ));
+        }
+        for message in self.unknown_fields() {
+            log::warn!("{message}");
+        }
+        let registry = self.registry();
+        for (i, dep) in self.deps.iter().enumerate() {
+            if dep.url.is_empty() {
+                return Err(format_err!(
+                    "dependency #{} has no url, every dependency must have a url",
+                    i + 1
+                ));
+            }
+            if is_plain_refname(&dep.refname) && dep.refname.chars().any(char::is_whitespace) {
+                return Err(format_err!(
+                    "dependency {} has refname '{}', which contains whitespace",
+                    dep.url,
+                    dep.refname
+                ));
+            }
+            if self.deps[..i].iter().any(|other| other.is_same(dep)) {
+                return Err(format_err!(
+                    "dependency {} is listed more than once, give each one a distinct name",
+                    dep.url
+                ));
+            }
+            if let Some(dir) = dep
+                .vendor_dir
+                .as_deref()
+                .filter(|dir| !is_relative_dir(dir))
+            {
+                return Err(format_err!(
+                    "dependency {} has vendor_dir '{dir}', it must be a path relative to the \
+                     vendor folder",
+                    dep.url
+                ));
+            }
+            let Some(name) = &dep.preset else {
+                continue;
+            };
+            if registry.get(name).is_none() {
+                return Err(format_err!(
+                    "dependency {} references undefined preset '{name}', available presets: {}",
+                    dep.url,
+                    registry.names().join(", ")
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns a message for each top-level field of the spec file that is
+    /// not known, hinting the known fields that are close to it, as those are
+    /// usually typos that would otherwise be silently ignored.
+    #[must_use]
+    pub fn unknown_fields(&self) -> Vec<String> {
+        self.extra
+            .keys()
+            .map(|key| {
+                format!(
+                    "unknown field '{key}' in the spec{}",
+                    suggestions(key, FIELDS)
+                )
+            })
+            .collect()
+    }
+
+    fn registry(&self) -> Registry {
+        let mut registry = Registry::builtin();
+        registry.register(self.preset.clone());
+        registry
+    }
+
+    /// Returns the presets to apply to a dependency: the preset of the spec,
+    /// followed by the preset that the dependency references, if any.
+    fn dependency_presets(&self, dep: &Dependency) -> Vec<Preset> {
+        let mut presets = vec![self.preset.clone()];
+        match &dep.preset {
+            Some(name) if name != self.preset.name() => {
+                presets.extend(self.registry().get(name).cloned());
+            }
+            _ => {}
+        }
+        presets
+    }
+
+    /// Interpolates the environment variables referenced by the refname of
+    /// the dependencies, see [`interpolate`], once refnames that are aliases
+    /// are resolved from the versions.
+    ///
+    /// Urls are only checked to be interpolable, they are kept as written so
+    /// the secrets they may reference are not written into the lock, and are
+    /// interpolated when the repository is cloned.
+    fn interpolate(&mut self) -> Result<()> {
+        for dep in &mut self.deps {
+            interpolate(&dep.url)?;
+            let alias = dep.refname.strip_prefix(ALIAS_PREFIX);
+            if alias.is_none() && !has_references(&dep.refname) {
+                continue;
+            }
+            let raw = dep.refname.clone();
+            if let Some(alias) = alias {
+                dep.refname = self.versions.get(alias).cloned().ok_or_else(|| {
+                    format_err!(
+                        "dependency {} has refname '{}', but '{alias}' is not in the versions{}",
+                        dep.url,
+                        dep.refname,
+                        suggestions(alias, &self.versions.keys().collect::<Vec<_>>())
+                    )
+                })?;
+            }
+            dep.refname = interpolate(&dep.refname)?;
+            self.interpolated.push(Interpolated {
+                url: dep.url.clone(),
+                raw,
+                expanded: dep.refname.clone(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Replaces the interpolated refnames of the dependencies with the ones
+    /// they were interpolated from, or the other way around.
+    fn swap_interpolated(&mut self, to_raw: bool) {
+        for dep in &mut self.deps {
+            let found = self.interpolated.iter().find_map(|it| {
+                let (from, to) = if to_raw {
+                    (&it.expanded, &it.raw)
+                } else {
+                    (&it.raw, &it.expanded)
+                };
+                (dep.url == it.url && dep.refname == *from).then_some(to)
+            });
+            if let Some(refname) = found {
+                dep.refname.clone_from(refname);
+            }
+        }
+    }
+
+    fn load_includes(&mut self) -> Result<()> {
+        for include in &self.includes {
+            let dep: Dependency = yaml::load(self.spec_dir().join(include))?;
+            self.included.push(Included::new(&dep, include.clone()));
+            self.deps.push(dep);
+        }
+        Ok(())
+    }
+
+    fn save_includes(&self, deps: &[Dependency]) -> Result<()> {
+        for dep in deps {
+            let Some(include) = self.find_include(dep) else {
+                continue;
+            };
+            let path = self.spec_dir().join(&include.path);
+            if yaml::load::<Dependency, _>(&path).is_ok_and(|saved| saved == *dep) {
+                continue;
+            }
+            yaml::save(dep, &path)
+                .map_err(|err| format_err!("cannot save include {}: {err}", include.path))?;
+        }
+        Ok(())
+    }
+
+    fn find_include(&self, dep: &Dependency) -> Option<&Included> {
+        self.included
+            .iter()
+            .find(|include| url::is_same(&include.url, &dep.url) && include.name == dep.name)
+    }
+
+    fn spec_dir(&self) -> &Path {
+        Path::new(self.preset.spec())
+            .parent()
+            .unwrap_or_else(|| Path::new(""))
     }
 
     fn get_mut_dependency(&mut self, dep: &Dependency) -> Option<&mut Dependency> {
         self.deps
             .iter_mut()
-            .find(|d| d.url.eq_ignore_ascii_case(&dep.url))
+            .find(|d| url::is_same(&d.url, &dep.url) && d.name == dep.name)
     }
 
     fn apply_preset(&mut self) {
-        let crate_version = VERSION.to_string();
-        if self.version < crate_version {
-            self.version = crate_version;
+        if version::compare(&self.version, VERSION).is_lt() {
+            self.version = VERSION.to_string();
         }
-        self.vendor = self.preset.vendor().to_string();
+        self.vendor = self.preset.vendor().clone();
         if self.preset.force_filters() {
             self.filters.clear();
         }
         self.filters.merge(&self.preset.global_filters());
-        self.deps.iter_mut().for_each(|dep| {
-            dep.apply_preset(&self.preset);
-        });
-        self.preset_name = self.preset.name().to_string();
+        let presets: Vec<_> = self
+            .deps
+            .iter()
+            .map(|dep| self.dependency_presets(dep))
+            .collect();
+        self.deps
+            .iter_mut()
+            .zip(presets)
+            .for_each(|(dep, presets)| {
+                dep.apply_presets(&presets);
+            });
+        self.preset_name = self.preset.name().clone();
+        if !self.preset.preserve_unknown_fields() {
+            self.extra.clear();
+        }
     }
 
+    /// Sorts the dependencies by url, and the filters of the spec and of each
+    /// dependency, so the spec is saved the same regardless of the order it
+    /// was built in.
     fn lint(&mut self) {
-        self.deps.sort_by(|a, b| a.url.cmp(&b.url));
         self.deps
-            .dedup_by(|a, b| a.url.eq_ignore_ascii_case(&b.url));
+            .sort_by(|a, b| a.url.cmp(&b.url).then_with(|| a.name.cmp(&b.name)));
+        self.deps.dedup_by(|a, b| a.is_same(b));
+        self.filters.lint();
+        for dep in &mut self.deps {
+            dep.filters.lint();
+        }
     }
 
     #[cfg(test)]
-    pub fn new() -> Self {
+    pub(crate) fn new() -> Self {
         Self::with_preset(&Preset::default())
     }
 }
 
+/// Dependency that has been merged from an include file, see
+/// [`Spec::includes`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct Included {
+    url: String,
+    name: Option<String>,
+    path: String,
+}
+
+impl Included {
+    fn new(dep: &Dependency, path: String) -> Self {
+        Self {
+            url: dep.url.clone(),
+            name: dep.name.clone(),
+            path,
+        }
+    }
+}
+
+/// Refname of a dependency, both as written in the spec file and with its
+/// environment variables interpolated.
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct Interpolated {
+    url: String,
+    raw: String,
+    expanded: String,
+}
+
+/// Returns whether the path stays within the folder it is relative to.
+fn is_relative_dir(dir: &str) -> bool {
+    Path::new(dir)
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+}
+
+/// Returns whether the refname names a git reference, rather than a version
+/// file or a semver range, which may contain whitespace.
+fn is_plain_refname(refname: &str) -> bool {
+    version_file(refname).is_none() && semver_range(refname).is_none()
+}
+
+/// Returns whether the value is a plain environment variable name, made of
+/// ascii letters, digits and underscores, not starting with a digit.
+fn is_env_name(value: &str) -> bool {
+    !value.starts_with(|c: char| c.is_ascii_digit())
+        && !value.is_empty()
+        && value
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'_')
+}
+
+/// Returns whether the value is a valid `YYYY-MM-DD` date.
+fn is_date(value: &str) -> bool {
+    let parts: Vec<_> = value.split('-').collect();
+    let is_numeric = parts.len() == 3
+        && parts
+            .iter()
+            .zip([4, 2, 2])
+            .all(|(part, len)| part.len() == len && part.bytes().all(|b| b.is_ascii_digit()));
+    if !is_numeric {
+        return false;
+    }
+    let [year, month, day] = [0, 1, 2].map(|i| parts[i].parse::<u32>().unwrap_or(0));
+    let is_leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days = match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if is_leap => 29,
+        2 => 28,
+        _ => return false,
+    };
+    (1..=days).contains(&day)
+}
+
+fn path_to_string(path: &Path) -> Result<String> {
+    path.to_str().map(ToString::to_string).ok_or_else(|| {
+        format_err!(
+            "invalid path {}, contains non-utf8 characters",
+            path.display()
+        )
+    })
+}
+
 #[cfg(test)]
 mod tests {
 
     use super::*;
+    use crate::svec;
     use crate::test_utils::build_preset;
+    use crate::test_utils::read_to_string;
+    use crate::test_utils::write_to;
     use crate::test_utils::TestContext;
 
+    const SPEC_WITH_UNKNOWN_FIELD: &str = "# owned by team-a
+version: 0.1.0
+vendor: vendor
+deps: []
+owner: team-a
+";
+
     #[test]
     fn test_spec_new() {
         let sut = Spec::new();
@@ -176,7 +1081,7 @@ mod tests {
     #[test]
     fn test_spec_with_preset_then_save_then_load() -> Result<()> {
         let ctx = TestContext::new();
-        let dep = Dependency::new("some url", "some ref");
+        let dep = Dependency::new("some url", "some-ref");
         let mut expected = Spec::with_preset(&ctx.preset);
         expected.add_dependency(dep);
 
@@ -187,10 +1092,514 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_spec_lint_is_independent_of_order() {
+        let preset = build_preset();
+        let build = |urls: [&str; 2], targets: Vec<String>| {
+            let mut spec = Spec::with_preset(&preset);
+            spec.filters.targets = targets.clone();
+            for url in urls {
+                let mut dep = Dependency::new(url, "master");
+                dep.filters.targets = targets.clone();
+                spec.deps.push(dep);
+            }
+            spec.lint();
+            spec
+        };
+
+        let sut = build(["b-url", "a-url"], svec!["b", "a", "b"]);
+        let other = build(["a-url", "b-url"], svec!["a", "b"]);
+
+        assert_eq!(other, sut);
+        assert_eq!(svec!["a", "b"], sut.filters.targets);
+        assert_eq!("a-url", sut.deps[0].url);
+        assert_eq!(svec!["a", "b"], sut.deps[1].filters.targets);
+    }
+
+    #[test]
+    fn test_spec_split_then_load() -> Result<()> {
+        let ctx = TestContext::new();
+        let mut spec = Spec::with_preset(&ctx.preset);
+        spec.locking.lock_trees = true;
+        spec.add_dependency(Dependency::new("https://github.com/a/ledger", "master"));
+        spec.add_dependency(Dependency::new("https://github.com/b/ledger.git", "v1"));
+        spec.add_dependency(Dependency::new("git@github.com:a/protos.git", "main"));
+        spec.lint();
+
+        let mut root = spec.split("deps")?;
+        root.save()?;
+
+        let saved = read_to_string(ctx.preset.spec());
+        assert!(!saved.contains("url:"), "{saved}");
+        assert_eq!(
+            svec!["deps/protos.yml", "deps/ledger.yml", "deps/ledger-2.yml"],
+            root.includes
+        );
+
+        let mut actual = Spec::load_from(&ctx.preset)?;
+        actual.lint();
+        assert_eq!(spec.deps, actual.deps);
+        assert_eq!(spec.filters, actual.filters);
+        assert_eq!(spec.vendor, actual.vendor);
+        assert!(actual.locking.lock_trees);
+        Ok(())
+    }
+
+    #[test]
+    fn test_spec_save_keeps_included_dependencies_apart() -> Result<()> {
+        let ctx = TestContext::new();
+        let mut spec = Spec::with_preset(&ctx.preset);
+        spec.add_dependency(Dependency::new("https://host/included", "master"));
+        let mut root = spec.split("deps")?;
+        root.save()?;
+
+        let mut loaded = Spec::load_from(&ctx.preset)?;
+        loaded.add_dependency(Dependency::new("own-url", "master"));
+        loaded.save()?;
+
+        let saved = read_to_string(ctx.preset.spec());
+        assert!(saved.contains("url: own-url"), "{saved}");
+        assert!(!saved.contains("url: https://host/included"), "{saved}");
+        assert_eq!(2, Spec::load_from(&ctx.preset)?.deps.len());
+
+        let mut loaded = Spec::load_from(&ctx.preset)?;
+        loaded.add_dependency(Dependency::new("https://host/included", "v2"));
+        loaded.save()?;
+
+        let saved = read_to_string(ctx.preset.spec());
+        assert!(!saved.contains("url: https://host/included"), "{saved}");
+        let included =
+            read_to_string(&Path::new(ctx.preset.spec()).with_file_name("deps/included.yml"));
+        assert!(included.contains("refname: v2"), "{included}");
+        let reloaded = Spec::load_from(&ctx.preset)?;
+        assert_eq!(2, reloaded.deps.len());
+        assert!(reloaded.deps.iter().any(|dep| dep.refname == "v2"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_spec_resolve_url() {
+        let mut sut = Spec::new();
+        assert_eq!("protos-common", sut.resolve_url("protos-common"));
+
+        sut.base_url = Some("https://github.com/some-org/".into());
+        assert_eq!(
+            "https://github.com/some-org/protos-common",
+            sut.resolve_url("protos-common")
+        );
+        for url in [
+            "https://github.com/other-org/protos",
+            "git@github.com:other-org/protos.git",
+            "/some/local/protos",
+            "./protos",
+        ] {
+            assert_eq!(url, sut.resolve_url(url));
+        }
+    }
+
+    #[test]
+    fn test_spec_validate_referenced_presets() -> Result<()> {
+        let ctx = TestContext::new();
+        let mut sut = Spec::with_preset(&ctx.preset);
+        let mut dep = Dependency::new("some-url", "some-refname");
+        dep.preset = Some("test-preset".into());
+        sut.add_dependency(dep.clone());
+        dep.url = "other-url".into();
+        dep.preset = Some("default".into());
+        sut.add_dependency(dep);
+        sut.validate()?;
+        sut.save()?;
+
+        Spec::load_from(&ctx.preset)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_spec_load_resolves_dependency_preset() -> Result<()> {
+        let ctx = TestContext::new();
+        write_to(
+            ctx.preset.spec(),
+            "version: 0.1.0
+vendor: vendor
+deps:
+- url: some-url
+  refname: master
+  preset: protobuf
+- url: other-url
+  refname: master
+",
+        );
+
+        let actual = Spec::load_from(&ctx.preset)?;
+
+        assert_eq!(Some("protobuf".into()), actual.deps[0].preset);
+        assert_eq!(svec!["md", "proto"], actual.deps[0].filters.extensions);
+        assert_eq!(svec!["md"], actual.deps[1].filters.extensions);
+        Ok(())
+    }
+
+    #[test]
+    fn test_spec_validate_undefined_preset() -> Result<()> {
+        let ctx = TestContext::new();
+        let mut sut = Spec::with_preset(&ctx.preset);
+        let mut dep = Dependency::new("some-url", "some-refname");
+        dep.preset = Some("missing".into());
+        sut.add_dependency(dep);
+
+        let expected = "dependency some-url references undefined preset 'missing', available \
+                        presets: default, protobuf, test-preset";
+        assert_eq!(expected, sut.validate().unwrap_err().to_string());
+
+        sut.save()?;
+        let actual = Spec::load_from(&ctx.preset).unwrap_err();
+        assert_eq!(expected, actual.to_string());
+        Ok(())
+    }
+
+    #[test]
+    fn test_spec_validate_duplicated_dependencies() -> Result<()> {
+        let ctx = TestContext::new();
+        write_to(
+            ctx.preset.spec(),
+            "version: 0.1.0
+vendor: vendor
+deps:
+- url: some-url
+  refname: v1
+  name: v1
+  vendor_dir: v1
+- url: some-url
+  refname: v2
+  name: v2
+  vendor_dir: v2
+",
+        );
+        let spec = Spec::load_from(&ctx.preset)?;
+        assert_eq!(2, spec.deps.len());
+
+        let mut sut = spec.clone();
+        sut.deps[1].name = Some("v1".into());
+        assert_eq!(
+            "dependency some-url is listed more than once, give each one a distinct name",
+            sut.validate().unwrap_err().to_string()
+        );
+
+        let mut sut = spec;
+        sut.deps[1].vendor_dir = Some("../v2".into());
+        assert_eq!(
+            "dependency some-url has vendor_dir '../v2', it must be a path relative to the vendor \
+             folder",
+            sut.validate().unwrap_err().to_string()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_spec_load_interpolates_environment_variables() -> Result<()> {
+        let ctx = TestContext::new();
+        write_to(
+            ctx.preset.spec(),
+            "version: 0.1.0
+vendor: vendor
+deps:
+- url: https://${UNSET_HOST:-github.com}/org/repo
+  refname: ${UNSET_REFNAME:-v1.0.0}
+",
+        );
+
+        let mut sut = Spec::load_from(&ctx.preset)?;
+
+        let url = "https://${UNSET_HOST:-github.com}/org/repo";
+        assert_eq!(url, sut.deps[0].url);
+        assert_eq!("v1.0.0", sut.deps[0].refname);
+        sut.save()?;
+        let saved = read_to_string(ctx.preset.spec());
+        assert!(
+            saved.contains("refname: ${UNSET_REFNAME:-v1.0.0}"),
+            "{saved}"
+        );
+        assert!(saved.contains(&format!("url: {url}")), "{saved}");
+        assert_eq!("v1.0.0", sut.deps[0].refname);
+
+        write_to(
+            ctx.preset.spec(),
+            "version: 0.1.0
+vendor: vendor
+deps:
+- url: https://${UNSET_HOST}/org/repo
+  refname: v1.0.0
+",
+        );
+        assert_eq!(
+            "environment variable UNSET_HOST is not set, in 'https://${UNSET_HOST}/org/repo'",
+            Spec::load_from(&ctx.preset).unwrap_err().to_string()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_spec_load_resolves_version_aliases() -> Result<()> {
+        let ctx = TestContext::new();
+        write_to(
+            ctx.preset.spec(),
+            r"version: 0.1.0
+vendor: vendor
+versions:
+  release-train: v2.1.0
+deps:
+- url: https://github.com/org/a
+  refname: '@release-train'
+- url: https://github.com/org/b
+  refname: '@release-train'
+- url: https://github.com/org/c
+  refname: v1.0.0
+",
+        );
+
+        let mut sut = Spec::load_from(&ctx.preset)?;
+
+        let refnames: Vec<_> = sut.deps.iter().map(|dep| dep.refname.as_str()).collect();
+        assert_eq!(vec!["v2.1.0", "v2.1.0", "v1.0.0"], refnames);
+        sut.save()?;
+        let saved = read_to_string(ctx.preset.spec());
+        assert_eq!(
+            2,
+            saved.matches("refname: '@release-train'").count(),
+            "{saved}"
+        );
+        assert_eq!("v2.1.0", sut.deps[0].refname);
+        Ok(())
+    }
+
+    #[test]
+    fn test_spec_load_fails_on_undefined_version_aliases() {
+        let ctx = TestContext::new();
+        write_to(
+            ctx.preset.spec(),
+            r"version: 0.1.0
+vendor: vendor
+versions:
+  release-train: v2.1.0
+deps:
+- url: https://github.com/org/a
+  refname: '@release-trian'
+",
+        );
+
+        assert_eq!(
+            "dependency https://github.com/org/a has refname '@release-trian', but \
+             'release-trian' is not in the versions, did you mean 'release-train'?",
+            Spec::load_from(&ctx.preset).unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn test_spec_load_keeps_urls_as_written() -> Result<()> {
+        let ctx = TestContext::new();
+        let mut sut = Spec::with_preset(&ctx.preset);
+        sut.deps
+            .push(Dependency::new("git@github.com:a/b.git", "master"));
+        sut.save()?;
+
+        let mut actual = Spec::load_from(&ctx.preset)?;
+
+        assert_eq!("git@github.com:a/b.git", actual.deps[0].url);
+        let err = actual.add_new_dependency(Dependency::new("ssh://git@GitHub.com/a/b.git", "v1"));
+        assert_eq!(
+            "dependency ssh://git@GitHub.com/a/b.git is already in the spec",
+            err.unwrap_err().to_string()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_spec_validate_dependency_url_and_refname() {
+        let mut sut = Spec::new();
+        sut.deps.push(Dependency::new("some-url", "v1"));
+        sut.deps.push(Dependency::new("", "v1"));
+        assert_eq!(
+            "dependency #2 has no url, every dependency must have a url",
+            sut.validate().unwrap_err().to_string()
+        );
+
+        sut.deps[1] = Dependency::new("other-url", "v1 ");
+        assert_eq!(
+            "dependency other-url has refname 'v1 ', which contains whitespace",
+            sut.validate().unwrap_err().to_string()
+        );
+
+        sut.deps[1].refname = "semver:>=1, <2".into();
+        assert!(sut.validate().is_ok());
+        sut.deps[1].refname = String::new();
+        assert!(sut.validate().is_ok());
+    }
+
+    #[test]
+    fn test_spec_unknown_fields() -> Result<()> {
+        let ctx = TestContext::new();
+        write_to(
+            ctx.preset.spec(),
+            "version: 0.1.0
+vendor: vendor
+lock_tree: true
+whatever: 1
+deps: []
+",
+        );
+
+        let sut: Spec = yaml::load(ctx.preset.spec())?;
+
+        assert_eq!(
+            vec![
+                "unknown field 'lock_tree' in the spec, did you mean 'lock_trees'?",
+                "unknown field 'whatever' in the spec",
+            ],
+            sut.unknown_fields()
+        );
+        assert!(Spec::load_from(&ctx.preset).is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn test_spec_fields_are_the_serialized_keys() -> Result<()> {
+        let base = "version: 0.1.0\nvendor: vendor\ndeps: []\n";
+        for field in FIELDS {
+            if base.contains(&format!("{field}:")) {
+                continue;
+            }
+            let parsed = serde_yaml::from_str::<Spec>(&format!("{base}{field}: ~\n"));
+            if let Ok(spec) = parsed {
+                assert!(spec.unknown_fields().is_empty(), "{field} is not a key");
+            }
+        }
+
+        let mut filters = Filters::new();
+        filters.targets = svec!["a"];
+        filters.ignores = svec!["b"];
+        filters.extensions = svec!["c"];
+        filters.rename_template = Some("{path}".into());
+        filters.exclude_binary = true;
+        filters.include_hidden = true;
+        let mut spec = Spec::new();
+        spec.filters = filters;
+        let serialized = serde_yaml::to_value(&spec)?;
+        for key in serialized.as_mapping().unwrap().keys() {
+            let key = key.as_str().unwrap();
+            assert!(FIELDS.contains(&key), "{key} is missing from the fields");
+        }
+
+        let sut: Spec = serde_yaml::from_str(&format!("{base}target: [a]\nfilters: [a]\n"))?;
+        assert_eq!(
+            vec![
+                "unknown field 'filters' in the spec",
+                "unknown field 'target' in the spec, did you mean 'targets'?",
+            ],
+            sut.unknown_fields()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_spec_validate_shallow_since() {
+        let mut sut = Spec::new();
+        sut.shallow_since = Some("2024-01-01".into());
+        assert!(sut.validate().is_ok());
+
+        sut.shallow_since = Some("2 weeks ago".into());
+        assert_eq!(
+            "shallow_since must be a date like 2024-01-01, found '2 weeks ago'",
+            sut.validate().unwrap_err().to_string()
+        );
+
+        sut.shallow_since = Some("2024-02-29".into());
+        assert!(sut.validate().is_ok());
+        for impossible in ["2024-99-99", "2023-02-29", "2024-04-31", "2024-00-10"] {
+            sut.shallow_since = Some(impossible.into());
+            assert!(sut.validate().is_err(), "{impossible}");
+        }
+    }
+
+    #[test]
+    fn test_spec_validate_manifest() {
+        let mut sut = Spec::new();
+        sut.manifest = Some("meta/MANIFEST.yml".into());
+        assert!(sut.validate().is_ok());
+
+        sut.manifest = Some("../MANIFEST.yml".into());
+        assert_eq!(
+            "manifest '../MANIFEST.yml' must be a path relative to the vendor folder",
+            sut.validate().unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn test_spec_validate_bare_cache() {
+        let mut sut = Spec::new();
+        sut.bare_cache = true;
+        assert!(sut.validate().is_ok());
+
+        sut.partial_clone = true;
+        assert_eq!(
+            "bare_cache cannot be combined with shallow_since, partial_clone, lfs nor submodules",
+            sut.validate().unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn test_spec_validate_hardlink() {
+        let mut sut = Spec::new();
+        sut.write.hardlink = true;
+        assert!(sut.validate().is_ok());
+
+        sut.write.reproducible = true;
+        assert_eq!(
+            "hardlink cannot be combined with reproducible",
+            sut.validate().unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn test_spec_validate_token_env() {
+        let mut sut = Spec::new();
+        sut.token_env = Some("GITHUB_TOKEN".into());
+        assert!(sut.validate().is_ok());
+
+        sut.token_env = Some("$(id)".into());
+        assert_eq!(
+            "token_env must be the name of an environment variable, found '$(id)'",
+            sut.validate().unwrap_err().to_string()
+        );
+    }
+
     #[test]
     fn test_spec_cannot_load_from_non_existent_file() {
         let ctx = TestContext::new();
         let actual = Spec::load_from(&ctx.preset);
         assert!(actual.is_err(), "there should be an error");
     }
+
+    #[test]
+    fn test_spec_preserves_unknown_fields_by_default() -> Result<()> {
+        let ctx = TestContext::new();
+        write_to(ctx.preset.spec(), SPEC_WITH_UNKNOWN_FIELD);
+
+        Spec::load_from(&ctx.preset)?.save()?;
+
+        let actual = read_to_string(ctx.preset.spec());
+        assert!(actual.starts_with("# owned by team-a\n"), "{actual}");
+        assert!(actual.contains("owner: team-a"), "{actual}");
+        Ok(())
+    }
+
+    #[test]
+    fn test_spec_drops_unknown_fields_when_disabled() -> Result<()> {
+        let ctx = TestContext::with_preset(|builder| builder.preserve_unknown_fields(false));
+        write_to(ctx.preset.spec(), SPEC_WITH_UNKNOWN_FIELD);
+
+        Spec::load_from(&ctx.preset)?.save()?;
+
+        let actual = read_to_string(ctx.preset.spec());
+        assert!(!actual.contains("owner"), "{actual}");
+        Ok(())
+    }
 }
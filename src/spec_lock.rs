@@ -1,9 +1,18 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::mem;
+use std::path::Path;
+
+use anyhow::format_err;
 use anyhow::Result;
 use serde::Deserialize;
 use serde::Serialize;
 
+use crate::checksum::Checksums;
+use crate::deps::Dependency;
 use crate::deps::LockedDependency;
 use crate::preset::Preset;
+use crate::version;
 use crate::yaml;
 use crate::VERSION;
 
@@ -15,68 +24,194 @@ pub struct SpecLock {
     /// List of locked dependencies
     pub deps: Vec<LockedDependency>,
 
+    /// Checksums of the vendored files, recorded when the spec enables
+    /// `lock_checksums`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub checksums: Checksums,
+
     #[serde(skip)]
     preset: Preset,
 }
 
 impl SpecLock {
+    #[must_use]
     pub fn with_preset(preset: &Preset) -> Self {
         let mut lock = Self {
             version: VERSION.to_owned(),
             deps: Vec::new(),
+            checksums: Checksums::new(),
             preset: preset.clone(),
         };
         lock.apply_preset(preset);
         lock
     }
 
+    /// Loads the lock file of the preset.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` when the lock file cannot be read or parsed, or when
+    /// it was generated by a newer version and the preset is strict about
+    /// it.
     pub fn load_from(preset: &Preset) -> Result<Self> {
         let mut lock: Self = yaml::load(preset.spec_lock())?;
+        lock.check_version(preset)?;
         lock.apply_preset(preset);
         Ok(lock)
     }
 
+    /// Saves the lock, unless the lock on disk already has the same contents.
+    /// An older version in the lock on disk does not make it rewritten.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` when the lock file cannot be written.
     pub fn save(&mut self) -> Result<()> {
         self.lint();
+        if self.is_saved() {
+            log::debug!("{} did not change", self.preset.spec_lock());
+            return Ok(());
+        }
         yaml::save(self, self.preset.spec_lock())
     }
 
     pub fn apply_preset(&mut self, preset: &Preset) {
-        if self.version < VERSION.into() {
+        if version::compare(&self.version, VERSION).is_lt() {
             self.version = VERSION.into();
         }
         self.preset = preset.clone();
     }
 
+    /// Checks that the lock has not been generated by a newer version of the
+    /// tool, as its format might have changed.
+    fn check_version(&self, preset: &Preset) -> Result<()> {
+        if version::compare(&self.version, VERSION) != Ordering::Greater {
+            return Ok(());
+        }
+
+        let message = format!(
+            "{} was generated by version {}, which is newer than the running version {VERSION}",
+            preset.spec_lock(),
+            self.version
+        );
+        if preset.strict_version() {
+            return Err(format_err!(message));
+        }
+        log::warn!("{message}");
+        Ok(())
+    }
+
     pub fn add_locked_dependency(&mut self, dep: LockedDependency) {
-        match self.get_mut_locked_dependency(&dep.url) {
-            Some(found) => {
-                found.refname = dep.refname;
-            }
-            None => {
-                self.deps.push(dep);
+        match self.deps.iter_mut().find(|l| l.is_same(&dep)) {
+            Some(found) => *found = dep,
+            None => self.deps.push(dep),
+        }
+    }
+
+    /// Fails when the lock on disk locks other commits, or other checksums,
+    /// than this one. The metadata derived from the commits, such as their
+    /// commit time or the mirror they were vendored from, is not compared, so
+    /// locks written without it are still up to date.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` when the lock would be modified.
+    pub fn check_unchanged(&mut self) -> Result<()> {
+        self.lint();
+        let saved = yaml::load::<Self, _>(self.preset.spec_lock()).ok();
+        if let Some(mut saved) = saved {
+            saved.lint();
+            if self.is_frozen_as(&saved) {
+                return Ok(());
             }
         }
+        Err(format_err!(
+            "{} needs to be updated, but it is frozen",
+            self.preset.spec_lock()
+        ))
     }
 
-    pub fn get_locked_dependency(&self, url: &str) -> Option<&LockedDependency> {
-        self.deps.iter().find(|l| l.url.eq_ignore_ascii_case(url))
+    /// Fails when any of `deps` is not locked, or is locked from another
+    /// refname than the spec asks for, as installing it would modify the
+    /// lock. Checked before installing, so the vendor folder is untouched.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` listing the dependencies that would modify the lock.
+    pub fn check_frozen(&self, deps: &[Dependency]) -> Result<()> {
+        for dep in deps {
+            let reason = match self.get_locked_dependency(dep) {
+                None => "is not locked".to_string(),
+                Some(lock) if lock.is_outdated(dep) => format!(
+                    "asks for {}, but {} is locked",
+                    dep.refname,
+                    lock.requested.as_deref().unwrap_or_default()
+                ),
+                Some(_) => continue,
+            };
+            return Err(format_err!(
+                "{} {reason}, {} needs to be updated, but it is frozen",
+                dep.url,
+                self.preset.spec_lock()
+            ));
+        }
+        Ok(())
     }
 
-    fn get_mut_locked_dependency(&mut self, url: &str) -> Option<&mut LockedDependency> {
-        self.deps
-            .iter_mut()
-            .find(|l| l.url.eq_ignore_ascii_case(url))
+    /// Removes the locked dependencies that do not lock any of `deps`,
+    /// returns the removed ones. Urls are compared ignoring their case.
+    pub fn prune(&mut self, deps: &[Dependency]) -> Vec<LockedDependency> {
+        let (kept, pruned) = mem::take(&mut self.deps)
+            .into_iter()
+            .partition(|locked| deps.iter().any(|dep| locked.is_lock_of(dep)));
+        self.deps = kept;
+        pruned
+    }
+
+    #[must_use]
+    pub fn get_locked_dependency(&self, dep: &Dependency) -> Option<&LockedDependency> {
+        self.deps.iter().find(|l| l.is_lock_of(dep))
+    }
+
+    /// Returns whether the lock on disk has the same dependencies and
+    /// checksums, regardless of the version that generated it.
+    fn is_saved(&self) -> bool {
+        yaml::load::<Self, _>(self.preset.spec_lock())
+            .is_ok_and(|saved| saved.deps == self.deps && saved.checksums == self.checksums)
+    }
+
+    /// Returns whether this locks the same commits as `saved`, and has the
+    /// same checksums outside of `.git`, which a frozen install cannot change.
+    fn is_frozen_as(&self, saved: &Self) -> bool {
+        fn locked(lock: &SpecLock) -> Vec<(&String, &Option<String>, &String)> {
+            lock.deps
+                .iter()
+                .map(|dep| (&dep.url, &dep.name, &dep.refname))
+                .collect()
+        }
+        fn checksums(lock: &SpecLock) -> Vec<(&String, &String)> {
+            lock.checksums
+                .iter()
+                .filter(|(path, _)| !Path::new(path).starts_with(".git"))
+                .collect()
+        }
+        locked(self) == locked(saved) && checksums(self) == checksums(saved)
     }
 
+    /// Sorts the dependencies and their failed files, so the lock is saved
+    /// the same regardless of the order the dependencies were imported in.
     fn lint(&mut self) {
-        self.deps.sort_by(|a, b| a.url.cmp(&b.url));
         self.deps
-            .dedup_by(|a, b| a.url.eq_ignore_ascii_case(&b.url));
+            .sort_by(|a, b| a.url.cmp(&b.url).then_with(|| a.name.cmp(&b.name)));
+        self.deps.dedup_by(|a, b| a.is_same(b));
+        for dep in &mut self.deps {
+            dep.failed_files.sort();
+            dep.failed_files.dedup();
+        }
     }
 
     #[cfg(test)]
-    pub fn new() -> Self {
+    pub(crate) fn new() -> Self {
         Self::with_preset(&Preset::default())
     }
 }
@@ -86,6 +221,8 @@ mod tests {
 
     use super::*;
     use crate::test_utils::build_preset;
+    use crate::test_utils::read_to_string;
+    use crate::test_utils::write_to;
     use crate::test_utils::TestContext;
 
     #[test]
@@ -118,6 +255,56 @@ mod tests {
         assert_eq!(dep, sut.deps[0]);
     }
 
+    #[test]
+    fn test_spec_lock_check_frozen() {
+        let mut sut = SpecLock::new();
+        let mut locked = LockedDependency::new("some-url", "some-commit");
+        locked.requested = Some("master".into());
+        sut.add_locked_dependency(locked);
+
+        assert!(sut
+            .check_frozen(&[Dependency::new("some-url", "master")])
+            .is_ok());
+        let actual = sut
+            .check_frozen(&[Dependency::new("some-url", "v1.0.0")])
+            .unwrap_err();
+        assert!(
+            actual
+                .to_string()
+                .starts_with("some-url asks for v1.0.0, but master is locked"),
+            "{actual}"
+        );
+        let actual = sut
+            .check_frozen(&[Dependency::new("other-url", "master")])
+            .unwrap_err();
+        assert!(
+            actual.to_string().starts_with("other-url is not locked"),
+            "{actual}"
+        );
+    }
+
+    #[test]
+    fn test_spec_lock_check_unchanged_ignores_derived_fields() -> Result<()> {
+        let ctx = TestContext::new();
+        let mut saved = SpecLock::with_preset(&ctx.preset);
+        saved.add_locked_dependency(LockedDependency::new("some-url", "some-commit"));
+        saved.save()?;
+
+        let mut sut = SpecLock::load_from(&ctx.preset)?;
+        sut.deps[0].requested = Some("master".into());
+        sut.deps[0].committed_at = Some(1);
+        sut.deps[0].mirror = Some("some-mirror".into());
+        sut.checksums.insert(".git/HEAD".into(), "some-sum".into());
+        assert!(sut.check_unchanged().is_ok());
+
+        sut.deps[0].refname = "other-commit".into();
+        assert!(sut.check_unchanged().is_err());
+        sut.deps[0].refname = "some-commit".into();
+        sut.checksums.insert("file.txt".into(), "some-sum".into());
+        assert!(sut.check_unchanged().is_err());
+        Ok(())
+    }
+
     #[test]
     fn test_spec_lock_apply_preset_updates_version() -> Result<()> {
         let ctx = TestContext::new();
@@ -131,6 +318,63 @@ mod tests {
         Ok(())
     }
 
+    fn save_with_version(preset: &Preset, version: &str) -> Result<()> {
+        let mut lock = SpecLock::with_preset(preset);
+        lock.version = version.into();
+        yaml::save(&lock, preset.spec_lock())
+    }
+
+    #[test]
+    fn test_spec_lock_load_older_version() -> Result<()> {
+        let ctx = TestContext::with_preset(|builder| builder.strict_version(true));
+        save_with_version(&ctx.preset, "0.0.1")?;
+
+        let actual = SpecLock::load_from(&ctx.preset)?;
+
+        assert_eq!(VERSION, actual.version);
+        Ok(())
+    }
+
+    #[test]
+    fn test_spec_lock_load_equal_version() -> Result<()> {
+        let ctx = TestContext::with_preset(|builder| builder.strict_version(true));
+        save_with_version(&ctx.preset, VERSION)?;
+
+        let actual = SpecLock::load_from(&ctx.preset)?;
+
+        assert_eq!(VERSION, actual.version);
+        Ok(())
+    }
+
+    #[test]
+    fn test_spec_lock_load_newer_version() -> Result<()> {
+        let ctx = TestContext::new();
+        save_with_version(&ctx.preset, "99.0.0")?;
+
+        let actual = SpecLock::load_from(&ctx.preset)?;
+
+        assert_eq!("99.0.0", actual.version);
+        Ok(())
+    }
+
+    #[test]
+    fn test_spec_lock_load_newer_version_strict() -> Result<()> {
+        let ctx = TestContext::with_preset(|builder| builder.strict_version(true));
+        save_with_version(&ctx.preset, "99.0.0")?;
+
+        let actual = SpecLock::load_from(&ctx.preset);
+
+        assert_eq!(
+            format!(
+                "{} was generated by version 99.0.0, which is newer than the running version \
+                 {VERSION}",
+                ctx.preset.spec_lock()
+            ),
+            actual.expect_err("should fail").to_string()
+        );
+        Ok(())
+    }
+
     #[test]
     fn test_spec_lock_with_preset_then_save_then_load() -> Result<()> {
         let dep = LockedDependency::new("some url", "some ref");
@@ -145,6 +389,69 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_spec_lock_save_skips_unchanged_lock() -> Result<()> {
+        let ctx = TestContext::new();
+        let mut sut = SpecLock::with_preset(&ctx.preset);
+        sut.add_locked_dependency(LockedDependency::new("some url", "some ref"));
+        sut.save()?;
+        let saved = format!("# untouched\n{}", read_to_string(&ctx.preset.spec_lock()));
+        write_to(ctx.preset.spec_lock(), &saved);
+
+        sut.version = "99.0.0".into();
+        sut.save()?;
+        assert_eq!(saved, read_to_string(&ctx.preset.spec_lock()));
+
+        sut.add_locked_dependency(LockedDependency::new("some url", "other ref"));
+        sut.save()?;
+        assert_eq!(sut, SpecLock::load_from(&ctx.preset)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_spec_lock_prune() {
+        let mut sut = SpecLock::new();
+        sut.add_locked_dependency(LockedDependency::new("https://host/Kept", "some-ref"));
+        sut.add_locked_dependency(LockedDependency::new("https://host/orphan", "some-ref"));
+        let deps = [Dependency::new("https://host/kept", "master")];
+
+        let pruned = sut.prune(&deps);
+
+        assert_eq!(
+            vec!["https://host/orphan"],
+            pruned.iter().map(|dep| &dep.url).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec!["https://host/Kept"],
+            sut.deps.iter().map(|dep| &dep.url).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_spec_lock_lint_is_independent_of_order() {
+        let locked = |url: &str, failed_files: &[&str]| {
+            let mut dep = LockedDependency::new(url, "some-ref");
+            dep.failed_files = failed_files.iter().map(ToString::to_string).collect();
+            dep
+        };
+        let mut sut = SpecLock::new();
+        sut.add_locked_dependency(locked("b-url", &["b.txt", "a.txt", "b.txt"]));
+        sut.add_locked_dependency(locked("a-url", &[]));
+        let mut other = SpecLock::new();
+        other.add_locked_dependency(locked("a-url", &[]));
+        other.add_locked_dependency(locked("b-url", &["a.txt", "b.txt"]));
+
+        sut.lint();
+        other.lint();
+
+        assert_eq!(other, sut);
+        assert_eq!(
+            vec!["a-url", "b-url"],
+            sut.deps.iter().map(|dep| &dep.url).collect::<Vec<_>>()
+        );
+        assert_eq!(vec!["a.txt", "b.txt"], sut.deps[1].failed_files);
+    }
+
     #[test]
     fn test_spec_lock_cannot_load_from_non_existent_file() {
         let ctx = &TestContext::new();
@@ -0,0 +1,71 @@
+/// Returns the hint of the candidates closest to `name`, empty when none is
+/// close enough to be a typo.
+pub fn suggestions<S: AsRef<str>>(name: &str, candidates: &[S]) -> String {
+    let max_distance = (name.chars().count() / 3).clamp(1, 3);
+    let mut close: Vec<_> = candidates
+        .iter()
+        .map(std::convert::AsRef::as_ref)
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .collect();
+    close.sort_unstable();
+    let names: Vec<_> = close
+        .iter()
+        .take(3)
+        .map(|(_, name)| format!("'{name}'"))
+        .collect();
+    if names.is_empty() {
+        String::new()
+    } else {
+        format!(", did you mean {}?", names.join(" or "))
+    }
+}
+
+/// Returns the edit distance between two strings, in characters, counting
+/// the swap of two adjacent characters as a single edit, as typos do.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut rows = vec![(0..=b.len()).collect::<Vec<_>>()];
+    for i in 1..=a.len() {
+        let mut row = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            let previous = &rows[i - 1];
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            row[j] = (previous[j - 1] + cost)
+                .min(previous[j] + 1)
+                .min(row[j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                row[j] = row[j].min(rows[i - 2][j - 2] + 1);
+            }
+        }
+        rows.push(row);
+    }
+    rows[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(0, edit_distance("main", "main"));
+        assert_eq!(1, edit_distance("mian", "main"));
+        assert_eq!(2, edit_distance("mian", "mains"));
+        assert_eq!(1, edit_distance("v1.0", "v1.1"));
+        assert_eq!(6, edit_distance("", "master"));
+    }
+
+    #[test]
+    fn test_suggestions() {
+        let candidates = ["lock_trees", "lock_checksums", "lfs"];
+
+        assert_eq!(
+            ", did you mean 'lock_trees'?",
+            suggestions("lock_tree", &candidates)
+        );
+        assert_eq!("", suggestions("unrelated", &candidates));
+    }
+}
@@ -1,6 +1,12 @@
 use std::fs;
 use std::path::Path;
 
+use git2::Index;
+use git2::Oid;
+use git2::Repository;
+use git2::RepositoryInitOptions;
+use git2::Signature;
+use git2::Time;
 use tempfile::TempDir;
 
 use crate::deps::Dependency;
@@ -37,11 +43,99 @@ pub fn read_to_string<P: AsRef<Path>>(src: &P) -> String {
         .unwrap_or_else(|_| panic!("cannot read path {}", src.as_ref().display()))
 }
 
+/// Initialises a git repository at `path`, with `master` as initial branch,
+/// and commits the provided files into it.
+pub fn git_init<P: AsRef<Path>>(path: P, files: &[(&str, &str)]) -> Repository {
+    let repository = Repository::init_opts(
+        path.as_ref(),
+        RepositoryInitOptions::new().initial_head("master"),
+    )
+    .unwrap_or_else(|err| panic!("cannot init git repository: {err}"));
+    git_commit(&repository, files, "initial commit");
+    repository
+}
+
+/// Writes the provided files in the repository working directory and
+/// commits them on top of the current HEAD.
+pub fn git_commit(repository: &Repository, files: &[(&str, &str)], message: &str) -> Oid {
+    let signature = Signature::now("test", "test@example.com").expect("signature");
+    git_commit_as(repository, files, message, &signature)
+}
+
+/// Commits like [`git_commit`], dated `seconds` after the epoch.
+pub fn git_commit_at(
+    repository: &Repository,
+    files: &[(&str, &str)],
+    message: &str,
+    seconds: i64,
+) -> Oid {
+    let signature =
+        Signature::new("test", "test@example.com", &Time::new(seconds, 0)).expect("signature");
+    git_commit_as(repository, files, message, &signature)
+}
+
+fn git_commit_as(
+    repository: &Repository,
+    files: &[(&str, &str)],
+    message: &str,
+    signature: &Signature,
+) -> Oid {
+    let workdir = repository
+        .workdir()
+        .expect("repository has a working directory");
+    let mut index = repository.index().expect("repository index");
+    for (path, data) in files {
+        let dst = workdir.join(path);
+        fs::create_dir_all(dst.parent().unwrap()).expect("parent directory");
+        write_to(&dst, data);
+        index
+            .add_path(Path::new(path))
+            .expect("file added to index");
+    }
+    commit_index(repository, &mut index, message, signature)
+}
+
+/// Commits the removal of the files at `paths`.
+pub fn git_remove(repository: &Repository, paths: &[&str], message: &str) -> Oid {
+    let signature = Signature::now("test", "test@example.com").expect("signature");
+    let workdir = repository
+        .workdir()
+        .expect("repository has a working directory");
+    let mut index = repository.index().expect("repository index");
+    for path in paths {
+        fs::remove_file(workdir.join(path)).expect("file removed");
+        index
+            .remove_path(Path::new(path))
+            .expect("file removed from index");
+    }
+    commit_index(repository, &mut index, message, &signature)
+}
+
+fn commit_index(
+    repository: &Repository,
+    index: &mut Index,
+    message: &str,
+    signature: &Signature,
+) -> Oid {
+    index.write().expect("index written");
+
+    let tree_id = index.write_tree().expect("tree written");
+    let tree = repository.find_tree(tree_id).expect("tree");
+    let parent = repository
+        .head()
+        .ok()
+        .and_then(|head| head.peel_to_commit().ok());
+    let parents: Vec<_> = parent.iter().collect();
+    repository
+        .commit(Some("HEAD"), signature, signature, message, &tree, &parents)
+        .unwrap_or_else(|err| panic!("cannot commit: {err}"))
+}
+
 pub fn build_preset() -> Preset {
     preset_builder().build()
 }
 
-pub fn build_preset_with_fs(temp_dir: &TempDir) -> Preset {
+pub fn preset_builder_with_fs(temp_dir: &TempDir) -> Builder {
     let root = temp_dir.path();
     let tmp = |x: &str| root.join(x).into_os_string().into_string().unwrap();
 
@@ -51,7 +145,6 @@ pub fn build_preset_with_fs(temp_dir: &TempDir) -> Preset {
         .vendor(&tmp(".test-vendor"))
         .spec(&tmp(".test-vendor.yml"))
         .spec_lock(&tmp(".test-vendor-lock.yml"))
-        .build()
 }
 
 pub fn preset_builder() -> Builder {
@@ -87,9 +180,15 @@ pub struct TestContext {
 
 impl TestContext {
     pub fn new() -> Self {
+        Self::with_preset(|builder| builder)
+    }
+
+    /// Creates a context whose preset is customised by `configure`, on top
+    /// of the temporary filesystem layout.
+    pub fn with_preset(configure: impl FnOnce(Builder) -> Builder) -> Self {
         let temp_dir = tempdir();
         TestContext {
-            preset: build_preset_with_fs(&temp_dir),
+            preset: configure(preset_builder_with_fs(&temp_dir)).build(),
             _temp_dir: temp_dir,
         }
     }
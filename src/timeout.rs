@@ -0,0 +1,123 @@
+use std::error;
+use std::fmt;
+use std::sync::mpsc;
+use std::sync::mpsc::RecvTimeoutError;
+use std::thread;
+use std::time::Duration;
+
+use anyhow::format_err;
+use anyhow::Result;
+
+/// Default timeout of the operations that reach the network, in seconds.
+pub const DEFAULT_SECS: u64 = 120;
+
+/// Error of an operation that did not finish within its timeout.
+#[derive(Debug)]
+pub struct TimedOut {
+    what: String,
+    timeout: Duration,
+}
+
+impl fmt::Display for TimedOut {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} timed out after {:?}", self.what, self.timeout)
+    }
+}
+
+impl error::Error for TimedOut {}
+
+/// Returns whether the error, or any of its causes, is a [`TimedOut`].
+pub fn is_timed_out(err: &anyhow::Error) -> bool {
+    err.chain()
+        .any(<dyn std::error::Error + 'static>::is::<TimedOut>)
+}
+
+/// Runs the operation on its own thread, and fails with [`TimedOut`] when it
+/// does not finish within `timeout`.
+///
+/// Git operations cannot be interrupted, so the thread of an operation that
+/// timed out is left behind, it ends with the operation or the process. The
+/// thread holds `guard` until then: given the lock of a repository, nobody
+/// else touches the repository while the operation is still writing to it.
+pub fn run<T: Send + 'static, G: Send + 'static>(
+    what: &str,
+    timeout: Duration,
+    guard: G,
+    operation: impl FnOnce() -> Result<T> + Send + 'static,
+) -> Result<T> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let result = operation();
+        drop(guard);
+        let _ = sender.send(result);
+    });
+    match receiver.recv_timeout(timeout) {
+        Ok(result) => result,
+        Err(RecvTimeoutError::Timeout) => Err(TimedOut {
+            what: what.to_string(),
+            timeout,
+        }
+        .into()),
+        Err(RecvTimeoutError::Disconnected) => Err(format_err!("{what} panicked")),
+    }
+}
+
+#[allow(clippy::trivially_copy_pass_by_ref)]
+pub fn is_default_secs(secs: &u64) -> bool {
+    *secs == DEFAULT_SECS
+}
+
+pub fn default_secs() -> u64 {
+    DEFAULT_SECS
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::sync::Arc;
+    use std::time::Instant;
+
+    use super::*;
+
+    #[test]
+    fn test_timeout_run() -> Result<()> {
+        assert_eq!(1, run("fast", Duration::from_secs(5), (), || Ok(1))?);
+
+        let started = Instant::now();
+        let actual = run("slow", Duration::from_millis(50), (), || {
+            thread::sleep(Duration::from_secs(5));
+            Ok(1)
+        });
+
+        let err = actual.unwrap_err();
+        assert_eq!("slow timed out after 50ms", err.to_string());
+        assert!(is_timed_out(&err));
+        assert!(started.elapsed() < Duration::from_secs(5));
+        Ok(())
+    }
+
+    #[test]
+    fn test_timeout_run_keeps_the_guard_until_the_operation_ends() {
+        let guard = Arc::new(());
+        let (release, released) = mpsc::channel::<()>();
+
+        let actual = run(
+            "slow",
+            Duration::from_millis(50),
+            guard.clone(),
+            move || {
+                let _ = released.recv();
+                Ok(1)
+            },
+        );
+
+        assert!(is_timed_out(&actual.unwrap_err()));
+        assert_eq!(2, Arc::strong_count(&guard));
+        release.send(()).unwrap();
+        let started = Instant::now();
+        while Arc::strong_count(&guard) > 1 {
+            assert!(started.elapsed() < Duration::from_secs(5));
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
@@ -0,0 +1,139 @@
+/// Returns whether both urls are spellings of the same remote, as they have
+/// the same [`cache_key`]. Local paths are compared ignoring their case too,
+/// so a dependency can be named as loosely as a remote one.
+pub fn is_same(url: &str, other: &str) -> bool {
+    cache_key(url).eq_ignore_ascii_case(&cache_key(other))
+}
+
+/// Returns the path of the bundle file that a url points to, either with the
+/// `bundle://` scheme or as a local path with the `.bundle` extension.
+pub fn bundle_path(url: &str) -> Option<&str> {
+    let url = url.trim();
+    match url.strip_prefix("bundle://") {
+        Some(path) => Some(path),
+        None => Some(url).filter(|url| url.ends_with(".bundle") && split_remote(url).is_none()),
+    }
+}
+
+/// Returns the key that identifies a remote, so the different spellings of
+/// the same remote share their clone and their lock entry. Urls are only
+/// compared in this form, they are cloned and saved as written:
+///
+///  1) Surrounding whitespace and trailing slashes are removed.
+///  2) The `.git` suffix of the path is removed.
+///  3) The scheme and user are left out, `https://github.com/a/b` and
+///     `git@github.com:a/b.git` share the key `github.com/a/b`.
+///  4) The host and path are lowercased, as hosting services match them
+///     ignoring their case.
+///
+/// Local paths and `file://` urls are only trimmed, as the `.git` suffix is
+/// part of the path of bare repositories, and file systems may be case
+/// sensitive.
+pub fn cache_key(url: &str) -> String {
+    let url = url.trim();
+    match split_remote(url) {
+        Some(Remote { host, path }) => format!("{host}/{path}").to_ascii_lowercase(),
+        None => url.to_string(),
+    }
+}
+
+/// Components of a remote url, the path has no leading nor trailing slashes
+/// and no `.git` suffix.
+struct Remote<'a> {
+    host: &'a str,
+    path: &'a str,
+}
+
+fn split_remote(url: &str) -> Option<Remote<'_>> {
+    let (authority, path) = match url.split_once("://") {
+        Some((scheme, _))
+            if scheme.eq_ignore_ascii_case("file") || scheme.eq_ignore_ascii_case("bundle") =>
+        {
+            return None
+        }
+        Some((_, rest)) => rest.split_once('/').unwrap_or((rest, "")),
+        None => split_scp_like(url)?,
+    };
+    let host = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+    let path = path.trim_matches('/');
+    let path = path
+        .strip_suffix(".git")
+        .unwrap_or(path)
+        .trim_end_matches('/');
+    Some(Remote { host, path })
+}
+
+/// Splits `user@host:path` into its authority and path. Single letter hosts
+/// are not accepted, as they are drive letters of Windows paths.
+fn split_scp_like(url: &str) -> Option<(&str, &str)> {
+    let (authority, path) = url.split_once(':')?;
+    let host = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+    if host.len() < 2 || authority.contains('/') || authority.contains('\\') {
+        return None;
+    }
+    Some((authority, path))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_is_same() {
+        assert!(is_same(
+            "git@github.com:a/b.git",
+            "ssh://git@GitHub.com/a/b"
+        ));
+        assert!(is_same(
+            "https://github.com/a/b",
+            "https://github.com/A/b.git"
+        ));
+        assert!(is_same("https://github.com/a/b", "git@github.com:a/b"));
+        assert!(!is_same("https://github.com/a/b", "https://gitlab.com/a/b"));
+        assert!(is_same("/srv/git/protos", "/srv/git/Protos"));
+    }
+
+    #[test]
+    fn test_cache_key_keeps_local_paths() {
+        for url in [
+            "/srv/git/protos.git",
+            "../protos.git",
+            "protos-common",
+            "file:///srv/git/Protos.git",
+            "C:/git/protos.git",
+            "bundle:///media/protos.bundle",
+        ] {
+            assert_eq!(url, cache_key(&format!(" {url} ")));
+        }
+    }
+
+    #[test]
+    fn test_bundle_path() {
+        assert_eq!(Some("/media/protos"), bundle_path("bundle:///media/protos"));
+        assert_eq!(
+            Some("/media/protos.bundle"),
+            bundle_path("/media/protos.bundle")
+        );
+        assert_eq!(None, bundle_path("https://github.com/a/protos.bundle"));
+        assert_eq!(None, bundle_path("/srv/git/protos.git"));
+    }
+
+    #[test]
+    fn test_cache_key_ignores_the_protocol_and_case() {
+        for url in [
+            "https://github.com/a/b",
+            "https://github.com/a/b.git",
+            "git@github.com:a/b.git",
+            "ssh://git@GitHub.com/a/b",
+            "  HTTPS://GitHub.com/A/B.git/ ",
+        ] {
+            assert_eq!("github.com/a/b", cache_key(url), "{url}");
+        }
+        assert_eq!("/srv/git/protos.git", cache_key("/srv/git/protos.git"));
+    }
+}
@@ -0,0 +1,61 @@
+use std::cmp::Ordering;
+
+use semver::Version;
+
+/// Compares two `major.minor.patch` versions numerically, so `0.10.0` is
+/// newer than `0.9.0`. Pre-releases are older than their release, as semver
+/// orders them, and build metadata is ignored. Versions that are not semver,
+/// such as `0.1`, are compared by their numeric components, the missing ones
+/// count as zero.
+pub fn compare(a: &str, b: &str) -> Ordering {
+    match (parse(a), parse(b)) {
+        (Some(a), Some(b)) => a.cmp_precedence(&b),
+        _ => compare_components(&components(a), &components(b)),
+    }
+}
+
+fn parse(version: &str) -> Option<Version> {
+    Version::parse(version.trim_start_matches('v')).ok()
+}
+
+fn compare_components(a: &[u64], b: &[u64]) -> Ordering {
+    let len = a.len().max(b.len());
+    (0..len)
+        .map(|i| {
+            let a = a.get(i).copied().unwrap_or(0);
+            let b = b.get(i).copied().unwrap_or(0);
+            a.cmp(&b)
+        })
+        .find(|ordering| ordering.is_ne())
+        .unwrap_or(Ordering::Equal)
+}
+
+fn components(version: &str) -> Vec<u64> {
+    version
+        .trim_start_matches('v')
+        .split(['-', '+'])
+        .next()
+        .unwrap_or_default()
+        .split('.')
+        .map(|component| component.parse().unwrap_or(0))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_compare() {
+        assert_eq!(Ordering::Equal, compare("0.1.0", "0.1.0"));
+        assert_eq!(Ordering::Equal, compare("0.1", "0.1.0"));
+        assert_eq!(Ordering::Greater, compare("v1.2.3", "1.2.3-rc.1"));
+        assert_eq!(Ordering::Less, compare("1.2.3-rc.1", "1.2.3-rc.2"));
+        assert_eq!(Ordering::Equal, compare("1.2.3+build.1", "1.2.3+build.2"));
+        assert_eq!(Ordering::Less, compare("0.1.0", "0.1.1"));
+        assert_eq!(Ordering::Less, compare("0.9.0", "0.10.0"));
+        assert_eq!(Ordering::Greater, compare("1.0.0", "0.99.99"));
+        assert_eq!(Ordering::Greater, compare("0.10.0", "0.9.0"));
+    }
+}
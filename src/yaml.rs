@@ -23,15 +23,38 @@ pub fn load<T: Sized + DeserializeOwned, P: AsRef<Path>>(path: P) -> Result<T> {
     }
 }
 
+/// Returns the comment lines found at the top of a yaml file, serde drops
+/// comments so they have to be carried over separately.
+pub fn leading_comments<P: AsRef<Path>>(path: P) -> String {
+    fs::read_to_string(path)
+        .unwrap_or_default()
+        .lines()
+        .take_while(|line| line.starts_with('#'))
+        .fold(String::new(), |mut comments, line| {
+            comments.push_str(line);
+            comments.push('\n');
+            comments
+        })
+}
+
 pub fn save<T: Sized + Serialize, P: AsRef<Path>>(input: &T, path: P) -> Result<()> {
+    save_with_header(input, path, "")
+}
+
+/// Saves the input like [`save`], writing `header` verbatim before it.
+pub fn save_with_header<T: Sized + Serialize, P: AsRef<Path>>(
+    input: &T,
+    path: P,
+    header: &str,
+) -> Result<()> {
     let do_save = || -> Result<()> {
         let contents = serde_yaml::to_string(input)?;
-        fs::write(&path, contents)?;
+        fs::write(&path, format!("{header}{contents}"))?;
         Ok(())
     };
 
     match do_save() {
-        Ok(_) => Ok(()),
+        Ok(()) => Ok(()),
         Err(err) => Err(format_err!(
             "cannot save {path}: {err}",
             path = path.as_ref().display(),
@@ -39,3 +62,35 @@ pub fn save<T: Sized + Serialize, P: AsRef<Path>>(input: &T, path: P) -> Result<
         )),
     }
 }
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::test_utils::read_to_string;
+    use crate::test_utils::tempdir;
+    use crate::test_utils::write_to;
+
+    #[test]
+    fn test_leading_comments_round_trip() -> Result<()> {
+        let dir = tempdir();
+        let path = dir.path().join("file.yml");
+        write_to(
+            &path,
+            "# first\n# second\nkey: value # inline\n# trailing\n",
+        );
+
+        let header = leading_comments(&path);
+        assert_eq!("# first\n# second\n", header);
+
+        let value: serde_yaml::Value = load(&path)?;
+        save_with_header(&value, &path, &header)?;
+        assert_eq!("# first\n# second\nkey: value\n", read_to_string(&path));
+        Ok(())
+    }
+
+    #[test]
+    fn test_leading_comments_of_missing_file() {
+        assert_eq!("", leading_comments("missing.yml"));
+    }
+}
@@ -0,0 +1,285 @@
+use std::fs;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Write;
+use std::net::TcpListener;
+use std::path::Path;
+use std::thread;
+
+use anyhow::Result;
+use git2::Repository;
+use git2::RepositoryInitOptions;
+use git2::Signature;
+use tempfile::TempDir;
+use vendify::CopyDecision;
+use vendify::Dependency;
+use vendify::Hooks;
+use vendify::Outdated;
+use vendify::PresetBuilder;
+use vendify::RemoteRefs;
+use vendify::Spec;
+use vendify::SpecLock;
+use vendify::VendorError;
+
+/// Lists the same references for every remote, as an API of a git hosting
+/// service would.
+struct StaticRefs;
+
+impl RemoteRefs for StaticRefs {
+    fn list_refs(&self, _url: &str) -> Result<Vec<(String, String)>> {
+        Ok(vec![("refs/heads/master".into(), "latest-commit".into())])
+    }
+}
+
+fn git_init(path: &Path, files: &[(&str, &str)]) -> Result<()> {
+    let repository =
+        Repository::init_opts(path, RepositoryInitOptions::new().initial_head("master"))?;
+    let mut index = repository.index()?;
+    for (file, data) in files {
+        let dst = path.join(file);
+        fs::create_dir_all(dst.parent().unwrap())?;
+        fs::write(dst, data)?;
+        index.add_path(Path::new(file))?;
+    }
+    let tree = repository.find_tree(index.write_tree()?)?;
+    let signature = Signature::now("test", "test@example.com")?;
+    repository.commit(Some("HEAD"), &signature, &signature, "initial", &tree, &[])?;
+    Ok(())
+}
+
+fn path(dir: &TempDir, name: &str) -> String {
+    dir.path().join(name).to_string_lossy().to_string()
+}
+
+fn get_spec(workdir: &TempDir, dep: Dependency) -> (Spec, SpecLock) {
+    let preset = PresetBuilder::new()
+        .vendor(&path(workdir, "vendor"))
+        .spec(&path(workdir, ".vendor.yml"))
+        .spec_lock(&path(workdir, ".vendor-lock.yml"))
+        .build();
+    let mut spec = Spec::with_preset(&preset);
+    spec.add_dependency(dep);
+    (spec, SpecLock::with_preset(&preset))
+}
+
+/// Serves every request with a challenge of an authentication scheme that
+/// git does not support, so authenticating always fails.
+fn serve_unauthorized() -> Result<String> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let url = format!("http://{}/a/b.git", listener.local_addr()?);
+    thread::spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            let Ok(reader) = stream.try_clone() else {
+                continue;
+            };
+            for line in BufReader::new(reader).lines() {
+                if line.map_or(true, |line| line.is_empty()) {
+                    break;
+                }
+            }
+            let _ = stream.write_all(
+                b"HTTP/1.1 401 Unauthorized\r\nWWW-Authenticate: Custom realm=\"test\"\r\n\
+                  Content-Length: 0\r\nConnection: close\r\n\r\n",
+            );
+        }
+    });
+    Ok(url)
+}
+
+#[test]
+fn test_vendor() -> Result<()> {
+    let upstream = TempDir::new()?;
+    git_init(
+        upstream.path(),
+        &[
+            ("protos/api.proto", "syntax = \"proto3\";"),
+            ("README.md", "readme"),
+        ],
+    )?;
+    let workdir = TempDir::new()?;
+    let preset = PresetBuilder::new()
+        .vendor(&path(&workdir, "vendor"))
+        .spec(&path(&workdir, ".vendor.yml"))
+        .spec_lock(&path(&workdir, ".vendor-lock.yml"))
+        .build();
+    let mut spec = Spec::with_preset(&preset);
+    spec.filters.extensions = vec!["proto".into()];
+    spec.add_dependency(Dependency::new(upstream.path().to_string_lossy(), "master"));
+
+    let report = vendify::vendor(
+        &spec,
+        SpecLock::with_preset(&preset),
+        workdir.path().join("cache"),
+    )?;
+
+    assert_eq!(1, report.metrics.dependencies);
+    assert_eq!(0, report.metrics.failures);
+    assert_eq!(1, report.metrics.files);
+    assert_eq!(1, report.spec_lock.deps.len());
+    let vendor = workdir.path().join("vendor");
+    assert!(vendor.join("protos/api.proto").exists());
+    assert!(!vendor.join("README.md").exists());
+    assert!(!workdir.path().join(".vendor-lock.yml").exists());
+    Ok(())
+}
+
+#[test]
+fn test_vendor_with_filter() -> Result<()> {
+    let upstream = TempDir::new()?;
+    git_init(
+        upstream.path(),
+        &[
+            ("protos/api.proto", "option go_package = \"api\";"),
+            ("protos/internal.proto", "syntax = \"proto3\";"),
+        ],
+    )?;
+    let workdir = TempDir::new()?;
+    let mut dep = Dependency::new(upstream.path().to_string_lossy(), "master");
+    dep.filters.extensions = vec!["proto".into()];
+    let (spec, spec_lock) = get_spec(&workdir, dep);
+
+    let report = vendify::vendor_with_filter(
+        &spec,
+        spec_lock,
+        workdir.path().join("cache"),
+        |path, contents| {
+            assert!(path.is_relative(), "{}", path.display());
+            String::from_utf8_lossy(contents).contains("go_package")
+        },
+    )?;
+
+    assert_eq!(0, report.metrics.failures);
+    assert_eq!(1, report.metrics.files);
+    let vendor = workdir.path().join("vendor");
+    assert!(vendor.join("protos/api.proto").exists());
+    assert!(!vendor.join("protos/internal.proto").exists());
+    Ok(())
+}
+
+#[test]
+fn test_vendor_with_hooks() -> Result<()> {
+    let upstream = TempDir::new()?;
+    git_init(
+        upstream.path(),
+        &[("protos/api.proto", "syntax = \"proto3\";")],
+    )?;
+    let workdir = TempDir::new()?;
+    let mut dep = Dependency::new(upstream.path().to_string_lossy(), "master");
+    dep.filters.extensions = vec!["proto".into()];
+    let (spec, spec_lock) = get_spec(&workdir, dep);
+
+    let report = vendify::vendor_with_hooks(
+        &spec,
+        spec_lock,
+        workdir.path().join("cache"),
+        Hooks::new().with_lock_hook(|spec_lock| {
+            for dep in &mut spec_lock.deps {
+                dep.url = "https://mirror.internal/api".into();
+            }
+            Ok(())
+        }),
+    )?;
+
+    assert_eq!("https://mirror.internal/api", report.spec_lock.deps[0].url);
+    assert!(workdir.path().join("vendor/protos/api.proto").exists());
+
+    let rejected = TempDir::new()?;
+    let (spec, spec_lock) = get_spec(&rejected, spec.deps[0].clone());
+    let actual = vendify::vendor_with_hooks(
+        &spec,
+        spec_lock,
+        workdir.path().join("cache"),
+        Hooks::new().with_lock_hook(|_| Err(VendorError::Other("not allowed".into()))),
+    );
+
+    let err = actual.unwrap_err();
+    assert!(matches!(err, VendorError::Other(_)), "{err:?}");
+    assert_eq!("lock hook failed: not allowed", err.to_string());
+    assert!(!rejected.path().join("vendor").exists());
+    Ok(())
+}
+
+#[test]
+fn test_outdated_with_remote_refs() -> Result<()> {
+    let workdir = TempDir::new()?;
+    let (spec, spec_lock) = get_spec(
+        &workdir,
+        Dependency::new("https://unreachable.internal/a/b", "master"),
+    );
+
+    let actual = vendify::outdated(&spec, spec_lock, Hooks::new().with_remote_refs(StaticRefs))?;
+
+    assert_eq!(
+        vec![Outdated {
+            url: "https://unreachable.internal/a/b".into(),
+            locked: None,
+            latest: "latest-commit".into(),
+        }],
+        actual
+    );
+    Ok(())
+}
+
+#[test]
+fn test_explain_without_cloning() -> Result<()> {
+    let workdir = TempDir::new()?;
+    let mut dep = Dependency::new("https://unreachable.internal/a/b", "master");
+    dep.filters.extensions.push("proto".into());
+    let (spec, _) = get_spec(&workdir, dep);
+
+    assert_eq!(
+        CopyDecision::Copied(None),
+        vendify::explain(
+            &spec,
+            "https://unreachable.internal/a/b",
+            "protos/api.proto"
+        )?
+    );
+    assert_eq!(
+        CopyDecision::Hidden,
+        vendify::explain(&spec, "https://unreachable.internal/a/b", ".github/ci.yml")?
+    );
+    assert!(vendify::explain(&spec, "https://other.internal/c/d", "file.txt").is_err());
+    Ok(())
+}
+
+#[test]
+fn test_vendor_reports_missing_refname() -> Result<()> {
+    let upstream = TempDir::new()?;
+    git_init(
+        upstream.path(),
+        &[("protos/api.proto", "syntax = \"proto3\";")],
+    )?;
+    let workdir = TempDir::new()?;
+    let dep = Dependency::new(upstream.path().to_string_lossy(), "missing");
+    let (spec, spec_lock) = get_spec(&workdir, dep);
+
+    let report = vendify::vendor(&spec, spec_lock, workdir.path().join("cache"))?;
+
+    assert_eq!(1, report.metrics.failures);
+    let failure = &report.failures[0];
+    assert_eq!(upstream.path().to_string_lossy(), failure.url);
+    assert!(
+        matches!(failure.error, VendorError::RefNotFound(_)),
+        "{:?}",
+        failure.error
+    );
+    Ok(())
+}
+
+#[test]
+fn test_vendor_reports_auth_failure() -> Result<()> {
+    let url = serve_unauthorized()?;
+    let workdir = TempDir::new()?;
+    let (spec, spec_lock) = get_spec(&workdir, Dependency::new(&url, "master"));
+
+    let report = vendify::vendor(&spec, spec_lock, workdir.path().join("cache"))?;
+
+    assert_eq!(1, report.metrics.failures);
+    assert!(
+        matches!(report.failures[0].error, VendorError::Auth(_)),
+        "{:?}",
+        report.failures[0].error
+    );
+    Ok(())
+}